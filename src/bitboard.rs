@@ -4,6 +4,49 @@
 
 use crate::def;
 
+const RANK_1_MASK: u64 = 0x00000000_000000FF;
+const RANK_8_MASK: u64 = 0xFF000000_00000000;
+const FILE_A_MASK: u64 = 0x01010101_01010101;
+const FILE_H_MASK: u64 = 0x80808080_80808080;
+
+static B_MAGIC_NUMS: [u64; def::BOARD_SIZE] = [
+    0x0019081808004090, 0x3008080800485020, 0x8090848600400418, 0x00B1104A00509210,
+    0x80011040001C8801, 0x080101A010108002, 0x00010090108A0000, 0x0009010041044000,
+    0x0100100401081E08, 0x0A07240404042220, 0x5000112800810900, 0x0001040420810020,
+    0x6801020211209800, 0x588C020210050000, 0x20000C0482301090, 0x1020020504010500,
+    0xA00400A2A002320C, 0x0004104210025200, 0x0A10840800214200, 0x0C08082C02424940,
+    0x0224000A02110806, 0x0242010041100100, 0x000A010305502208, 0x4002083048420800,
+    0x2222120028A00802, 0x0810080002022402, 0x8150440008043400, 0x1020080001004009,
+    0x0A00848084002010, 0x00010A00440A0102, 0x00808A2009111000, 0x1040810102010090,
+    0x0104420801C01002, 0x010202100002D008, 0x0411220800210800, 0x0200100820440400,
+    0x0808220400001100, 0x0008180020011000, 0xC001280108008408, 0x1008128110102702,
+    0x0021080840600442, 0x0084014108001000, 0x0402014028001080, 0x0000020212002403,
+    0x0100011020810400, 0x018410044A008442, 0x18100200A1082C20, 0x0424040440450210,
+    0x040080B410404843, 0x0012091108028000, 0x0212004404112200, 0x0008000042020000,
+    0x2001824030248850, 0x1000413002808000, 0x004010044094A200, 0x08841048C2028250,
+    0x0002002401041010, 0x010672020114C200, 0x1490048104010420, 0x8044001828420200,
+    0x0301008010202219, 0x4211802003020200, 0x0205102002408200, 0x482810088C930202,
+];
+
+static R_MAGIC_NUMS: [u64; def::BOARD_SIZE] = [
+    0x098000A080114001, 0x8840001004200046, 0x48800A8020001000, 0x0480100104800801,
+    0x0200080200200410, 0x0100020100040008, 0x0080008002000100, 0x0200002283004406,
+    0x0280800040008020, 0x2204401004200241, 0x0000808010002000, 0x000100201001000D,
+    0x4201000502080010, 0x8202000C11487A00, 0x0414000230084164, 0x0443001441000086,
+    0x0481030024800040, 0x0200810040010020, 0x0800848010002000, 0x0082808010060800,
+    0x8008008008040080, 0x0000808004000201, 0x0208010100020004, 0x0080820000409411,
+    0xC880400080082880, 0x2020400440201000, 0x2020100080200080, 0x8090090100100021,
+    0x8070040080080080, 0x0020840801204010, 0x08110009000A0004, 0x1008008200040041,
+    0x0080002000404000, 0x0000201000400040, 0x0240102001004100, 0x0221002209001000,
+    0x2000080101000410, 0x0002002004040010, 0x5022928114002810, 0x8080800040802100,
+    0x0001800040018020, 0x0000A0005000C009, 0x0009001020010040, 0x0000080010008080,
+    0x0028000900110004, 0x1042001084520008, 0x0300081002040001, 0x080848D402920011,
+    0x4400801040002080, 0x00120C4121008200, 0x2000200904401100, 0x5030100101210900,
+    0x0E04028800800480, 0x0800800200040080, 0x0001000200040100, 0x0801204081141200,
+    0x0A05045140820422, 0x00045081C00A2101, 0xA000120920010041, 0x0224210010000509,
+    0x0006000804603006, 0x4801000802040001, 0x4444100100822804, 0x0010070083225402,
+];
+
 #[derive(Copy, Clone)]
 pub struct BitBoard {
     pub w_all: u64,
@@ -85,6 +128,24 @@ pub struct BitMask {
 
     pub wk_attack_zone_masks: [u64; def::BOARD_SIZE],
     pub bk_attack_zone_masks: [u64; def::BOARD_SIZE],
+
+    b_magics: [Magic; def::BOARD_SIZE],
+    r_magics: [Magic; def::BOARD_SIZE],
+    magic_attack_table: Vec<u64>,
+}
+
+#[derive(Copy, Clone, Default)]
+struct Magic {
+    occupy_mask: u64,
+    magic_num: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Default for BitMask {
+    fn default() -> Self {
+        BitMask::new()
+    }
 }
 
 impl BitMask {
@@ -129,6 +190,10 @@ impl BitMask {
 
             wk_attack_zone_masks: [0; def::BOARD_SIZE],
             bk_attack_zone_masks: [0; def::BOARD_SIZE],
+
+            b_magics: [Magic::default(); def::BOARD_SIZE],
+            r_magics: [Magic::default(); def::BOARD_SIZE],
+            magic_attack_table: Vec::new(),
         };
 
         bitmask.init_base();
@@ -147,6 +212,7 @@ impl BitMask {
         bitmask.init_right_masks();
 
         bitmask.init_rb_attack_masks();
+        bitmask.init_magics();
 
         bitmask.init_p_attack_masks();
         bitmask.init_p_mov_masks();
@@ -158,6 +224,7 @@ impl BitMask {
         bitmask
     }
 
+    #[allow(clippy::assign_op_pattern)]
     fn init_base(&mut self) {
         let mut file_masks = [0; def::DIM_SIZE];
 
@@ -175,6 +242,7 @@ impl BitMask {
         }
     }
 
+    #[allow(clippy::assign_op_pattern)]
     fn init_n_masks(&mut self) {
         for index in 0..def::BOARD_SIZE {
             if index + 6 < def::BOARD_SIZE
@@ -223,6 +291,7 @@ impl BitMask {
         }
     }
 
+    #[allow(clippy::assign_op_pattern)]
     fn init_k_masks(&mut self) {
         for index in 0..def::BOARD_SIZE {
             if index + 1 < def::BOARD_SIZE && (self.index_masks[index] & self.file_masks[7] == 0) {
@@ -354,6 +423,85 @@ impl BitMask {
         }
     }
 
+    fn init_magics(&mut self) {
+        for index in 0..def::BOARD_SIZE {
+            let b_occupy_mask = self.b_attack_masks[index] & !(RANK_1_MASK | RANK_8_MASK | FILE_A_MASK | FILE_H_MASK);
+            self.b_magics[index] = self.init_magic(index, b_occupy_mask, B_MAGIC_NUMS[index], BitMask::get_b_ray_attack_mask);
+
+            let r_occupy_mask = (self.up_attack_masks[index] & !RANK_8_MASK)
+                | (self.down_attack_masks[index] & !RANK_1_MASK)
+                | (self.left_attack_masks[index] & !FILE_A_MASK)
+                | (self.right_attack_masks[index] & !FILE_H_MASK);
+            self.r_magics[index] = self.init_magic(index, r_occupy_mask, R_MAGIC_NUMS[index], BitMask::get_r_ray_attack_mask);
+        }
+    }
+
+    fn init_magic(&mut self, index: usize, occupy_mask: u64, magic_num: u64, get_ray_attack_mask: fn(&BitMask, usize, u64) -> u64) -> Magic {
+        let bit_count = occupy_mask.count_ones();
+        let magic = Magic {
+            occupy_mask,
+            magic_num,
+            shift: 64 - bit_count,
+            offset: self.magic_attack_table.len(),
+        };
+
+        self.magic_attack_table.resize(magic.offset + (1 << bit_count), 0);
+
+        let mut occupy_subset = 0u64;
+        loop {
+            let magic_index = magic.offset + (occupy_subset.wrapping_mul(magic_num) >> magic.shift) as usize;
+            let attack_mask = get_ray_attack_mask(self, index, occupy_subset);
+
+            debug_assert!(self.magic_attack_table[magic_index] == 0 || self.magic_attack_table[magic_index] == attack_mask);
+            self.magic_attack_table[magic_index] = attack_mask;
+
+            occupy_subset = occupy_subset.wrapping_sub(occupy_mask) & occupy_mask;
+            if occupy_subset == 0 {
+                break
+            }
+        }
+
+        magic
+    }
+
+    fn get_b_ray_attack_mask(&self, index: usize, occupy_mask: u64) -> u64 {
+        get_up_ray_attack_mask(&self.up_left_attack_masks, index, occupy_mask)
+            | get_up_ray_attack_mask(&self.up_right_attack_masks, index, occupy_mask)
+            | get_down_ray_attack_mask(&self.down_left_attack_masks, index, occupy_mask)
+            | get_down_ray_attack_mask(&self.down_right_attack_masks, index, occupy_mask)
+    }
+
+    fn get_r_ray_attack_mask(&self, index: usize, occupy_mask: u64) -> u64 {
+        get_up_ray_attack_mask(&self.up_attack_masks, index, occupy_mask)
+            | get_up_ray_attack_mask(&self.right_attack_masks, index, occupy_mask)
+            | get_down_ray_attack_mask(&self.down_attack_masks, index, occupy_mask)
+            | get_down_ray_attack_mask(&self.left_attack_masks, index, occupy_mask)
+    }
+
+    /// Returns the squares a bishop on `index` attacks given the occupied squares, including blockers of either side.
+    #[inline]
+    pub fn get_b_attack_mask(&self, index: usize, occupy_mask: u64) -> u64 {
+        self.get_magic_attack_mask(&self.b_magics[index], occupy_mask)
+    }
+
+    /// Returns the squares a rook on `index` attacks given the occupied squares, including blockers of either side.
+    #[inline]
+    pub fn get_r_attack_mask(&self, index: usize, occupy_mask: u64) -> u64 {
+        self.get_magic_attack_mask(&self.r_magics[index], occupy_mask)
+    }
+
+    #[inline]
+    pub fn get_q_attack_mask(&self, index: usize, occupy_mask: u64) -> u64 {
+        self.get_b_attack_mask(index, occupy_mask) | self.get_r_attack_mask(index, occupy_mask)
+    }
+
+    #[inline]
+    fn get_magic_attack_mask(&self, magic: &Magic, occupy_mask: u64) -> u64 {
+        let magic_index = ((occupy_mask & magic.occupy_mask).wrapping_mul(magic.magic_num) >> magic.shift) as usize;
+        self.magic_attack_table[magic.offset + magic_index]
+    }
+
+    #[allow(clippy::assign_op_pattern)]
     fn init_p_attack_masks(&mut self) {
         for index in 0..def::BOARD_SIZE {
             if self.index_masks[index] & self.file_masks[0] == 0 {
@@ -522,3 +670,55 @@ impl BitMask {
         }
     }
 }
+
+#[inline]
+fn get_up_ray_attack_mask(ray_masks: &[u64; def::BOARD_SIZE], index: usize, occupy_mask: u64) -> u64 {
+    let ray_mask = ray_masks[index];
+
+    if ray_mask & occupy_mask != 0 {
+        ray_mask & !ray_masks[(ray_mask & occupy_mask).trailing_zeros() as usize]
+    } else {
+        ray_mask
+    }
+}
+
+#[inline]
+fn get_down_ray_attack_mask(ray_masks: &[u64; def::BOARD_SIZE], index: usize, occupy_mask: u64) -> u64 {
+    let ray_mask = ray_masks[index];
+
+    if ray_mask & occupy_mask != 0 {
+        ray_mask & !ray_masks[63 - (ray_mask & occupy_mask).leading_zeros() as usize]
+    } else {
+        ray_mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_magic_attack_masks() {
+        let bitmask = BitMask::new();
+
+        for index in 0..def::BOARD_SIZE {
+            for (magic, get_ray_attack_mask) in [(bitmask.b_magics[index], BitMask::get_b_ray_attack_mask as fn(&BitMask, usize, u64) -> u64), (bitmask.r_magics[index], BitMask::get_r_ray_attack_mask)] {
+                let mut occupy_subset = 0u64;
+                loop {
+                    for edge_mask in [0, RANK_1_MASK, RANK_8_MASK | FILE_A_MASK, FILE_H_MASK] {
+                        let occupy_mask = occupy_subset | edge_mask;
+                        assert_eq!(get_ray_attack_mask(&bitmask, index, occupy_mask), bitmask.get_magic_attack_mask(&magic, occupy_mask));
+                    }
+
+                    occupy_subset = occupy_subset.wrapping_sub(magic.occupy_mask) & magic.occupy_mask;
+                    if occupy_subset == 0 {
+                        break
+                    }
+                }
+            }
+        }
+
+        assert_eq!(bitmask.b_attack_masks[27], bitmask.get_b_attack_mask(27, 0));
+        assert_eq!(bitmask.r_attack_masks[0] | bitmask.b_attack_masks[0], bitmask.get_q_attack_mask(0, 0));
+    }
+}
@@ -0,0 +1,495 @@
+/*
+ * Copyright (C) 2020 Zixiao Han
+ */
+
+use crate::{
+    bitboard::BitMask,
+    def,
+    endgame,
+    eval_params::EvalParams,
+    hashtable::PerftHashTable,
+    perft,
+    polyglot::PolyglotBook,
+    syzygy::SyzygyTablebase,
+    prng::XorshiftPrng,
+    search::{SearchControl, SearchEngine, SearchLimits},
+    state::State,
+    time_control::{self, TimeCapacity},
+    uci::{self, GoLimits, TimeInfo, UciCommand, UciError},
+};
+
+use std::env;
+use std::fs;
+use std::io::{self, prelude::*};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time;
+
+const DEFAULT_MAX_TIME: TimeCapacity = TimeCapacity {
+    main_time_millis: u128::MAX,
+    extra_time_millis: 0,
+};
+
+const DEFAULT_MAX_DEPTH: u8 = 128;
+
+/// Runs the engine on a dedicated worker thread. `isready`, `stop`, `ponderhit` and `quit` are
+/// answered on the calling thread, so they take effect while the worker is searching; all other
+/// commands are queued to the worker and processed in the order they were received.
+pub struct EngineController {
+    cmd_sender: mpsc::Sender<UciCommand>,
+    search_control: SearchControl,
+    search_active: Arc<Mutex<bool>>,
+    worker_handle: Option<thread::JoinHandle<()>>,
+}
+
+impl EngineController {
+    pub fn new() -> Self {
+        let (cmd_sender, cmd_receiver) = mpsc::channel();
+
+        let mut search_engine = SearchEngine::new(def::DEFAULT_HASH_SIZE_UNIT);
+        search_engine.set_info_sink(|info| println!("{}", info));
+
+        let search_control = search_engine.get_search_control();
+        let search_active = Arc::new(Mutex::new(false));
+
+        let worker_search_active = Arc::clone(&search_active);
+        let worker_handle = thread::spawn(move || run_worker(search_engine, cmd_receiver, worker_search_active));
+
+        EngineController {
+            cmd_sender,
+            search_control,
+            search_active,
+            worker_handle: Some(worker_handle),
+        }
+    }
+
+    /// Processes one line of input; returns `false` once the engine should exit.
+    pub fn process_cmd(&mut self, cmd: &str) -> bool {
+        let uci_cmd = uci::process_uci_cmd(cmd.trim());
+
+        match uci_cmd {
+            UciCommand::IsReady => {
+                if *self.search_active.lock().unwrap() {
+                    print_ready();
+                } else {
+                    self.send_cmd(UciCommand::IsReady);
+                }
+            },
+            UciCommand::Stop => {
+                let search_active = self.search_active.lock().unwrap();
+
+                if *search_active {
+                    self.search_control.stop();
+                }
+            },
+            UciCommand::PonderHit => {
+                let search_active = self.search_active.lock().unwrap();
+
+                if *search_active {
+                    self.search_control.ponder_hit();
+                }
+            },
+            UciCommand::Quit => {
+                {
+                    let search_active = self.search_active.lock().unwrap();
+
+                    if *search_active {
+                        self.search_control.stop();
+                    }
+                }
+
+                self.send_cmd(UciCommand::Quit);
+
+                if let Some(worker_handle) = self.worker_handle.take() {
+                    worker_handle.join().ok();
+                }
+
+                return false
+            },
+            UciCommand::Noop => {},
+            uci_cmd => {
+                if is_search_cmd(&uci_cmd) {
+                    *self.search_active.lock().unwrap() = true;
+                }
+
+                self.send_cmd(uci_cmd);
+            },
+        }
+
+        true
+    }
+
+    /// Queues `quit` behind the commands already sent and waits for the worker to process them all,
+    /// e.g. once the input has ended; unlike `quit`, a running search is allowed to finish.
+    pub fn finish_pending_cmds(&mut self) {
+        self.send_cmd(UciCommand::Quit);
+
+        if let Some(worker_handle) = self.worker_handle.take() {
+            worker_handle.join().ok();
+        }
+    }
+
+    fn send_cmd(&self, uci_cmd: UciCommand) {
+        self.cmd_sender.send(uci_cmd).expect("engine worker stopped");
+    }
+}
+
+impl Default for EngineController {
+    fn default() -> Self {
+        EngineController::new()
+    }
+}
+
+fn is_search_cmd(uci_cmd: &UciCommand) -> bool {
+    matches!(uci_cmd,
+        UciCommand::StartSearch(_)
+        | UciCommand::StartSearchWithMovs(_, _)
+        | UciCommand::StartPonder(_))
+}
+
+fn run_worker(mut search_engine: SearchEngine, cmd_receiver: mpsc::Receiver<UciCommand>, search_active: Arc<Mutex<bool>>) {
+    let search_control = search_engine.get_search_control();
+    let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+    let bitmask = BitMask::new();
+    let mut state = State::new(uci::FEN_START_POS, &zob_keys, &bitmask);
+
+    // built here rather than on the first KPK position reached, which would be timed against a move
+    endgame::get_kpk_bitbase(&bitmask);
+    let mut is_chess960 = false;
+    let mut eval_params = EvalParams::default();
+
+    let mut book: Option<PolyglotBook> = None;
+    let mut is_own_book = false;
+    let mut book_depth = def::DEFAULT_BOOK_DEPTH;
+    let mut is_best_book_mov = false;
+    let mut book_prng = XorshiftPrng::with_seed(time::SystemTime::now().duration_since(time::UNIX_EPOCH).map_or(0, |duration| duration.as_nanos() as u64));
+
+    for uci_cmd in cmd_receiver {
+        let is_book_allowed = matches!(&uci_cmd, UciCommand::StartSearch(go_limits) if !go_limits.is_infinite() && go_limits.mate_mov_count.is_none());
+
+        let uci_cmd = match uci_cmd {
+            UciCommand::StartSearchWithMovs(raw_mov_list, go_cmd) => {
+                let root_mov_list = uci::match_raw_mov_list(&mut state, &raw_mov_list);
+
+                // an empty list would lift the restriction instead of leaving nothing to search
+                if root_mov_list.is_empty() {
+                    let mov_list_str = raw_mov_list.iter().map(|raw_mov| raw_mov.origin_mov_str.as_str()).collect::<Vec<&str>>().join(" ");
+                    uci::print_error(&UciError::IllegalMov(mov_list_str));
+                    finish_search(&search_control, &search_active);
+                    print_best_mov(&state, 0, 0);
+                    continue
+                }
+
+                search_engine.set_root_mov_list(root_mov_list);
+                *go_cmd
+            },
+            uci_cmd => uci_cmd,
+        };
+
+        if is_book_allowed && is_own_book && get_game_ply(&state) < book_depth as usize {
+            if let Some(book_mov) = book.as_ref().and_then(|book| book.probe(&state, is_best_book_mov, &mut book_prng)) {
+                finish_search(&search_control, &search_active);
+                print_best_mov(&state, book_mov, 0);
+                continue
+            }
+        }
+
+        let best_mov = match uci_cmd {
+            UciCommand::SetHashSize(hash_size) => {
+                search_engine.set_hash_size(hash_size);
+                continue
+            },
+            UciCommand::SetThreadCount(thread_count) => {
+                search_engine.set_thread_count(thread_count);
+                continue
+            },
+            UciCommand::SetMultiPV(multi_pv) => {
+                search_engine.set_multi_pv(multi_pv);
+                continue
+            },
+            UciCommand::SetChess960(chess960_enabled) => {
+                is_chess960 = chess960_enabled;
+                state.is_chess960 = is_chess960;
+                continue
+            },
+            UciCommand::SetOwnBook(own_book_enabled) => {
+                is_own_book = own_book_enabled;
+                continue
+            },
+            UciCommand::SetBookFile(path) => {
+                book = load_book(&path);
+                continue
+            },
+            UciCommand::SetBookDepth(depth) => {
+                book_depth = depth;
+                continue
+            },
+            UciCommand::SetBestBookMove(best_book_mov_enabled) => {
+                is_best_book_mov = best_book_mov_enabled;
+                continue
+            },
+            UciCommand::SetSyzygyPath(path) => {
+                search_engine.set_tablebase(load_tablebase(&path));
+                continue
+            },
+            UciCommand::SetSyzygyProbeDepth(probe_depth) => {
+                search_engine.set_tb_probe_depth(probe_depth);
+                continue
+            },
+            UciCommand::SetSyzygyProbeLimit(probe_limit) => {
+                search_engine.set_tb_probe_limit(probe_limit);
+                continue
+            },
+            UciCommand::SetEvalFile(path) => {
+                if let Some(file_eval_params) = load_eval_params(&path) {
+                    eval_params = file_eval_params;
+                    search_engine.set_eval_params(eval_params.clone());
+                }
+
+                continue
+            },
+            UciCommand::SetEvalParam(name, param_val) => {
+                if let Some(param) = eval_params.get_scalar_mut(&name) {
+                    *param = param_val;
+                    search_engine.set_eval_params(eval_params.clone());
+                }
+
+                continue
+            },
+            UciCommand::Position(fen_str, mov_list) => {
+                state = match State::from_fen(&fen_str, &zob_keys, &bitmask) {
+                    Ok(state) => state,
+                    Err(error) => {
+                        uci::print_error(&UciError::InvalidFen(error.to_string()));
+                        continue
+                    },
+                };
+
+                state.is_chess960 = is_chess960;
+
+                for raw_mov in mov_list {
+                    if let Err(error) = uci::do_raw_mov(&mut state, raw_mov) {
+                        uci::print_error(&error);
+                        break
+                    }
+                }
+
+                continue
+            },
+            UciCommand::IsReady => {
+                print_ready();
+                continue
+            },
+            UciCommand::Quit => break,
+            UciCommand::StartSearch(go_limits) => {
+                let search_limits = get_search_limits(state.player, go_limits);
+                search_engine.search_with_limits(&mut state, search_limits)
+            },
+            UciCommand::StartPonder(go_cmd) => {
+                let search_limits = match *go_cmd {
+                    UciCommand::StartSearch(go_limits) => get_search_limits(state.player, go_limits),
+                    _ => get_search_limits(state.player, GoLimits::default()),
+                };
+
+                search_engine.ponder(&mut state, search_limits)
+            },
+            UciCommand::Perft(depth) => {
+                let start_time = time::Instant::now();
+                let perft_val = search_engine.perft(&mut state, depth);
+
+                println!("depth {} perft {} time {} milliseconds", depth, perft_val, start_time.elapsed().as_millis());
+                continue
+            },
+            UciCommand::PerftDivide(depth) => {
+                let start_time = time::Instant::now();
+                let divide_list = perft::perft_divide(&mut state, depth);
+
+                for (mov, node_count) in &divide_list {
+                    println!("{}: {}", uci::format_mov(&state, *mov), node_count);
+                }
+
+                let perft_val: usize = divide_list.iter().map(|(_, node_count)| node_count).sum();
+                println!("depth {} perft {} time {} milliseconds", depth, perft_val, start_time.elapsed().as_millis());
+                continue
+            },
+            UciCommand::PerftHashed(depth) => {
+                let start_time = time::Instant::now();
+                let mut perft_hash_table = PerftHashTable::new(perft::PERFT_HASH_SIZE_UNIT);
+                let perft_val = perft::perft_hashed(&mut state, depth, &mut perft_hash_table);
+
+                println!("depth {} perft {} time {} milliseconds", depth, perft_val, start_time.elapsed().as_millis());
+                continue
+            },
+            UciCommand::PerftSuite(path, max_depth) => {
+                run_perft_suite(&path, max_depth, &zob_keys, &bitmask);
+                continue
+            },
+            UciCommand::PrintDebugInfo => {
+                println!("{}", &state);
+                println!("{}", state.to_fen());
+
+                let (pawn_hash_probe_count, pawn_hash_hit_count) = search_engine.get_pawn_hash_stats();
+                println!("pawn hash hits {} of {} probes", pawn_hash_hit_count, pawn_hash_probe_count);
+                continue
+            },
+            UciCommand::Reset => {
+                search_engine.reset();
+                continue
+            },
+            _ => continue,
+        };
+
+        finish_search(&search_control, &search_active);
+        print_best_mov(&state, best_mov, search_engine.get_ponder_mov());
+    }
+}
+
+fn run_perft_suite(path: &str, max_depth: u8, zob_keys: &Vec<Vec<u64>>, bitmask: &BitMask) {
+    let case_list = match fs::read_to_string(path).map_err(|error| error.to_string()).and_then(|suite_str| perft::parse_perft_suite(&suite_str).map_err(|error| error.to_string())) {
+        Ok(case_list) => case_list,
+        Err(reason) => {
+            uci::print_error(&UciError::InvalidFile(path.to_owned(), reason));
+            return
+        },
+    };
+
+    let start_time = time::Instant::now();
+    let mut mismatch_count = 0;
+
+    for perft_case in &case_list {
+        match perft::run_perft_case(perft_case, max_depth, zob_keys, bitmask) {
+            Ok(mismatch_list) => {
+                for mismatch in mismatch_list {
+                    println!("mismatch fen {} depth {} expected {} perft {}", perft_case.fen, mismatch.depth, mismatch.expected_count, mismatch.node_count);
+                    mismatch_count += 1;
+                }
+            },
+            Err(error) => {
+                uci::print_error(&UciError::InvalidFen(format!("{}: {}", perft_case.fen, error)));
+                mismatch_count += 1;
+            },
+        }
+    }
+
+    println!("perft suite {} positions {} mismatches time {} milliseconds", case_list.len(), mismatch_count, start_time.elapsed().as_millis());
+    io::stdout().flush().ok();
+}
+
+fn finish_search(search_control: &SearchControl, search_active: &Mutex<bool>) {
+    let mut search_active = search_active.lock().unwrap();
+    search_control.clear_stop();
+    search_control.clear_ponder_hit();
+    *search_active = false;
+}
+
+fn load_book(path: &str) -> Option<PolyglotBook> {
+    if path.is_empty() {
+        return None
+    }
+
+    match fs::read(path).map_err(|error| error.to_string()).and_then(|book_bytes| PolyglotBook::from_bytes(&book_bytes).map_err(|error| error.to_string())) {
+        Ok(book) => Some(book),
+        Err(reason) => {
+            uci::print_error(&UciError::InvalidFile(path.to_owned(), reason));
+            None
+        },
+    }
+}
+
+/// Loads evaluation weights from `path`; an empty path restores the defaults.
+fn load_eval_params(path: &str) -> Option<EvalParams> {
+    if path.is_empty() {
+        return Some(EvalParams::default())
+    }
+
+    match fs::read_to_string(path).map_err(|error| error.to_string()).and_then(|params_str| EvalParams::parse(&params_str).map_err(|error| error.to_string())) {
+        Ok(eval_params) => Some(eval_params),
+        Err(reason) => {
+            uci::print_error(&UciError::InvalidFile(path.to_owned(), reason));
+            None
+        },
+    }
+}
+
+/// Loads the tables of every directory in `path`, separated like the `PATH` environment variable.
+fn load_tablebase(path: &str) -> SyzygyTablebase {
+    let mut tablebase = SyzygyTablebase::new();
+
+    if path.is_empty() {
+        return tablebase
+    }
+
+    let mut table_count = 0;
+
+    for dir_path in env::split_paths(path) {
+        match tablebase.add_directory(&dir_path) {
+            Ok(dir_table_count) => table_count += dir_table_count,
+            Err(error) => uci::print_error(&UciError::InvalidFile(dir_path.display().to_string(), error.to_string())),
+        }
+    }
+
+    println!("info string found {} tablebases", table_count);
+    io::stdout().flush().ok();
+
+    tablebase
+}
+
+/// Plies played since the start of the game, as implied by the FEN move counter.
+fn get_game_ply(state: &State) -> usize {
+    let ply = (state.full_mov_count as usize).saturating_sub(1) * 2;
+
+    if state.player == def::PLAYER_B {
+        ply + 1
+    } else {
+        ply
+    }
+}
+
+fn calculate_time_capacity(player: u8, w_time_info: TimeInfo, b_time_info: TimeInfo) -> TimeCapacity {
+    if player == def::PLAYER_W {
+        time_control::calculate_time_capacity(w_time_info.all_time_millis, w_time_info.moves_to_go, w_time_info.increment_millis)
+    } else {
+        time_control::calculate_time_capacity(b_time_info.all_time_millis, b_time_info.moves_to_go, b_time_info.increment_millis)
+    }
+}
+
+/// Every limit of `go_limits` is applied; given both a clock and a move time, the shorter budget is used.
+fn get_search_limits(player: u8, go_limits: GoLimits) -> SearchLimits {
+    let mut time_capacity = match go_limits.time_info {
+        Some((w_time_info, b_time_info)) => calculate_time_capacity(player, w_time_info, b_time_info),
+        None => DEFAULT_MAX_TIME,
+    };
+
+    if let Some(movetime_millis) = go_limits.movetime_millis {
+        let movetime_capacity = time_control::calculate_time_capacity(movetime_millis, 1, 0);
+
+        if movetime_capacity.main_time_millis < time_capacity.main_time_millis {
+            time_capacity = movetime_capacity;
+        }
+    }
+
+    SearchLimits {
+        time_capacity,
+        max_depth: go_limits.depth.unwrap_or(DEFAULT_MAX_DEPTH),
+        max_node_count: go_limits.node_count.unwrap_or(u64::MAX),
+        mate_mov_count: go_limits.mate_mov_count,
+    }
+}
+
+fn print_ready() {
+    println!("readyok");
+    io::stdout().flush().ok();
+}
+
+/// A `best_mov` of 0 is sent as the null move `0000`.
+fn print_best_mov(state: &State, best_mov: u32, ponder_mov: u32) {
+    if best_mov == 0 {
+        println!("bestmove 0000");
+    } else if ponder_mov != 0 {
+        println!("bestmove {} ponder {}", uci::format_mov(state, best_mov), uci::format_mov(state, ponder_mov));
+    } else {
+        println!("bestmove {}", uci::format_mov(state, best_mov));
+    }
+
+    io::stdout().flush().ok();
+}
@@ -12,7 +12,21 @@ pub const MIN_HASH_SIZE_MB: usize = 1;
 pub const MIN_HASH_SIZE_UNIT: usize = 32768;
 pub const MAX_HASH_SIZE_MB: usize = 512;
 
+pub const DEFAULT_THREAD_COUNT: usize = 1;
+pub const MAX_THREAD_COUNT: usize = 128;
+
+pub const DEFAULT_MULTI_PV: usize = 1;
+pub const MAX_MULTI_PV: usize = 64;
+
+pub const DEFAULT_BOOK_DEPTH: u16 = 255;
+pub const MAX_BOOK_DEPTH: u16 = 1024;
+
+pub const DEFAULT_SYZYGY_PROBE_DEPTH: u8 = 1;
+pub const MAX_SYZYGY_PROBE_DEPTH: u8 = 100;
+pub const DEFAULT_SYZYGY_PROBE_LIMIT: usize = 7;
+
 pub const BOARD_SIZE: usize = 64;
+pub const DARK_SQR_MASK: u64 = 0xAA55AA55_AA55AA55;
 pub const DIM_SIZE: usize = 8;
 pub const PIECE_CODE_RANGE: usize = 131;
 
@@ -30,6 +44,11 @@ pub const CAS_SQUARE_WQ: usize = 2;
 pub const CAS_SQUARE_BK: usize = 62;
 pub const CAS_SQUARE_BQ: usize = 58;
 
+pub const CAS_RIGHT_BITS: [u8; 4] = [0b1000, 0b0100, 0b0010, 0b0001];
+pub const CAS_KING_SQUARES: [usize; 4] = [CAS_SQUARE_WK, CAS_SQUARE_WQ, CAS_SQUARE_BK, CAS_SQUARE_BQ];
+pub const CAS_ROOK_SQUARES: [usize; 4] = [5, 3, 61, 59];
+pub const DEFAULT_CAS_ROOK_INDEXES: [usize; 4] = [7, 0, 63, 56];
+
 pub const PLAYER_W: u8 = 0b10;
 pub const PLAYER_B: u8 = 0b01;
 pub const PLAYER_SWITCH: u8 = 0b11;
@@ -79,6 +98,24 @@ pub fn get_file_distance(index1: usize, index2: usize) -> i32 {
     ((index1 % DIM_SIZE) as isize - (index2 % DIM_SIZE) as isize).abs() as i32
 }
 
+/// Returns the number of king moves between two squares.
+#[inline]
+pub fn get_distance(index1: usize, index2: usize) -> i32 {
+    get_file_distance(index1, index2).max(((index1 / DIM_SIZE) as i32 - (index2 / DIM_SIZE) as i32).abs())
+}
+
+/// Maps the king destination of a castling move to its index in the `CAS_*` tables.
+#[inline]
+pub fn get_cas_index(king_to_index: usize) -> usize {
+    match king_to_index {
+        CAS_SQUARE_WK => 0,
+        CAS_SQUARE_WQ => 1,
+        CAS_SQUARE_BK => 2,
+        CAS_SQUARE_BQ => 3,
+        _ => panic!("invalid castling square {}", king_to_index),
+    }
+}
+
 #[inline]
 pub const fn is_k(piece_code: u8) -> bool {
     piece_code & K != 0
@@ -143,6 +180,13 @@ mod tests {
         assert_eq!(3, get_file_distance(1, 12));
     }
 
+    #[test]
+    fn test_get_distance() {
+        assert_eq!(3, get_distance(0, 27));
+        assert_eq!(7, get_distance(0, 63));
+        assert_eq!(1, get_distance(28, 35));
+    }
+
     #[test]
     fn test_piece_type() {
         assert!(is_k(WK));
@@ -0,0 +1,623 @@
+/*
+ * Copyright (C) 2020 Zixiao Han
+ */
+
+//! Evaluation of basic endgames that the generic evaluation misjudges or cannot make progress in.
+
+use crate::{
+    bitboard::BitMask,
+    def,
+    eval,
+    eval_params::EvalParams,
+    state::State,
+};
+
+use std::sync::OnceLock;
+
+static KNOWN_WIN_VAL: i32 = 2000;
+
+static KPK_POS_COUNT: usize = 24 * def::BOARD_SIZE * def::BOARD_SIZE * 2;
+
+static FILE_A_MASK: u64 = 0x01010101_01010101;
+
+static PUSH_CLOSE_VAL: [i32; def::DIM_SIZE] = [0, 0, 100, 80, 60, 40, 20, 10];
+
+static PUSH_TO_EDGE_VAL: [i32; def::BOARD_SIZE] = [
+    100, 90, 80, 70, 70, 80, 90, 100,
+     90, 70, 60, 50, 50, 60, 70,  90,
+     80, 60, 40, 30, 30, 40, 60,  80,
+     70, 50, 30, 20, 20, 30, 50,  70,
+     70, 50, 30, 20, 20, 30, 50,  70,
+     80, 60, 40, 30, 30, 40, 60,  80,
+     90, 70, 60, 50, 50, 60, 70,  90,
+    100, 90, 80, 70, 70, 80, 90, 100,
+];
+
+static PUSH_TO_DARK_CORNER_VAL: i32 = 420;
+static KING_DISTANCE_PEN: i32 = -20;
+
+static PAWN_RACE_STEP_VAL: i32 = 40;
+
+static QUEEN_DIR_LIST: [(i32, i32); 8] = [(1, 0), (-1, 0), (0, 1), (0, -1), (1, 1), (1, -1), (-1, 1), (-1, -1)];
+static ROOK_DIR_LIST: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+static KPK_BITBASE: OnceLock<KpkBitbase> = OnceLock::new();
+
+/// Win/draw bitbase for king and pawn versus king, from the point of view of white holding the
+/// pawn on files a to d.
+pub struct KpkBitbase {
+    win_bits: Vec<u64>,
+}
+
+impl KpkBitbase {
+    /// Generates the bitbase by marking won positions until no more are found: white to move wins if
+    /// one of its moves promotes safely or reaches a won position, black to move loses if every one of
+    /// its moves reaches a won position. All remaining positions are draws.
+    pub fn new(bitmask: &BitMask) -> Self {
+        let legal_list: Vec<bool> = (0..KPK_POS_COUNT).map(|kpk_index| is_legal_kpk_pos(bitmask, kpk_index)).collect();
+        let mut win_list = vec![false; KPK_POS_COUNT];
+
+        loop {
+            let mut win_found = false;
+
+            for kpk_index in 0..KPK_POS_COUNT {
+                if win_list[kpk_index] || !legal_list[kpk_index] {
+                    continue
+                }
+
+                let (wk_index, wp_index, bk_index, is_white_to_mov) = decode_kpk_index(kpk_index);
+
+                let is_win = if is_white_to_mov {
+                    is_white_win(bitmask, &win_list, wk_index, wp_index, bk_index)
+                } else {
+                    is_black_loss(bitmask, &win_list, wk_index, wp_index, bk_index)
+                };
+
+                if is_win {
+                    win_list[kpk_index] = true;
+                    win_found = true;
+                }
+            }
+
+            if !win_found {
+                break
+            }
+        }
+
+        let mut win_bits = vec![0; KPK_POS_COUNT / 64];
+
+        for (kpk_index, is_win) in win_list.iter().enumerate() {
+            if *is_win {
+                win_bits[kpk_index / 64] |= 1 << (kpk_index % 64);
+            }
+        }
+
+        KpkBitbase {
+            win_bits,
+        }
+    }
+
+    /// Returns whether the side with the pawn wins.
+    pub fn is_win(&self, strong_player: u8, strong_king_index: usize, pawn_index: usize, weak_king_index: usize, is_strong_to_mov: bool) -> bool {
+        let mut index_list = [strong_king_index, pawn_index, weak_king_index];
+
+        if strong_player == def::PLAYER_B {
+            for index in index_list.iter_mut() {
+                *index ^= 0b111000;
+            }
+        }
+
+        if index_list[1] % def::DIM_SIZE >= 4 {
+            for index in index_list.iter_mut() {
+                *index ^= 0b111;
+            }
+        }
+
+        let kpk_index = get_kpk_index(index_list[0], index_list[1], index_list[2], is_strong_to_mov);
+
+        self.win_bits[kpk_index / 64] & (1 << (kpk_index % 64)) != 0
+    }
+}
+
+/// Returns the shared bitbase, generating it from the attack masks of `bitmask` on first use; the
+/// engine controller calls this at startup so that no search has to wait for it.
+pub fn get_kpk_bitbase(bitmask: &BitMask) -> &'static KpkBitbase {
+    KPK_BITBASE.get_or_init(|| KpkBitbase::new(bitmask))
+}
+
+#[inline]
+fn get_kpk_index(wk_index: usize, wp_index: usize, bk_index: usize, is_white_to_mov: bool) -> usize {
+    let pawn_index = (wp_index / def::DIM_SIZE - 1) * 4 + wp_index % def::DIM_SIZE;
+
+    ((pawn_index * def::BOARD_SIZE + wk_index) * def::BOARD_SIZE + bk_index) * 2 + !is_white_to_mov as usize
+}
+
+#[inline]
+fn decode_kpk_index(kpk_index: usize) -> (usize, usize, usize, bool) {
+    let pawn_index = kpk_index / (def::BOARD_SIZE * def::BOARD_SIZE * 2);
+    let wp_index = (pawn_index / 4 + 1) * def::DIM_SIZE + pawn_index % 4;
+    let wk_index = kpk_index / (def::BOARD_SIZE * 2) % def::BOARD_SIZE;
+    let bk_index = kpk_index / 2 % def::BOARD_SIZE;
+
+    (wk_index, wp_index, bk_index, kpk_index.is_multiple_of(2))
+}
+
+fn is_legal_kpk_pos(bitmask: &BitMask, kpk_index: usize) -> bool {
+    let (wk_index, wp_index, bk_index, is_white_to_mov) = decode_kpk_index(kpk_index);
+
+    if wk_index == wp_index || bk_index == wp_index || def::get_distance(wk_index, bk_index) <= 1 {
+        return false
+    }
+
+    // with white to move, black must not be in check
+    !is_white_to_mov || bitmask.wp_attack_masks[wp_index] & bitmask.index_masks[bk_index] == 0
+}
+
+fn is_white_win(bitmask: &BitMask, win_list: &[bool], wk_index: usize, wp_index: usize, bk_index: usize) -> bool {
+    let mut wk_mov_mask = bitmask.k_attack_masks[wk_index] & !bitmask.k_attack_masks[bk_index] & !bitmask.index_masks[wp_index];
+
+    while wk_mov_mask != 0 {
+        let to_index = wk_mov_mask.trailing_zeros() as usize;
+
+        if win_list[get_kpk_index(to_index, wp_index, bk_index, false)] {
+            return true
+        }
+
+        wk_mov_mask &= wk_mov_mask - 1;
+    }
+
+    let push_index = wp_index + def::DIM_SIZE;
+
+    if push_index == wk_index || push_index == bk_index {
+        return false
+    }
+
+    if push_index / def::DIM_SIZE == def::DIM_SIZE - 1 {
+        return is_safe_promo(bitmask, wk_index, push_index, bk_index)
+    }
+
+    if win_list[get_kpk_index(wk_index, push_index, bk_index, false)] {
+        return true
+    }
+
+    let double_push_index = push_index + def::DIM_SIZE;
+
+    wp_index / def::DIM_SIZE == 1
+    && double_push_index != wk_index
+    && double_push_index != bk_index
+    && win_list[get_kpk_index(wk_index, double_push_index, bk_index, false)]
+}
+
+fn is_black_loss(bitmask: &BitMask, win_list: &[bool], wk_index: usize, wp_index: usize, bk_index: usize) -> bool {
+    let bk_mov_mask = bitmask.k_attack_masks[bk_index] & !bitmask.k_attack_masks[wk_index] & !bitmask.wp_attack_masks[wp_index];
+
+    if bk_mov_mask == 0 {
+        // checkmate, or a draw by stalemate
+        return bitmask.wp_attack_masks[wp_index] & bitmask.index_masks[bk_index] != 0
+    }
+
+    // taking the pawn draws
+    if bk_mov_mask & bitmask.index_masks[wp_index] != 0 {
+        return false
+    }
+
+    let mut bk_mov_mask = bk_mov_mask;
+
+    while bk_mov_mask != 0 {
+        let to_index = bk_mov_mask.trailing_zeros() as usize;
+
+        if !win_list[get_kpk_index(wk_index, wp_index, to_index, true)] {
+            return false
+        }
+
+        bk_mov_mask &= bk_mov_mask - 1;
+    }
+
+    true
+}
+
+/// Returns whether promoting on `promo_index` leaves a won king and queen or rook against king, with
+/// black to move: the new piece must not be lost and black must not be stalemated.
+fn is_safe_promo(bitmask: &BitMask, wk_index: usize, promo_index: usize, bk_index: usize) -> bool {
+    if def::get_distance(bk_index, promo_index) == 1 && def::get_distance(wk_index, promo_index) > 1 {
+        return false
+    }
+
+    [&QUEEN_DIR_LIST[..], &ROOK_DIR_LIST[..]].iter().any(|dir_list| {
+        let attack_mask = bitmask.k_attack_masks[wk_index] | get_slider_attack_mask(promo_index, dir_list, wk_index);
+
+        attack_mask & bitmask.index_masks[bk_index] != 0 || bitmask.k_attack_masks[bk_index] & !attack_mask != 0
+    })
+}
+
+/// Squares attacked by a piece sliding from `from_index` along `dir_list`, with only the square at
+/// `blocker_index` blocking it.
+fn get_slider_attack_mask(from_index: usize, dir_list: &[(i32, i32)], blocker_index: usize) -> u64 {
+    let mut attack_mask = 0;
+
+    for &(file_step, rank_step) in dir_list {
+        let mut file = (from_index % def::DIM_SIZE) as i32 + file_step;
+        let mut rank = (from_index / def::DIM_SIZE) as i32 + rank_step;
+
+        while (0..def::DIM_SIZE as i32).contains(&file) && (0..def::DIM_SIZE as i32).contains(&rank) {
+            let index = rank as usize * def::DIM_SIZE + file as usize;
+            attack_mask |= 1 << index;
+
+            if index == blocker_index {
+                break
+            }
+
+            file += file_step;
+            rank += rank_step;
+        }
+    }
+
+    attack_mask
+}
+
+/// Endgames with a dedicated evaluator; `material::MaterialEntry` records which one its piece counts match.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Endgame {
+    Kpk,
+    Kbnk,
+    Kxk,
+    Krkp,
+    Kqkp,
+    Kbpsk,
+}
+
+struct SideMaterial {
+    pawn: u64,
+    knight: u64,
+    bishop: u64,
+    rook: u64,
+    queen: u64,
+}
+
+impl SideMaterial {
+    fn new(state: &State, player: u8) -> Self {
+        let bitboard = &state.bitboard;
+
+        if player == def::PLAYER_W {
+            SideMaterial {
+                pawn: bitboard.w_pawn,
+                knight: bitboard.w_knight,
+                bishop: bitboard.w_bishop,
+                rook: bitboard.w_rook,
+                queen: bitboard.w_queen,
+            }
+        } else {
+            SideMaterial {
+                pawn: bitboard.b_pawn,
+                knight: bitboard.b_knight,
+                bishop: bitboard.b_bishop,
+                rook: bitboard.b_rook,
+                queen: bitboard.b_queen,
+            }
+        }
+    }
+
+    fn get_material_val(&self, eval_params: &EvalParams) -> i32 {
+        self.queen.count_ones() as i32 * eval_params.q_val
+        + self.rook.count_ones() as i32 * eval_params.r_val
+        + self.bishop.count_ones() as i32 * eval_params.b_val
+        + self.knight.count_ones() as i32 * eval_params.n_val
+        + self.pawn.count_ones() as i32 * eval_params.p_val
+    }
+}
+
+impl Endgame {
+    /// Returns whether `eval_endgame` gives the complete evaluation of the position, so that no
+    /// positional terms should be added to it.
+    pub fn has_complete_eval(self) -> bool {
+        self != Endgame::Kbpsk
+    }
+}
+
+/// Evaluates the position with the dedicated evaluator of `endgame`, where `strong_player` is the side
+/// with the extra material; the score is from the point of view of white, with the same draw flag as
+/// `eval::eval_materials`.
+pub fn eval_endgame(state: &State, endgame: Endgame, strong_player: u8) -> Option<(i32, bool)> {
+    let (strong_king_index, weak_king_index) = if strong_player == def::PLAYER_W {
+        (state.wk_index, state.bk_index)
+    } else {
+        (state.bk_index, state.wk_index)
+    };
+
+    let strong_material = SideMaterial::new(state, strong_player);
+    let weak_material = SideMaterial::new(state, def::get_opposite_player(strong_player));
+    let is_strong_to_mov = state.player == strong_player;
+    let eval_params = &state.eval_params;
+
+    let (score, is_draw) = match endgame {
+        Endgame::Kpk => eval_kpk(state, strong_player, strong_king_index, strong_material.pawn.trailing_zeros() as usize, weak_king_index, is_strong_to_mov),
+        Endgame::Kbnk => eval_kbnk(eval_params, strong_king_index, strong_material.bishop.trailing_zeros() as usize, weak_king_index),
+        Endgame::Kxk => eval_kxk(eval_params, &strong_material, strong_king_index, weak_king_index),
+        Endgame::Krkp => eval_krkp(eval_params, strong_player, strong_king_index, weak_material.pawn.trailing_zeros() as usize, weak_king_index, is_strong_to_mov),
+        Endgame::Kqkp => eval_kqkp(eval_params, strong_player, strong_king_index, weak_material.pawn.trailing_zeros() as usize, weak_king_index),
+        Endgame::Kbpsk => eval_kbpsk(strong_player, &strong_material, weak_king_index)?,
+    };
+
+    if strong_player == def::PLAYER_W {
+        Some((score, is_draw))
+    } else {
+        Some((-score, is_draw))
+    }
+}
+
+fn eval_kpk(state: &State, strong_player: u8, strong_king_index: usize, pawn_index: usize, weak_king_index: usize, is_strong_to_mov: bool) -> (i32, bool) {
+    if !get_kpk_bitbase(state.bitmask).is_win(strong_player, strong_king_index, pawn_index, weak_king_index, is_strong_to_mov) {
+        return (0, true)
+    }
+
+    (KNOWN_WIN_VAL + state.eval_params.p_val + def::get_rank(strong_player, pawn_index) as i32 * 10, false)
+}
+
+/// Drives the weak king towards a corner of the bishop's colour.
+fn eval_kbnk(eval_params: &EvalParams, strong_king_index: usize, bishop_index: usize, weak_king_index: usize) -> (i32, bool) {
+    let corner_index = if def::DARK_SQR_MASK & (1 << bishop_index) == 0 {
+        weak_king_index ^ 0b111
+    } else {
+        weak_king_index
+    };
+
+    let dark_corner_closeness = (7 - (corner_index / def::DIM_SIZE) as i32 - (corner_index % def::DIM_SIZE) as i32).abs();
+
+    let score = eval_params.b_val + eval_params.n_val
+    + dark_corner_closeness * PUSH_TO_DARK_CORNER_VAL
+    + def::get_distance(strong_king_index, weak_king_index) * KING_DISTANCE_PEN
+    + KNOWN_WIN_VAL;
+
+    (score, false)
+}
+
+/// Drives the lone king towards the edge and brings the kings together.
+fn eval_kxk(eval_params: &EvalParams, strong_material: &SideMaterial, strong_king_index: usize, weak_king_index: usize) -> (i32, bool) {
+    let score = strong_material.get_material_val(eval_params)
+    + PUSH_TO_EDGE_VAL[weak_king_index]
+    + PUSH_CLOSE_VAL[def::get_distance(strong_king_index, weak_king_index) as usize]
+    + KNOWN_WIN_VAL;
+
+    (score.min(eval::TERM_VAL - 1), false)
+}
+
+/// Rook against pawn: the rook side wins the pawn if its king gets in front of the pawn before it
+/// promotes or the pawn is cut off from its king. Otherwise the pawn costs the rook, so little more
+/// than a draw is left, less the further behind the strong king is in the race.
+fn eval_krkp(eval_params: &EvalParams, strong_player: u8, strong_king_index: usize, pawn_index: usize, weak_king_index: usize, is_strong_to_mov: bool) -> (i32, bool) {
+    let (strong_king_index, pawn_index, weak_king_index) = if strong_player == def::PLAYER_W {
+        (strong_king_index, pawn_index, weak_king_index)
+    } else {
+        (strong_king_index ^ 0b111000, pawn_index ^ 0b111000, weak_king_index ^ 0b111000)
+    };
+
+    // the pawn moves towards the first rank, two squares at once from its starting rank
+    let queening_index = pawn_index % def::DIM_SIZE;
+    let pawn_mov_count = (pawn_index / def::DIM_SIZE).min(def::DIM_SIZE - 3) as i32;
+    let strong_king_mov_count = def::get_distance(strong_king_index, queening_index) - is_strong_to_mov as i32;
+    let race_lead = pawn_mov_count - strong_king_mov_count;
+
+    let is_pawn_supported = def::get_distance(weak_king_index, pawn_index) <= 2 - is_strong_to_mov as i32;
+
+    let score = if race_lead > 0 || !is_pawn_supported {
+        eval_params.r_val - eval_params.p_val + PUSH_CLOSE_VAL[def::get_distance(strong_king_index, pawn_index) as usize]
+    } else {
+        ((eval_params.r_val - eval_params.p_val) / 4 + race_lead * PAWN_RACE_STEP_VAL).max(0)
+    };
+
+    (score, false)
+}
+
+/// Queen against pawn: a win, except that a king next to a rook or bishop pawn one step from promotion
+/// holds the draw through stalemate while the strong king is too far away to help.
+fn eval_kqkp(eval_params: &EvalParams, strong_player: u8, strong_king_index: usize, pawn_index: usize, weak_king_index: usize) -> (i32, bool) {
+    let pawn_file = pawn_index % def::DIM_SIZE;
+    let queening_index = if strong_player == def::PLAYER_W {
+        pawn_file
+    } else {
+        pawn_file + def::BOARD_SIZE - def::DIM_SIZE
+    };
+
+    let is_stalemate_defence = def::get_rank(def::get_opposite_player(strong_player), pawn_index) == def::DIM_SIZE - 2
+    && def::get_distance(weak_king_index, pawn_index) == 1
+    && matches!(pawn_file.min(def::DIM_SIZE - 1 - pawn_file), 0 | 2)
+    && def::get_distance(strong_king_index, queening_index) > 2;
+
+    let mut score = PUSH_CLOSE_VAL[def::get_distance(strong_king_index, pawn_index) as usize];
+
+    if !is_stalemate_defence {
+        score += eval_params.q_val - eval_params.p_val;
+    }
+
+    (score, false)
+}
+
+/// Bishop and rook pawns: a draw when the bishop does not control the queening square and the
+/// lone king has reached it.
+fn eval_kbpsk(strong_player: u8, strong_material: &SideMaterial, weak_king_index: usize) -> Option<(i32, bool)> {
+    let pawn_file = strong_material.pawn.trailing_zeros() as usize % def::DIM_SIZE;
+
+    if pawn_file != 0 && pawn_file != def::DIM_SIZE - 1 {
+        return None
+    }
+
+    let file_mask = FILE_A_MASK << pawn_file;
+
+    if strong_material.pawn & !file_mask != 0 {
+        return None
+    }
+
+    let queening_index = if strong_player == def::PLAYER_W {
+        pawn_file + def::BOARD_SIZE - def::DIM_SIZE
+    } else {
+        pawn_file
+    };
+
+    let queening_sqr_mask = if def::DARK_SQR_MASK & (1 << queening_index) == 0 {
+        !def::DARK_SQR_MASK
+    } else {
+        def::DARK_SQR_MASK
+    };
+
+    if strong_material.bishop & queening_sqr_mask == 0 && def::get_distance(weak_king_index, queening_index) <= 1 {
+        return Some((0, true))
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        material::MaterialEntry,
+        mov_table,
+        prng::XorshiftPrng,
+        syzygy::{self, SyzygyTablebase},
+    };
+
+    use std::path::Path;
+
+    #[test]
+    fn test_kpk_bitbase() {
+        let bitmask = BitMask::new();
+        let kpk_bitbase = get_kpk_bitbase(&bitmask);
+
+        let is_win = |fen_board: &str, strong_player: u8, is_strong_to_mov: bool| {
+            let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+            let state = State::new(&format!("{} w - - 0 1", fen_board), &zob_keys, &bitmask);
+            let (strong_king_index, weak_king_index, pawn_mask) = if strong_player == def::PLAYER_W {
+                (state.wk_index, state.bk_index, state.bitboard.w_pawn)
+            } else {
+                (state.bk_index, state.wk_index, state.bitboard.b_pawn)
+            };
+
+            kpk_bitbase.is_win(strong_player, strong_king_index, pawn_mask.trailing_zeros() as usize, weak_king_index, is_strong_to_mov)
+        };
+
+        assert!(is_win("4k3/8/4K3/4P3/8/8/8/8", def::PLAYER_W, true));
+        assert!(is_win("4k3/8/4K3/4P3/8/8/8/8", def::PLAYER_W, false));
+        assert!(!is_win("8/8/8/8/8/4k3/4P3/4K3", def::PLAYER_W, true));
+        assert!(!is_win("k7/8/K7/P7/8/8/8/8", def::PLAYER_W, false));
+        assert!(is_win("8/8/8/8/3p4/3k4/8/3K4", def::PLAYER_B, true));
+        assert!(is_win("8/8/8/8/3p4/3k4/8/3K4", def::PLAYER_B, false));
+    }
+
+    #[test]
+    fn test_kpk_bitbase_matches_tablebase() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let kpk_bitbase = get_kpk_bitbase(&bitmask);
+        let mut tablebase = SyzygyTablebase::new();
+        tablebase.add_file(&Path::new(env!("CARGO_MANIFEST_DIR")).join("res/syzygy/KPvK.rtbw")).unwrap();
+
+        for &(strong_player, pawn_char, strong_king_char, weak_king_char) in [(def::PLAYER_W, 'P', 'K', 'k'), (def::PLAYER_B, 'p', 'k', 'K')].iter() {
+            for rank in 1..def::DIM_SIZE - 1 {
+                let pawn_index = rank * def::DIM_SIZE + 4;
+
+                for strong_king_index in 0..def::BOARD_SIZE {
+                    for weak_king_index in 0..def::BOARD_SIZE {
+                        if strong_king_index == pawn_index || weak_king_index == pawn_index || def::get_distance(strong_king_index, weak_king_index) <= 1 {
+                            continue
+                        }
+
+                        let mut squares = [' '; def::BOARD_SIZE];
+                        squares[pawn_index] = pawn_char;
+                        squares[strong_king_index] = strong_king_char;
+                        squares[weak_king_index] = weak_king_char;
+
+                        for &player in [def::PLAYER_W, def::PLAYER_B].iter() {
+                            let mut state = State::new(&format_fen(&squares, player), &zob_keys, &bitmask);
+
+                            if mov_table::is_in_check(&state, def::get_opposite_player(player)) || mov_table::gen_legal_mov_list(&state).is_empty() {
+                                continue
+                            }
+
+                            let is_strong_to_mov = player == strong_player;
+                            let strong_wdl = if is_strong_to_mov { tablebase.probe_wdl(&mut state) } else { tablebase.probe_wdl(&mut state).map(|wdl| -wdl) };
+
+                            assert_eq!(strong_wdl == Some(syzygy::WDL_WIN), kpk_bitbase.is_win(strong_player, strong_king_index, pawn_index, weak_king_index, is_strong_to_mov), "{}", state.to_fen());
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn format_fen(squares: &[char; def::BOARD_SIZE], player: u8) -> String {
+        let mut fen = String::new();
+
+        for rank in (0..def::DIM_SIZE).rev() {
+            let mut empty_count = 0;
+
+            for file in 0..def::DIM_SIZE {
+                match squares[rank * def::DIM_SIZE + file] {
+                    ' ' => empty_count += 1,
+                    piece_char => {
+                        if empty_count > 0 {
+                            fen.push_str(&empty_count.to_string());
+                            empty_count = 0;
+                        }
+
+                        fen.push(piece_char);
+                    },
+                }
+            }
+
+            if empty_count > 0 {
+                fen.push_str(&empty_count.to_string());
+            }
+
+            if rank > 0 {
+                fen.push('/');
+            }
+        }
+
+        fen + if player == def::PLAYER_W { " w - - 0 1" } else { " b - - 0 1" }
+    }
+
+    #[test]
+    fn test_eval_endgame() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let eval_params = EvalParams::default();
+
+        let eval = |fen: &str| {
+            let state = State::new(fen, &zob_keys, &bitmask);
+            let (endgame, strong_player) = MaterialEntry::new(state.material_key, &state.eval_params).endgame?;
+
+            eval_endgame(&state, endgame, strong_player)
+        };
+
+        assert_eq!(Some((0, true)), eval("8/8/8/8/8/4k3/4P3/4K3 w - - 0 1"));
+        assert!(eval("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap().0 > KNOWN_WIN_VAL);
+        assert!(eval("8/8/8/8/3p4/3k4/8/3K4 w - - 0 1").unwrap().0 < -KNOWN_WIN_VAL);
+
+        let (corner_score, _) = eval("7k/8/5K2/8/8/8/8/3NB3 w - - 0 1").unwrap();
+        let (wrong_corner_score, _) = eval("k7/8/2K5/8/8/8/8/3NB3 w - - 0 1").unwrap();
+        assert!(corner_score > wrong_corner_score);
+        assert!(wrong_corner_score > KNOWN_WIN_VAL);
+
+        let (edge_score, _) = eval("3k4/8/3K4/8/8/8/8/7R w - - 0 1").unwrap();
+        let (center_score, _) = eval("8/8/8/3k4/8/8/3K4/7R w - - 0 1").unwrap();
+        assert!(edge_score > center_score);
+        assert!(eval("8/8/8/3K4/8/8/3k4/7q w - - 0 1").unwrap().0 < -KNOWN_WIN_VAL);
+
+        let (krkp_win_score, _) = eval("8/8/8/2p5/3K4/8/8/k6R w - - 0 1").unwrap();
+        let (krkp_close_score, _) = eval("R7/8/8/8/8/K7/4pk2/8 w - - 0 1").unwrap();
+        assert!(krkp_win_score > krkp_close_score);
+        assert!(krkp_close_score < eval_params.r_val / 2);
+
+        assert!(eval("8/8/8/8/8/5K2/1Q1p4/2k5 w - - 0 1").unwrap().0 > eval_params.q_val / 2);
+        assert!(eval("7K/8/8/8/Q7/8/2p5/1k6 w - - 0 1").unwrap().0 < eval_params.q_val / 2);
+        assert!(eval("8/8/8/8/Q7/8/2p5/1k1K4 w - - 0 1").unwrap().0 > eval_params.q_val / 2);
+
+        assert_eq!(Some((0, true)), eval("7k/8/6KP/8/8/8/2B5/8 w - - 0 1"));
+        assert_eq!(None, eval("7k/8/6KP/8/8/2B5/8/8 w - - 0 1"));
+        assert_eq!(Some((0, true)), eval("8/1b6/8/8/8/p7/8/1K3k2 w - - 0 1"));
+        assert_eq!(None, eval("8/2b5/8/8/8/8/p7/4K1k1 w - - 0 1"));
+
+        assert_eq!(None, eval("4k3/8/8/8/8/8/4PP2/4K3 w - - 0 1"));
+        assert_eq!(None, eval("4k3/8/8/8/8/8/8/2N1K3 w - - 0 1"));
+    }
+}
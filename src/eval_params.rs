@@ -0,0 +1,463 @@
+/*
+ * Copyright (C) 2020 Zixiao Han
+ */
+
+use crate::def;
+
+use std::fmt;
+
+static Q_VAL: i32 = 1200;
+static R_VAL: i32 = 600;
+static B_VAL: i32 = 400;
+static N_VAL: i32 = 400;
+static P_VAL: i32 = 100;
+
+static SQR_TABLE_P: [i32; def::BOARD_SIZE] = [
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,-10,-10,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0, 20, 20,  0,  0,  0,
+      5,  5, 10, 25, 25, 10,  5,  5,
+     10, 20, 20, 30, 30, 20, 20, 10,
+     15, 30, 30, 30, 30, 30, 30, 15,
+      0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+static SQR_TABLE_P_ENDGAME: [i32; def::BOARD_SIZE] = [
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0, 10, 10, 10, 10, 10, 10,  0,
+      5, 15, 15, 15, 15, 15, 15,  5,
+     10, 20, 20, 20, 20, 20, 20, 10,
+     15, 30, 30, 30, 30, 30, 30, 15,
+      0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+static SQR_TABLE_N: [i32; def::BOARD_SIZE] = [
+    -60,-20,-20,-20,-20,-20,-20,-60,
+    -30,-30,  0,  0,  0,  0,-30,-30,
+    -20,  5, 10,  0,  0, 10,  5,-20,
+    -20,  0, 10, 20, 20, 10,  0,-20,
+    -20,  5, 10, 25, 25, 10,  5,-20,
+    -20,  0, 15, 20, 20, 15,  0,-20,
+    -30,-30, 20, 10, 10, 20,-30,-30,
+    -60,-20,-20,-20,-20,-20,-20,-60,
+];
+
+static SQR_TABLE_B: [i32; def::BOARD_SIZE] = [
+    -50,-10,-10,-10,-10,-10,-10,-50,
+    -10,  5,  0,  0,  0,  0,  5,-10,
+    -10, 10, 10,  5,  5, 10, 10,-10,
+    -10,  0, 10, 10, 10, 10,  0,-10,
+    -10,  5,  5, 10, 10,  5,  5,-10,
+    -10,  0,  5, 10, 10,  5,  0,-10,
+    -20,  0, 10,  0,  0, 10,  0,-20,
+    -50,-10,-10,-10,-10,-10,-10,-50,
+];
+
+static SQR_TABLE_R: [i32; def::BOARD_SIZE] = [
+     -5,  0,  0,  0,  0,  0,  0, -5,
+    -10, -5,  0,  0,  0,  0, -5,-10,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     -5,  0,  0,  0,  0,  0,  0, -5,
+     10, 20, 30, 30, 30, 30, 20, 10,
+     10, 10, 20, 20, 20, 20, 10, 10,
+];
+
+static SQR_TABLE_Q: [i32; def::BOARD_SIZE] = [
+    -20,-10,-10, -5, -5,-10,-10,-20,
+    -10,-10,  0,  0,  0,  0,-10,-10,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+      0,  0,  0,  0,  0,  0,  0,  0,
+    -10,-10,  0,  0,  0,  0,-10,-10,
+    -20,-10,-10, -5, -5,-10,-10,-20,
+];
+
+static SQR_TABLE_K: [i32; def::BOARD_SIZE] = [
+      0, 20,  0,-20,-20,  0, 20,  0,
+      0, 10,-10,-30,-30,-10, 10,  0,
+    -60,-60,-60,-60,-60,-60,-60,-60,
+    -90,-90,-90,-90,-90,-90,-90,-90,
+    -90,-90,-90,-90,-90,-90,-90,-90,
+    -90,-90,-90,-90,-90,-90,-90,-90,
+    -90,-90,-90,-90,-90,-90,-90,-90,
+    -90,-90,-90,-90,-90,-90,-90,-90,
+];
+
+static SQR_TABLE_K_ENDGAME: [i32; def::BOARD_SIZE] = [
+    -50,-40,-30,-20,-20,-30,-40,-50,
+    -40,-30,-10,-10,-10,-10,-30,-40,
+    -30,-10, 10, 30, 30, 10,-10,-30,
+    -30,-10, 30, 50, 50, 30,-10,-30,
+    -30,-10, 30, 50, 50, 30,-10,-30,
+    -30,-10, 10, 30, 30, 10,-10,-30,
+    -30,-30,-10,-10,-10,-10,-30,-40,
+    -50,-40,-30,-30,-30,-30,-40,-50,
+];
+
+static KING_EXPOSED_PEN: i32 = -30;
+static KING_LOST_CAS_RIGHTS_PEN: i32 = -30;
+
+static KING_ATTACKER_PEN: i32 = -10;
+
+static PASS_PAWN_VAL: [i32; def::DIM_SIZE] = [0, 10, 10, 20, 40, 80, 200, 0];
+static CANDIDATE_PASS_PAWN_VAL: [i32; def::DIM_SIZE] = [0, 10, 10, 10, 20, 20, 0, 0];
+
+static PASSED_PAWN_KING_DISTANCE_BASE_PEN: i32 = -6;
+static CANDIDATE_PASSED_PAWN_KING_DISTANCE_BASE_PEN: i32 = -4;
+static UNSTOPPABLE_PASS_PAWN_VAL: i32 = 90;
+static CONTROLLED_PASS_PAWN_VAL: i32 = 50;
+static DOUBLED_PAWN_PEN: i32 = -10;
+static ISOLATED_PAWN_PEN: i32 = -20;
+static OPEN_BEHIND_PAWN_PEN: i32 = -5;
+
+static ROOK_OPEN_LINE_VAL: i32 = 30;
+static QUEEN_OPEN_LINE_VAL: i32 = 20;
+
+static HANGING_P_PEN: i32 = -10;
+static HANGING_N_PEN: i32 = -20;
+static HANGING_B_PEN: i32 = -20;
+static HANGING_R_PEN: i32 = -30;
+static HANGING_Q_PEN: i32 = -50;
+
+static TEMPO_VAL: i32 = 10;
+
+static N_MOB_SCORE: [i32; 9] = [-50, -30, -10, 0, 5, 10, 20, 25, 30];
+static B_MOB_SCORE: [i32; 15] = [-50, -30, -20, 0, 0, 0, 0, 5, 10, 15, 20, 25, 30, 30, 30];
+static R_MOB_SCORE: [i32; 15] = [-50, -20, 0, 0, 5, 10, 15, 20, 25, 30, 30, 30, 30, 30, 30];
+static Q_MOB_SCORE: [i32; 29] = [-30, -20, -20, -10, -10, 0, 0, 5, 5, 10, 10, 10, 10, 15, 15, 15, 15, 20, 20, 20, 20, 30, 30, 30, 30, 30, 30, 30, 30];
+
+pub const MIN_PARAM_VAL: i32 = -1000;
+pub const MAX_PARAM_VAL: i32 = 1000;
+
+pub const MIN_PIECE_VAL: i32 = 1;
+pub const MAX_PIECE_VAL: i32 = 2000;
+
+/// Names of the scalar parameters, as used both in eval files and as UCI options.
+pub static SCALAR_PARAM_NAMES: [&str; 18] = [
+    "KingExposedPen",
+    "KingLostCasRightsPen",
+    "KingAttackerPen",
+    "PassedPawnKingDistanceBasePen",
+    "CandidatePassedPawnKingDistanceBasePen",
+    "UnstoppablePassPawnVal",
+    "ControlledPassPawnVal",
+    "DoubledPawnPen",
+    "IsolatedPawnPen",
+    "OpenBehindPawnPen",
+    "RookOpenLineVal",
+    "QueenOpenLineVal",
+    "HangingPPen",
+    "HangingNPen",
+    "HangingBPen",
+    "HangingRPen",
+    "HangingQPen",
+    "TempoVal",
+];
+
+#[derive(PartialEq, Debug)]
+pub enum EvalParamsError {
+    UnknownParam(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+    InvalidLength(String, usize, usize),
+}
+
+impl fmt::Display for EvalParamsError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalParamsError::UnknownParam(name) => write!(formatter, "unknown parameter {}", name),
+            EvalParamsError::MissingValue(name) => write!(formatter, "missing value for {}", name),
+            EvalParamsError::InvalidValue(name, value) => write!(formatter, "invalid value {} for {}", value, name),
+            EvalParamsError::InvalidLength(name, expected_len, len) => write!(formatter, "{} expects {} values but has {}", name, expected_len, len),
+        }
+    }
+}
+
+/// The tunable weights of the evaluation. The piece-square tables are from the point of view of white,
+/// indexed from a1 to h8.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EvalParams {
+    pub q_val: i32,
+    pub r_val: i32,
+    pub b_val: i32,
+    pub n_val: i32,
+    pub p_val: i32,
+
+    pub sqr_table_p: [i32; def::BOARD_SIZE],
+    pub sqr_table_p_endgame: [i32; def::BOARD_SIZE],
+    pub sqr_table_n: [i32; def::BOARD_SIZE],
+    pub sqr_table_b: [i32; def::BOARD_SIZE],
+    pub sqr_table_r: [i32; def::BOARD_SIZE],
+    pub sqr_table_q: [i32; def::BOARD_SIZE],
+    pub sqr_table_k: [i32; def::BOARD_SIZE],
+    pub sqr_table_k_endgame: [i32; def::BOARD_SIZE],
+
+    pub king_exposed_pen: i32,
+    pub king_lost_cas_rights_pen: i32,
+    pub king_attacker_pen: i32,
+
+    pub pass_pawn_val: [i32; def::DIM_SIZE],
+    pub candidate_pass_pawn_val: [i32; def::DIM_SIZE],
+
+    pub passed_pawn_king_distance_base_pen: i32,
+    pub candidate_passed_pawn_king_distance_base_pen: i32,
+    pub unstoppable_pass_pawn_val: i32,
+    pub controlled_pass_pawn_val: i32,
+    pub doubled_pawn_pen: i32,
+    pub isolated_pawn_pen: i32,
+    pub open_behind_pawn_pen: i32,
+
+    pub rook_open_line_val: i32,
+    pub queen_open_line_val: i32,
+
+    pub hanging_p_pen: i32,
+    pub hanging_n_pen: i32,
+    pub hanging_b_pen: i32,
+    pub hanging_r_pen: i32,
+    pub hanging_q_pen: i32,
+
+    pub tempo_val: i32,
+
+    pub n_mob_score: [i32; 9],
+    pub b_mob_score: [i32; 15],
+    pub r_mob_score: [i32; 15],
+    pub q_mob_score: [i32; 29],
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        EvalParams {
+            q_val: Q_VAL,
+            r_val: R_VAL,
+            b_val: B_VAL,
+            n_val: N_VAL,
+            p_val: P_VAL,
+
+            sqr_table_p: SQR_TABLE_P,
+            sqr_table_p_endgame: SQR_TABLE_P_ENDGAME,
+            sqr_table_n: SQR_TABLE_N,
+            sqr_table_b: SQR_TABLE_B,
+            sqr_table_r: SQR_TABLE_R,
+            sqr_table_q: SQR_TABLE_Q,
+            sqr_table_k: SQR_TABLE_K,
+            sqr_table_k_endgame: SQR_TABLE_K_ENDGAME,
+
+            king_exposed_pen: KING_EXPOSED_PEN,
+            king_lost_cas_rights_pen: KING_LOST_CAS_RIGHTS_PEN,
+            king_attacker_pen: KING_ATTACKER_PEN,
+
+            pass_pawn_val: PASS_PAWN_VAL,
+            candidate_pass_pawn_val: CANDIDATE_PASS_PAWN_VAL,
+
+            passed_pawn_king_distance_base_pen: PASSED_PAWN_KING_DISTANCE_BASE_PEN,
+            candidate_passed_pawn_king_distance_base_pen: CANDIDATE_PASSED_PAWN_KING_DISTANCE_BASE_PEN,
+            unstoppable_pass_pawn_val: UNSTOPPABLE_PASS_PAWN_VAL,
+            controlled_pass_pawn_val: CONTROLLED_PASS_PAWN_VAL,
+            doubled_pawn_pen: DOUBLED_PAWN_PEN,
+            isolated_pawn_pen: ISOLATED_PAWN_PEN,
+            open_behind_pawn_pen: OPEN_BEHIND_PAWN_PEN,
+
+            rook_open_line_val: ROOK_OPEN_LINE_VAL,
+            queen_open_line_val: QUEEN_OPEN_LINE_VAL,
+
+            hanging_p_pen: HANGING_P_PEN,
+            hanging_n_pen: HANGING_N_PEN,
+            hanging_b_pen: HANGING_B_PEN,
+            hanging_r_pen: HANGING_R_PEN,
+            hanging_q_pen: HANGING_Q_PEN,
+
+            tempo_val: TEMPO_VAL,
+
+            n_mob_score: N_MOB_SCORE,
+            b_mob_score: B_MOB_SCORE,
+            r_mob_score: R_MOB_SCORE,
+            q_mob_score: Q_MOB_SCORE,
+        }
+    }
+}
+
+impl EvalParams {
+    /// Parses `name = value` or `"name": value` pairs, with `[a, b, ...]` lists for the tables; braces,
+    /// commas and `#` comments are ignored, so both JSON and simple TOML files are accepted.
+    /// Parameters that are not listed keep their default value, and values out of range are clamped like
+    /// the UCI options; piece values have their own range and can only be set here.
+    pub fn parse(params_str: &str) -> Result<Self, EvalParamsError> {
+        let mut params = EvalParams::default();
+
+        let mut token_str = String::new();
+        for line in params_str.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            token_str.push_str(&line.replace('[', " [ ").replace(']', " ] "));
+            token_str.push(' ');
+        }
+
+        let token_list: Vec<&str> = token_str.split(|char_code: char| char_code.is_whitespace() || "{}\"',=:".contains(char_code))
+            .filter(|token| !token.is_empty())
+            .collect();
+
+        let mut token_index = 0;
+        while token_index < token_list.len() {
+            let name = token_list[token_index];
+
+            match token_list.get(token_index + 1) {
+                Some(&"[") => {
+                    let value_count = token_list[token_index + 2..].iter().position(|token| *token == "]").ok_or_else(|| EvalParamsError::MissingValue(name.to_owned()))?;
+                    let value_list = token_list[token_index + 2..token_index + 2 + value_count].iter()
+                        .map(|value| parse_value(name, value).map(|value| value.clamp(MIN_PARAM_VAL, MAX_PARAM_VAL)))
+                        .collect::<Result<Vec<i32>, EvalParamsError>>()?;
+
+                    let table = params.get_table_mut(name).ok_or_else(|| EvalParamsError::UnknownParam(name.to_owned()))?;
+                    if table.len() != value_list.len() {
+                        return Err(EvalParamsError::InvalidLength(name.to_owned(), table.len(), value_list.len()))
+                    }
+
+                    table.copy_from_slice(&value_list);
+                    token_index += value_count + 3;
+                },
+                Some(value) => {
+                    let value = parse_value(name, value)?;
+
+                    if let Some(piece_val) = params.get_piece_val_mut(name) {
+                        *piece_val = value.clamp(MIN_PIECE_VAL, MAX_PIECE_VAL);
+                    } else {
+                        *params.get_scalar_mut(name).ok_or_else(|| EvalParamsError::UnknownParam(name.to_owned()))? = value.clamp(MIN_PARAM_VAL, MAX_PARAM_VAL);
+                    }
+
+                    token_index += 2;
+                },
+                None => return Err(EvalParamsError::MissingValue(name.to_owned())),
+            }
+        }
+
+        Ok(params)
+    }
+
+    /// Looks up a scalar parameter by its case-insensitive name from `SCALAR_PARAM_NAMES`.
+    pub fn get_scalar_mut(&mut self, name: &str) -> Option<&mut i32> {
+        match name.to_lowercase().as_str() {
+            "kingexposedpen" => Some(&mut self.king_exposed_pen),
+            "kinglostcasrightspen" => Some(&mut self.king_lost_cas_rights_pen),
+            "kingattackerpen" => Some(&mut self.king_attacker_pen),
+            "passedpawnkingdistancebasepen" => Some(&mut self.passed_pawn_king_distance_base_pen),
+            "candidatepassedpawnkingdistancebasepen" => Some(&mut self.candidate_passed_pawn_king_distance_base_pen),
+            "unstoppablepasspawnval" => Some(&mut self.unstoppable_pass_pawn_val),
+            "controlledpasspawnval" => Some(&mut self.controlled_pass_pawn_val),
+            "doubledpawnpen" => Some(&mut self.doubled_pawn_pen),
+            "isolatedpawnpen" => Some(&mut self.isolated_pawn_pen),
+            "openbehindpawnpen" => Some(&mut self.open_behind_pawn_pen),
+            "rookopenlineval" => Some(&mut self.rook_open_line_val),
+            "queenopenlineval" => Some(&mut self.queen_open_line_val),
+            "hangingppen" => Some(&mut self.hanging_p_pen),
+            "hangingnpen" => Some(&mut self.hanging_n_pen),
+            "hangingbpen" => Some(&mut self.hanging_b_pen),
+            "hangingrpen" => Some(&mut self.hanging_r_pen),
+            "hangingqpen" => Some(&mut self.hanging_q_pen),
+            "tempoval" => Some(&mut self.tempo_val),
+            _ => None,
+        }
+    }
+
+    fn get_piece_val_mut(&mut self, name: &str) -> Option<&mut i32> {
+        match name.to_lowercase().as_str() {
+            "qval" => Some(&mut self.q_val),
+            "rval" => Some(&mut self.r_val),
+            "bval" => Some(&mut self.b_val),
+            "nval" => Some(&mut self.n_val),
+            "pval" => Some(&mut self.p_val),
+            _ => None,
+        }
+    }
+
+    fn get_table_mut(&mut self, name: &str) -> Option<&mut [i32]> {
+        match name.to_lowercase().as_str() {
+            "sqrtablep" => Some(&mut self.sqr_table_p),
+            "sqrtablependgame" => Some(&mut self.sqr_table_p_endgame),
+            "sqrtablen" => Some(&mut self.sqr_table_n),
+            "sqrtableb" => Some(&mut self.sqr_table_b),
+            "sqrtabler" => Some(&mut self.sqr_table_r),
+            "sqrtableq" => Some(&mut self.sqr_table_q),
+            "sqrtablek" => Some(&mut self.sqr_table_k),
+            "sqrtablekendgame" => Some(&mut self.sqr_table_k_endgame),
+            "passpawnval" => Some(&mut self.pass_pawn_val),
+            "candidatepasspawnval" => Some(&mut self.candidate_pass_pawn_val),
+            "nmobscore" => Some(&mut self.n_mob_score),
+            "bmobscore" => Some(&mut self.b_mob_score),
+            "rmobscore" => Some(&mut self.r_mob_score),
+            "qmobscore" => Some(&mut self.q_mob_score),
+            _ => None,
+        }
+    }
+}
+
+fn parse_value(name: &str, value: &str) -> Result<i32, EvalParamsError> {
+    value.parse::<i32>().map_err(|_| EvalParamsError::InvalidValue(name.to_owned(), value.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalar_param_names() {
+        let mut params = EvalParams::default();
+
+        for name in SCALAR_PARAM_NAMES.iter() {
+            *params.get_scalar_mut(name).unwrap() += 1;
+        }
+
+        assert_eq!(TEMPO_VAL + 1, params.tempo_val);
+        assert_eq!(KING_EXPOSED_PEN + 1, params.king_exposed_pen);
+        assert_eq!(HANGING_Q_PEN + 1, params.hanging_q_pen);
+        assert_eq!(None, params.get_scalar_mut("NMobScore"));
+    }
+
+    #[test]
+    fn test_parse_eval_params() {
+        assert_eq!(Ok(EvalParams::default()), EvalParams::parse(""));
+
+        let json_params = EvalParams::parse("{\n  \"TempoVal\": 15,\n  \"PassPawnVal\": [\n    0, 5, 10, 20, 40, 80, 160, 0\n  ]\n}").unwrap();
+        assert_eq!(15, json_params.tempo_val);
+        assert_eq!([0, 5, 10, 20, 40, 80, 160, 0], json_params.pass_pawn_val);
+        assert_eq!(DOUBLED_PAWN_PEN, json_params.doubled_pawn_pen);
+
+        let toml_params = EvalParams::parse("# tuned\nTempoVal = 15\nNMobScore = [-40, -30, -10, 0, 5, 10, 20, 25, 30] # knights\n").unwrap();
+        assert_eq!(15, toml_params.tempo_val);
+        assert_eq!(-40, toml_params.n_mob_score[0]);
+        assert_eq!(json_params.tempo_val, toml_params.tempo_val);
+
+        assert_eq!(Err(EvalParamsError::UnknownParam("tempo_val".to_owned())), EvalParams::parse("tempo_val = 15"));
+        assert_eq!(Err(EvalParamsError::InvalidValue("TempoVal".to_owned(), "high".to_owned())), EvalParams::parse("TempoVal = high"));
+        assert_eq!(Err(EvalParamsError::MissingValue("TempoVal".to_owned())), EvalParams::parse("TempoVal ="));
+        assert_eq!(Err(EvalParamsError::MissingValue("NMobScore".to_owned())), EvalParams::parse("NMobScore = [1, 2"));
+        assert_eq!(Err(EvalParamsError::InvalidLength("NMobScore".to_owned(), 9, 2)), EvalParams::parse("NMobScore = [1, 2]"));
+    }
+
+    #[test]
+    fn test_parse_piece_params() {
+        let params = EvalParams::parse("QVal = 1000\nPVal = 90\nSqrTableKEndgame = [\n    -40,-30,-20,-20,-20,-20,-30,-40,\n    -30,-20,-10,-10,-10,-10,-20,-30,\n    -20,-10, 10, 20, 20, 10,-10,-20,\n    -20,-10, 20, 40, 40, 20,-10,-20,\n    -20,-10, 20, 40, 40, 20,-10,-20,\n    -20,-10, 10, 20, 20, 10,-10,-20,\n    -30,-20,-10,-10,-10,-10,-20,-30,\n    -40,-30,-20,-20,-20,-20,-30,-40,\n]").unwrap();
+        assert_eq!(1000, params.q_val);
+        assert_eq!(90, params.p_val);
+        assert_eq!(R_VAL, params.r_val);
+        assert_eq!(40, params.sqr_table_k_endgame[27]);
+        assert_eq!(SQR_TABLE_K, params.sqr_table_k);
+
+        assert_eq!(Err(EvalParamsError::InvalidLength("SqrTableN".to_owned(), def::BOARD_SIZE, 3)), EvalParams::parse("SqrTableN = [1, 2, 3]"));
+        assert_eq!(None, EvalParams::default().get_scalar_mut("QVal"));
+    }
+
+    #[test]
+    fn test_parse_clamps_values() {
+        let params = EvalParams::parse("TempoVal = 5000\nKingExposedPen = -5000\nQVal = 9000\nPVal = -100\nPassPawnVal = [0, 10, 10, 20, 40, 80, 2000, 0]").unwrap();
+        assert_eq!(MAX_PARAM_VAL, params.tempo_val);
+        assert_eq!(MIN_PARAM_VAL, params.king_exposed_pen);
+        assert_eq!(MAX_PIECE_VAL, params.q_val);
+        assert_eq!(MIN_PIECE_VAL, params.p_val);
+        assert_eq!(MAX_PARAM_VAL, params.pass_pawn_val[6]);
+    }
+}
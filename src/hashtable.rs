@@ -2,31 +2,39 @@
  * Copyright (C) 2020 Zixiao Han
  */
 
-#[derive(Clone)]
+use crate::eval::PawnEntry;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
 struct TableEntry {
-    key: u64,
-    player: u8,
-    depth: u8,
-    cas_rights: u8,
-    enp_sqr: u8,
-    flag: u8,
-    score: i32,
-    mov: u32,
+    key: AtomicU64,
+    data: AtomicU64,
 }
 
 impl TableEntry {
     fn empty() -> Self {
         TableEntry {
-            key: 0,
-            player: 0,
-            depth: 0,
-            cas_rights: 0,
-            enp_sqr: 0,
-            flag: 0,
-            score: 0,
-            mov: 0,
+            key: AtomicU64::new(0),
+            data: AtomicU64::new(0),
         }
     }
+
+    #[inline]
+    fn load(&self) -> (u64, u64) {
+        let data = self.data.load(Ordering::Relaxed);
+        (self.key.load(Ordering::Relaxed) ^ data, data)
+    }
+
+    #[inline]
+    fn store(&self, key: u64, data: u64) {
+        self.key.store(key ^ data, Ordering::Relaxed);
+        self.data.store(data, Ordering::Relaxed);
+    }
+
+    fn clear(&self) {
+        self.key.store(0, Ordering::Relaxed);
+        self.data.store(0, Ordering::Relaxed);
+    }
 }
 
 pub const HASH_TYPE_EXACT: u8 = 0;
@@ -40,6 +48,33 @@ pub enum LookupResult {
     NoMatch,
 }
 
+#[inline]
+fn encode_entry_data(depth: u8, flag: u8, score: i32, mov: u32) -> u64 {
+    mov as u64 | (score as i16 as u16 as u64) << 32 | (depth as u64) << 48 | (flag as u64) << 56
+}
+
+#[inline]
+fn decode_entry_data(data: u64) -> (u8, u8, i32, u32) {
+    ((data >> 48) as u8, (data >> 56) as u8, (data >> 32) as u16 as i16 as i32, data as u32)
+}
+
+#[inline]
+fn lookup(entry: &TableEntry, key: u64, depth: u8) -> LookupResult {
+    let (entry_key, data) = entry.load();
+
+    if entry_key != key {
+        return LookupResult::NoMatch
+    }
+
+    let (entry_depth, flag, score, mov) = decode_entry_data(data);
+
+    if entry_depth >= depth {
+        LookupResult::Match(flag, score, mov)
+    } else {
+        LookupResult::MovOnly(mov)
+    }
+}
+
 pub struct DepthPreferredHashTable {
     mod_base: u64,
     table: Vec<TableEntry>,
@@ -49,47 +84,30 @@ impl DepthPreferredHashTable {
     pub fn new(size: usize) -> Self {
         DepthPreferredHashTable {
             mod_base: (size - 1) as u64,
-            table: vec![TableEntry::empty(); size],
+            table: (0..size).map(|_| TableEntry::empty()).collect(),
         }
     }
 
-    pub fn get(&self, key: u64, player: u8, depth: u8, cas_rights: u8, enp_sqr: usize) -> LookupResult {
-        let entry = &self.table[(key & self.mod_base) as usize];
-
-        if entry.key == key && entry.player == player && entry.cas_rights == cas_rights && entry.enp_sqr == enp_sqr as u8 {
-            if entry.depth >= depth {
-                LookupResult::Match(entry.flag, entry.score, entry.mov)
-            } else {
-                LookupResult::MovOnly(entry.mov)
-            }
-        } else {
-            LookupResult::NoMatch
-        }
+    pub fn get(&self, key: u64, depth: u8) -> LookupResult {
+        lookup(&self.table[(key & self.mod_base) as usize], key, depth)
     }
 
-    pub fn set(&mut self, key: u64, player: u8, depth: u8, cas_rights: u8, enp_sqr: usize, flag: u8, score: i32, mov: u32) -> bool {
+    pub fn set(&self, key: u64, depth: u8, flag: u8, score: i32, mov: u32) -> bool {
         let entry = &self.table[(key & self.mod_base) as usize];
 
-        if depth >= entry.depth || key != entry.key {
-            self.table[(key & self.mod_base) as usize] = TableEntry {
-                key,
-                player,
-                depth,
-                cas_rights,
-                enp_sqr: enp_sqr as u8,
-                flag,
-                score, 
-                mov,
-            };
+        let (existing_key, existing_data) = entry.load();
+        let (existing_depth, _, _, _) = decode_entry_data(existing_data);
 
+        if depth >= existing_depth || key != existing_key {
+            entry.store(key, encode_entry_data(depth, flag, score, mov));
             return true
         }
 
         false
     }
 
-    pub fn clear(&mut self) {
-        self.table = vec![TableEntry::empty(); self.mod_base as usize + 1];
+    pub fn clear(&self) {
+        self.table.iter().for_each(TableEntry::clear);
     }
 }
 
@@ -102,38 +120,152 @@ impl AlwaysReplaceHashTable {
     pub fn new(size: usize) -> Self {
         AlwaysReplaceHashTable {
             mod_base: (size - 1) as u64,
-            table: vec![TableEntry::empty(); size],
+            table: (0..size).map(|_| TableEntry::empty()).collect(),
         }
     }
 
-    pub fn get(&self, key: u64, player: u8, depth: u8, cas_rights: u8, enp_sqr: usize) -> LookupResult {
-        let entry = &self.table[(key & self.mod_base) as usize];
+    pub fn get(&self, key: u64, depth: u8) -> LookupResult {
+        lookup(&self.table[(key & self.mod_base) as usize], key, depth)
+    }
+
+    pub fn set(&self, key: u64, depth: u8, flag: u8, score: i32, mov: u32) {
+        self.table[(key & self.mod_base) as usize].store(key, encode_entry_data(depth, flag, score, mov));
+    }
+
+    pub fn clear(&self) {
+        self.table.iter().for_each(TableEntry::clear);
+    }
+}
+
+pub struct PerftHashTable {
+    mod_base: u64,
+    table: Vec<(u64, u64)>,
+}
 
-        if entry.key == key && entry.player == player && entry.cas_rights == cas_rights && entry.enp_sqr == enp_sqr as u8 {
-            if entry.depth >= depth {
-                LookupResult::Match(entry.flag, entry.score, entry.mov)
-            } else {
-                LookupResult::MovOnly(entry.mov)
-            }
+impl PerftHashTable {
+    pub fn new(size: usize) -> Self {
+        PerftHashTable {
+            mod_base: (size - 1) as u64,
+            table: vec![(0, 0); size],
+        }
+    }
+
+    pub fn get(&self, key: u64, depth: u8) -> Option<usize> {
+        let (entry_key, data) = self.table[(key & self.mod_base) as usize];
+
+        if entry_key == key && data as u8 == depth {
+            Some((data >> 8) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn set(&mut self, key: u64, depth: u8, node_count: usize) {
+        self.table[(key & self.mod_base) as usize] = (key, (node_count as u64) << 8 | depth as u64);
+    }
+}
+
+/// Per-thread cache of pawn structure evaluation; keeps count of its probes to report the hit rate.
+pub struct PawnHashTable {
+    mod_base: u64,
+    table: Vec<(u64, PawnEntry)>,
+    probe_count: u64,
+    hit_count: u64,
+}
+
+impl PawnHashTable {
+    pub fn new(size: usize) -> Self {
+        PawnHashTable {
+            mod_base: (size - 1) as u64,
+            table: vec![(0, PawnEntry::empty()); size],
+            probe_count: 0,
+            hit_count: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: u64) -> Option<PawnEntry> {
+        let (entry_key, pawn_entry) = self.table[(key & self.mod_base) as usize];
+
+        self.probe_count += 1;
+
+        if entry_key == key {
+            self.hit_count += 1;
+            Some(pawn_entry)
         } else {
-            LookupResult::NoMatch
+            None
         }
     }
 
-    pub fn set(&mut self, key: u64, player: u8, depth: u8, cas_rights: u8, enp_sqr: usize, flag: u8, score: i32, mov: u32) {
-        self.table[(key & self.mod_base) as usize] = TableEntry {
-            key,
-            player,
-            depth,
-            cas_rights,
-            enp_sqr: enp_sqr as u8,
-            flag,
-            score, 
-            mov,
-        };
+    pub fn set(&mut self, key: u64, pawn_entry: PawnEntry) {
+        self.table[(key & self.mod_base) as usize] = (key, pawn_entry);
+    }
+
+    /// Returns the number of probes and the number of hits since the statistics were last reset.
+    pub fn get_stats(&self) -> (u64, u64) {
+        (self.probe_count, self.hit_count)
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.probe_count = 0;
+        self.hit_count = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitboard::BitMask,
+        def,
+        eval,
+        eval_params::EvalParams,
+        prng::XorshiftPrng,
+        state::State,
+    };
+
+    #[test]
+    fn test_hash_table_entry() {
+        let depth_preferred_hash_table = DepthPreferredHashTable::new(1024);
+
+        assert!(depth_preferred_hash_table.set(12345, 6, HASH_TYPE_BETA, -19990, 0b11000000_00000010_00011000_00001100));
+        assert_eq!(LookupResult::Match(HASH_TYPE_BETA, -19990, 0b11000000_00000010_00011000_00001100), depth_preferred_hash_table.get(12345, 5));
+        assert_eq!(LookupResult::MovOnly(0b11000000_00000010_00011000_00001100), depth_preferred_hash_table.get(12345, 7));
+        assert_eq!(LookupResult::NoMatch, depth_preferred_hash_table.get(12345 + 1024, 5));
+
+        assert!(!depth_preferred_hash_table.set(12345, 5, HASH_TYPE_EXACT, 0, 0));
+        assert!(depth_preferred_hash_table.set(12345 + 1024, 1, HASH_TYPE_EXACT, 0, 0));
+        assert_eq!(LookupResult::NoMatch, depth_preferred_hash_table.get(12345, 1));
+
+        depth_preferred_hash_table.clear();
+        assert_eq!(LookupResult::NoMatch, depth_preferred_hash_table.get(12345 + 1024, 1));
+    }
+
+    #[test]
+    fn test_perft_hash_table_entry() {
+        let mut perft_hash_table = PerftHashTable::new(1024);
+
+        perft_hash_table.set(12345, 4, 197281);
+        assert_eq!(Some(197281), perft_hash_table.get(12345, 4));
+        assert_eq!(None, perft_hash_table.get(12345, 3));
+        assert_eq!(None, perft_hash_table.get(12345 + 1024, 4));
     }
 
-    pub fn clear(&mut self) {
-        self.table = vec![TableEntry::empty(); self.mod_base as usize + 1];
+    #[test]
+    fn test_pawn_hash_table_entry() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let state = State::new("4k3/pp3p2/8/3P4/8/8/PP6/4K3 w - - 0 1", &zob_keys, &bitmask);
+        let pawn_entry = eval::extract_pawn_features(&state, &EvalParams::default());
+
+        let mut pawn_hash_table = PawnHashTable::new(1024);
+
+        assert_eq!(None, pawn_hash_table.get(state.pawn_key));
+        pawn_hash_table.set(state.pawn_key, pawn_entry);
+        assert_eq!(Some(pawn_entry), pawn_hash_table.get(state.pawn_key));
+        assert_eq!(None, pawn_hash_table.get(state.pawn_key + 1024));
+        assert_eq!((3, 1), pawn_hash_table.get_stats());
+
+        pawn_hash_table.reset_stats();
+        assert_eq!((0, 0), pawn_hash_table.get_stats());
     }
 }
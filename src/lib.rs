@@ -0,0 +1,63 @@
+/*
+ * Copyright (C) 2020 Zixiao Han
+ */
+
+//! FoxSEE chess engine as a library.
+//!
+//! A position is a [`State`] built from a FEN string, backed by a shared Zobrist key table and a
+//! shared [`BitMask`]. Moves are encoded as `u32` (see [`util::encode_u32_mov`]).
+//!
+//! ```
+//! use foxsee::{def, eval, eval_params::EvalParams, material::MaterialEntry, mov_table, util, BitMask, SearchEngine, State, TimeCapacity, XorshiftPrng};
+//!
+//! let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+//! let bitmask = BitMask::new();
+//! let mut state = State::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &zob_keys, &bitmask);
+//!
+//! let legal_mov_list = mov_table::gen_legal_mov_list(&state);
+//! assert_eq!(20, legal_mov_list.len());
+//!
+//! let (from, to, tp, promo) = util::decode_u32_mov(legal_mov_list[0]);
+//! state.do_mov(from, to, tp, promo);
+//! state.undo_mov(from, to, tp);
+//!
+//! let eval_params = EvalParams::default();
+//! let material_entry = MaterialEntry::new(state.material_key, &eval_params);
+//! let pawn_entry = eval::extract_pawn_features(&state, &eval_params);
+//! let (material_score, _is_draw) = eval::eval_materials(&state, &material_entry);
+//! let _score = eval::eval_state(&state, &eval_params, &material_entry, &pawn_entry, material_score);
+//!
+//! let mut search_engine = SearchEngine::new(def::DEFAULT_HASH_SIZE_UNIT);
+//! let time_capacity = TimeCapacity { main_time_millis: 1000, extra_time_millis: 0 };
+//! let best_mov = search_engine.search(&mut state, time_capacity, 2);
+//! assert!(legal_mov_list.contains(&best_mov));
+//! ```
+
+pub(crate) mod bitboard;
+pub(crate) mod controller;
+pub mod def;
+pub(crate) mod endgame;
+pub mod eval;
+pub mod eval_params;
+pub mod material;
+pub mod mov_table;
+pub(crate) mod perft;
+pub mod pgn;
+pub(crate) mod polyglot;
+pub(crate) mod prng;
+pub mod san;
+pub(crate) mod search;
+pub(crate) mod state;
+pub(crate) mod syzygy;
+pub(crate) mod time_control;
+pub(crate) mod uci;
+pub mod util;
+
+mod hashtable;
+
+pub use bitboard::BitMask;
+pub use controller::EngineController;
+pub use prng::XorshiftPrng;
+pub use search::{SearchControl, SearchEngine, SearchLimits};
+pub use state::{FenError, State};
+pub use time_control::TimeCapacity;
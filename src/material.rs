@@ -0,0 +1,362 @@
+/*
+ * Copyright (C) 2020 Zixiao Han
+ */
+
+//! Material signatures: piece-count keys, imbalance bonuses and endgame scale factors.
+
+use crate::{
+    bitboard::BitBoard,
+    def,
+    endgame::Endgame,
+    eval_params::EvalParams,
+};
+
+pub static SCALE_FACTOR_NORMAL: i32 = 64;
+pub static SCALE_FACTOR_DRAW: i32 = 0;
+
+static OPPOSITE_BISHOP_SCALE_FACTOR: i32 = 32;
+static PAWNLESS_SCALE_FACTOR: i32 = 14;
+static PAWNLESS_MINOR_DEFENDER_SCALE_FACTOR: i32 = 4;
+
+static ENDGAME_P_BONUS_VAL: i32 = 20;
+static ENDGAME_R_BONUS_VAL: i32 = 50;
+
+static BISHOP_PAIR_VAL: i32 = 30;
+
+pub static TOTAL_PHASE: i32 = 96;
+static Q_PHASE_WEIGHT: i32 = 16;
+static R_PHASE_WEIGHT: i32 = 8;
+static B_PHASE_WEIGHT: i32 = 4;
+static N_PHASE_WEIGHT: i32 = 4;
+
+static KEY_COUNT_BITS: u32 = 4;
+static KEY_COUNT_MASK: u64 = 0b1111;
+
+static TABLE_INDEX_MULTIPLIER: u64 = 0x9E3779B97F4A7C15;
+
+/// Bit offset of the 4-bit count of `piece` in a material key.
+#[inline]
+fn get_key_shift(piece: u8) -> u32 {
+    let shift_index = match piece {
+        def::WP => 0,
+        def::WN => 1,
+        def::WB => 2,
+        def::WR => 3,
+        def::WQ => 4,
+        def::BP => 5,
+        def::BN => 6,
+        def::BB => 7,
+        def::BR => 8,
+        def::BQ => 9,
+        _ => panic!("invalid material piece {}", piece),
+    };
+
+    shift_index * KEY_COUNT_BITS
+}
+
+/// Returns the amount a material key changes by when `piece` is added to the board; kings are not counted.
+#[inline]
+pub fn get_key_unit(piece: u8) -> u64 {
+    if def::is_k(piece) || piece == 0 {
+        return 0
+    }
+
+    1 << get_key_shift(piece)
+}
+
+#[inline]
+pub fn get_piece_count(material_key: u64, piece: u8) -> i32 {
+    (material_key >> get_key_shift(piece) & KEY_COUNT_MASK) as i32
+}
+
+#[inline]
+pub fn get_phase_weight(piece: u8) -> i32 {
+    match piece {
+        def::WQ | def::BQ => Q_PHASE_WEIGHT,
+        def::WR | def::BR => R_PHASE_WEIGHT,
+        def::WB | def::BB => B_PHASE_WEIGHT,
+        def::WN | def::BN => N_PHASE_WEIGHT,
+        _ => 0,
+    }
+}
+
+/// Game phase from `TOTAL_PHASE` with all the pieces on the board down to 0 with only kings and pawns.
+pub fn get_phase(material_key: u64) -> i32 {
+    let count = |piece| get_piece_count(material_key, piece);
+
+    let phase = (count(def::WQ) + count(def::BQ)) * Q_PHASE_WEIGHT
+    + (count(def::WR) + count(def::BR)) * R_PHASE_WEIGHT
+    + (count(def::WB) + count(def::BB)) * B_PHASE_WEIGHT
+    + (count(def::WN) + count(def::BN)) * N_PHASE_WEIGHT;
+
+    phase.min(TOTAL_PHASE)
+}
+
+/// Everything the evaluation derives from the piece counts alone; scores are from the point of view of white.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct MaterialEntry {
+    key: u64,
+    pub imbalance: i32,
+    pub phase: i32,
+    pub is_draw: bool,
+    pub w_scale_factor: i32,
+    pub b_scale_factor: i32,
+    pub is_bishop_ending: bool,
+    pub endgame: Option<(Endgame, u8)>,
+}
+
+impl MaterialEntry {
+    pub fn new(material_key: u64, eval_params: &EvalParams) -> Self {
+        let count = |piece| get_piece_count(material_key, piece);
+
+        let (wp, wn, wb, wr, wq) = (count(def::WP), count(def::WN), count(def::WB), count(def::WR), count(def::WQ));
+        let (bp, bn, bb, br, bq) = (count(def::BP), count(def::BN), count(def::BB), count(def::BR), count(def::BQ));
+
+        let w_piece_val = wq * eval_params.q_val + wr * eval_params.r_val + wb * eval_params.b_val + wn * eval_params.n_val;
+        let b_piece_val = bq * eval_params.q_val + br * eval_params.r_val + bb * eval_params.b_val + bn * eval_params.n_val;
+
+        let phase = get_phase(material_key);
+
+        let mut endgame_material_score = (wp - bp) * ENDGAME_P_BONUS_VAL + (wr - br) * ENDGAME_R_BONUS_VAL;
+
+        if wb > 1 {
+            endgame_material_score += BISHOP_PAIR_VAL;
+        }
+
+        if bb > 1 {
+            endgame_material_score -= BISHOP_PAIR_VAL;
+        }
+
+        let mut is_draw = false;
+
+        if wp + bp + wr + br + wq + bq == 0 {
+            is_draw = (wb + wn < 2 && bb + bn < 2)
+            || (wb + wn == 0 && bb == 0 && bn < 3)
+            || (bb + bn == 0 && wb == 0 && wn < 3);
+        }
+
+        MaterialEntry {
+            key: material_key,
+            imbalance: endgame_material_score * (TOTAL_PHASE - phase) / TOTAL_PHASE,
+            phase,
+            is_draw,
+            w_scale_factor: get_pawnless_scale_factor(eval_params, wp, w_piece_val, b_piece_val),
+            b_scale_factor: get_pawnless_scale_factor(eval_params, bp, b_piece_val, w_piece_val),
+            is_bishop_ending: wn + bn + wr + br + wq + bq == 0 && wb == 1 && bb == 1,
+            endgame: get_endgame([wp, wn, wb, wr, wq], [bp, bn, bb, br, bq]),
+        }
+    }
+
+    /// Returns the scale factor for the side that `score` (from the point of view of white) favours.
+    /// Bishops of opposite colors need the bishop squares, so they are checked against `bitboard` here.
+    pub fn get_scale_factor(&self, bitboard: &BitBoard, score: i32) -> i32 {
+        let scale_factor = if score >= 0 {
+            self.w_scale_factor
+        } else {
+            self.b_scale_factor
+        };
+
+        if self.is_bishop_ending && (bitboard.w_bishop & def::DARK_SQR_MASK == 0) != (bitboard.b_bishop & def::DARK_SQR_MASK == 0) {
+            return scale_factor.min(OPPOSITE_BISHOP_SCALE_FACTOR)
+        }
+
+        scale_factor
+    }
+}
+
+/// Matches the piece counts (pawns, knights, bishops, rooks, queens) of each side against the endgames
+/// with a dedicated evaluator, returning the endgame and the side with the extra material.
+fn get_endgame(w_counts: [i32; 5], b_counts: [i32; 5]) -> Option<(Endgame, u8)> {
+    for &(strong_player, strong_counts, weak_counts) in [(def::PLAYER_W, w_counts, b_counts), (def::PLAYER_B, b_counts, w_counts)].iter() {
+        let [sp, sn, sb, sr, sq] = strong_counts;
+        let [wp, wn, wb, wr, wq] = weak_counts;
+
+        if wp + wn + wb + wr + wq == 0 {
+            if sp + sn + sb + sr + sq == 0 {
+                return None
+            }
+
+            if sp == 1 && sn + sb + sr + sq == 0 {
+                return Some((Endgame::Kpk, strong_player))
+            }
+
+            if sp + sr + sq == 0 && sb == 1 && sn == 1 {
+                return Some((Endgame::Kbnk, strong_player))
+            }
+
+            if sr + sq != 0 {
+                return Some((Endgame::Kxk, strong_player))
+            }
+
+            if sb != 0 && sp != 0 && sn == 0 {
+                return Some((Endgame::Kbpsk, strong_player))
+            }
+        }
+
+        if wp == 1 && wn + wb + wr + wq == 0 {
+            if sr == 1 && sp + sn + sb + sq == 0 {
+                return Some((Endgame::Krkp, strong_player))
+            }
+
+            if sq == 1 && sp + sn + sb + sr == 0 {
+                return Some((Endgame::Kqkp, strong_player))
+            }
+        }
+    }
+
+    None
+}
+
+/// A side without pawns needs more than a minor piece's worth of extra material to win.
+fn get_pawnless_scale_factor(eval_params: &EvalParams, pawn_count: i32, piece_val: i32, opponent_piece_val: i32) -> i32 {
+    if pawn_count > 0 || piece_val - opponent_piece_val > eval_params.b_val {
+        return SCALE_FACTOR_NORMAL
+    }
+
+    if piece_val < eval_params.r_val {
+        SCALE_FACTOR_DRAW
+    } else if opponent_piece_val <= eval_params.b_val {
+        PAWNLESS_MINOR_DEFENDER_SCALE_FACTOR
+    } else {
+        PAWNLESS_SCALE_FACTOR
+    }
+}
+
+/// Cache of material entries indexed by material key; each search thread keeps its own.
+pub struct MaterialTable {
+    mod_base: u64,
+    table: Vec<MaterialEntry>,
+}
+
+impl MaterialTable {
+    pub fn new(size: usize) -> Self {
+        MaterialTable {
+            mod_base: (size - 1) as u64,
+            table: vec![MaterialEntry::new(0, &EvalParams::default()); size],
+        }
+    }
+
+    /// Entries are not tagged with the parameters they were computed with, so the table has to be
+    /// recreated when `eval_params` changes.
+    pub fn get(&mut self, material_key: u64, eval_params: &EvalParams) -> MaterialEntry {
+        let index = (material_key.wrapping_mul(TABLE_INDEX_MULTIPLIER) >> 32 & self.mod_base) as usize;
+        let entry = &mut self.table[index];
+
+        if entry.key != material_key {
+            *entry = MaterialEntry::new(material_key, eval_params);
+        }
+
+        *entry
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitboard::BitMask,
+        prng::XorshiftPrng,
+        state::State,
+    };
+
+    #[test]
+    fn test_material_key() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let state = State::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &zob_keys, &bitmask);
+        assert_eq!(8, get_piece_count(state.material_key, def::WP));
+        assert_eq!(2, get_piece_count(state.material_key, def::BN));
+        assert_eq!(1, get_piece_count(state.material_key, def::BQ));
+        assert_eq!(TOTAL_PHASE, get_phase(state.material_key));
+
+        let state = State::new("4k3/8/8/8/8/8/8/QQQQKQQQ w - - 0 1", &zob_keys, &bitmask);
+        assert_eq!(7, get_piece_count(state.material_key, def::WQ));
+        assert_eq!(0, get_piece_count(state.material_key, def::BQ));
+        assert_eq!(TOTAL_PHASE, get_phase(state.material_key));
+
+        assert_eq!(0, get_key_unit(def::WK));
+        assert_eq!(get_key_unit(def::BP) << KEY_COUNT_BITS, get_key_unit(def::BN));
+    }
+
+    #[test]
+    fn test_material_entry() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let entry = |fen: &str| MaterialEntry::new(State::new(fen, &zob_keys, &bitmask).material_key, &EvalParams::default());
+
+        let start_entry = entry("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1");
+        assert_eq!(0, start_entry.imbalance);
+        assert!(!start_entry.is_draw);
+        assert_eq!(SCALE_FACTOR_NORMAL, start_entry.w_scale_factor);
+        assert_eq!(SCALE_FACTOR_NORMAL, start_entry.b_scale_factor);
+
+        assert!(entry("4k3/8/8/8/8/8/8/2B1K3 w - - 0 1").is_draw);
+        assert!(entry("4k3/8/8/8/8/8/8/1NN1K3 w - - 0 1").is_draw);
+        assert!(entry("4kb2/8/8/8/8/8/8/2B1K3 w - - 0 1").is_draw);
+        assert!(!entry("4k3/8/8/8/8/8/8/1BN1K3 w - - 0 1").is_draw);
+        assert!(!entry("4k3/8/8/8/8/8/8/2BBK3 w - - 0 1").is_draw);
+
+        assert!(entry("4k3/8/8/8/8/8/8/R1BBK3 w - - 0 1").imbalance > entry("4k3/8/8/8/8/8/8/R1BNK3 w - - 0 1").imbalance);
+
+        assert_eq!(SCALE_FACTOR_DRAW, entry("4k3/4p3/8/8/8/8/8/2N1K3 w - - 0 1").w_scale_factor);
+        assert_eq!(SCALE_FACTOR_NORMAL, entry("4k3/4p3/8/8/8/8/8/2N1K3 w - - 0 1").b_scale_factor);
+        assert_eq!(PAWNLESS_SCALE_FACTOR, entry("3rk3/8/8/8/8/8/8/2NRK3 w - - 0 1").w_scale_factor);
+        assert_eq!(PAWNLESS_MINOR_DEFENDER_SCALE_FACTOR, entry("3bk3/8/8/8/8/8/8/3RK3 w - - 0 1").w_scale_factor);
+        assert_eq!(SCALE_FACTOR_NORMAL, entry("3rk3/8/8/8/8/8/8/3QK3 w - - 0 1").w_scale_factor);
+        assert_eq!(SCALE_FACTOR_NORMAL, entry("3rk3/8/8/8/8/8/P7/2NRK3 w - - 0 1").w_scale_factor);
+    }
+
+    #[test]
+    fn test_material_entry_endgame() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let endgame = |fen: &str| MaterialEntry::new(State::new(fen, &zob_keys, &bitmask).material_key, &EvalParams::default()).endgame;
+
+        assert_eq!(Some((Endgame::Kpk, def::PLAYER_W)), endgame("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        assert_eq!(Some((Endgame::Kbnk, def::PLAYER_B)), endgame("3nbk2/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(Some((Endgame::Kxk, def::PLAYER_W)), endgame("4k3/8/8/8/8/8/PP6/R3K3 w - - 0 1"));
+        assert_eq!(Some((Endgame::Krkp, def::PLAYER_W)), endgame("4k3/4p3/8/8/8/8/8/R3K3 w - - 0 1"));
+        assert_eq!(Some((Endgame::Kqkp, def::PLAYER_B)), endgame("q3k3/8/8/8/8/8/4P3/4K3 w - - 0 1"));
+        assert_eq!(Some((Endgame::Kbpsk, def::PLAYER_W)), endgame("4k3/8/8/8/8/8/P7/2B1K3 w - - 0 1"));
+
+        assert_eq!(None, endgame("4k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(None, endgame("4k3/8/8/8/8/8/4PP2/4K3 w - - 0 1"));
+        assert_eq!(None, endgame("4k3/4p3/8/8/8/8/8/R2RK3 w - - 0 1"));
+        assert_eq!(None, endgame("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"));
+    }
+
+    #[test]
+    fn test_opposite_bishop_scale_factor() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let scale_factor = |fen: &str| {
+            let state = State::new(fen, &zob_keys, &bitmask);
+            let entry = MaterialEntry::new(state.material_key, &state.eval_params);
+            assert!(entry.is_bishop_ending);
+
+            entry.get_scale_factor(&state.bitboard, state.material_score)
+        };
+
+        assert_eq!(OPPOSITE_BISHOP_SCALE_FACTOR, scale_factor("4kb2/pp6/8/8/8/8/PPP5/3BK3 w - - 0 1"));
+        assert_eq!(SCALE_FACTOR_NORMAL, scale_factor("4k1b1/pp6/8/8/8/8/PPP5/3BK3 w - - 0 1"));
+        assert_eq!(OPPOSITE_BISHOP_SCALE_FACTOR, scale_factor("4kb2/ppp5/8/8/8/8/PP6/3BK3 w - - 0 1"));
+    }
+
+    #[test]
+    fn test_material_table() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let mut material_table = MaterialTable::new(64);
+
+        for fen in ["4k3/8/8/8/8/8/8/4K3 w - - 0 1", "r3k3/p7/8/8/8/8/P7/R3K3 w - - 0 1", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"] {
+            let state = State::new(fen, &zob_keys, &bitmask);
+            assert_eq!(MaterialEntry::new(state.material_key, &state.eval_params), material_table.get(state.material_key, &state.eval_params));
+            assert_eq!(MaterialEntry::new(state.material_key, &state.eval_params), material_table.get(state.material_key, &state.eval_params));
+        }
+    }
+}
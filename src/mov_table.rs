@@ -3,27 +3,12 @@
  */
 
 use crate::{
+    bitboard::BitMask,
     def,
     state::State,
-    util::{self, get_lowest_index, get_highest_index},
+    util::{self, get_lowest_index},
 };
 
-static CAS_WK_OCCUPY_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_10010000;
-static CAS_WK_R_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_10000000;
-static CAS_WK_EMPTY_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_01100000;
-
-static CAS_WQ_OCCUPY_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00010001;
-static CAS_WQ_R_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00000001;
-static CAS_WQ_EMPTY_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00001110;
-
-static CAS_BK_OCCUPY_MASK: u64 = 0b10010000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-static CAS_BK_R_MASK: u64 = 0b10000000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-static CAS_BK_EMPTY_MASK: u64 = 0b01100000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-
-static CAS_BQ_OCCUPY_MASK: u64 = 0b00010001_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-static CAS_BQ_R_MASK: u64 = 0b00000001_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-static CAS_BQ_EMPTY_MASK: u64 = 0b00001110_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-
 static ENP_SQRS_MASK: u64 = 0b00000000_00000000_11111111_00000000_00000000_11111111_00000000_00000000;
 
 pub fn gen_reg_mov_list(state: &State, mov_list: &mut [u32; def::MAX_MOV_COUNT]) {
@@ -167,37 +152,7 @@ pub fn gen_reg_mov_list(state: &State, mov_list: &mut [u32; def::MAX_MOV_COUNT])
                 }
             }
         } else if def::is_b(moving_piece) {
-            let mut mov_mask = 0;
-
-            let up_left_attack_mask = bitmask.up_left_attack_masks[from_index];
-            mov_mask ^= up_left_attack_mask;
-            if up_left_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_left_attack_masks[lowest_blocker_index];
-            }
-
-            let up_right_attack_mask = bitmask.up_right_attack_masks[from_index];
-            mov_mask ^= up_right_attack_mask;
-            if up_right_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_right_attack_masks[lowest_blocker_index];
-            }
-
-            let down_left_attack_mask = bitmask.down_left_attack_masks[from_index];
-            mov_mask ^= down_left_attack_mask;
-            if down_left_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_left_attack_masks[highest_blocker_index];
-            }
-
-            let down_right_attack_mask = bitmask.down_right_attack_masks[from_index];
-            mov_mask ^= down_right_attack_mask;
-            if down_right_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_right_attack_masks[highest_blocker_index];
-            }
-
-            mov_mask &= !self_mask;
+            let mut mov_mask = bitmask.get_b_attack_mask(from_index, occupy_mask) & !self_mask;
 
             if mov_mask != 0 {
                 let start_index = get_lowest_index(mov_mask);
@@ -214,37 +169,7 @@ pub fn gen_reg_mov_list(state: &State, mov_list: &mut [u32; def::MAX_MOV_COUNT])
                 }
             }
         } else if def::is_r(moving_piece) {
-            let mut mov_mask = 0;
-
-            let up_attack_mask = bitmask.up_attack_masks[from_index];
-            mov_mask ^= up_attack_mask;
-            if up_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_attack_masks[lowest_blocker_index];
-            }
-
-            let right_attack_mask = bitmask.right_attack_masks[from_index];
-            mov_mask ^= right_attack_mask;
-            if right_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.right_attack_masks[lowest_blocker_index];
-            }
-
-            let down_attack_mask = bitmask.down_attack_masks[from_index];
-            mov_mask ^= down_attack_mask;
-            if down_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_attack_masks[highest_blocker_index];
-            }
-
-            let left_attack_mask = bitmask.left_attack_masks[from_index];
-            mov_mask ^= left_attack_mask;
-            if left_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.left_attack_masks[highest_blocker_index];
-            }
-
-            mov_mask &= !self_mask;
+            let mut mov_mask = bitmask.get_r_attack_mask(from_index, occupy_mask) & !self_mask;
 
             if mov_mask != 0 {
                 let start_index = get_lowest_index(mov_mask);
@@ -261,65 +186,7 @@ pub fn gen_reg_mov_list(state: &State, mov_list: &mut [u32; def::MAX_MOV_COUNT])
                 }
             }
         } else if def::is_q(moving_piece) {
-            let mut mov_mask = 0;
-
-            let up_left_attack_mask = bitmask.up_left_attack_masks[from_index];
-            mov_mask ^= up_left_attack_mask;
-            if up_left_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_left_attack_masks[lowest_blocker_index];
-            }
-
-            let up_right_attack_mask = bitmask.up_right_attack_masks[from_index];
-            mov_mask ^= up_right_attack_mask;
-            if up_right_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_right_attack_masks[lowest_blocker_index];
-            }
-
-            let down_left_attack_mask = bitmask.down_left_attack_masks[from_index];
-            mov_mask ^= down_left_attack_mask;
-            if down_left_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_left_attack_masks[highest_blocker_index];
-            }
-
-            let down_right_attack_mask = bitmask.down_right_attack_masks[from_index];
-            mov_mask ^= down_right_attack_mask;
-            if down_right_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_right_attack_masks[highest_blocker_index];
-            }
-
-            let up_attack_mask = bitmask.up_attack_masks[from_index];
-            mov_mask ^= up_attack_mask;
-            if up_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_attack_masks[lowest_blocker_index];
-            }
-
-            let right_attack_mask = bitmask.right_attack_masks[from_index];
-            mov_mask ^= right_attack_mask;
-            if right_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.right_attack_masks[lowest_blocker_index];
-            }
-
-            let down_attack_mask = bitmask.down_attack_masks[from_index];
-            mov_mask ^= down_attack_mask;
-            if down_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_attack_masks[highest_blocker_index];
-            }
-
-            let left_attack_mask = bitmask.left_attack_masks[from_index];
-            mov_mask ^= left_attack_mask;
-            if left_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.left_attack_masks[highest_blocker_index];
-            }
-
-            mov_mask &= !self_mask;
+            let mut mov_mask = bitmask.get_q_attack_mask(from_index, occupy_mask) & !self_mask;
 
             if mov_mask != 0 {
                 let start_index = get_lowest_index(mov_mask);
@@ -355,55 +222,252 @@ pub fn gen_reg_mov_list(state: &State, mov_list: &mut [u32; def::MAX_MOV_COUNT])
         }
     }
 
-    let cas_rights = state.cas_rights;
+    gen_cas_mov_list(state, &mut add_mov);
+}
+
+/// Castling is driven by `State::cas_rook_indexes`, so the same rules cover both standard chess and Chess960:
+/// every square the king and rook pass over must be empty apart from the two of them, and the king may not
+/// pass through or land on an attacked square.
+fn gen_cas_mov_list(state: &State, add_mov: &mut impl FnMut(usize, usize, u8, u8)) {
+    let player = state.player;
     let bitboard = state.bitboard;
-    let all_mask = bitboard.w_all | bitboard.b_all;
+    let index_masks = &state.bitmask.index_masks;
+    let occupy_mask = bitboard.w_all | bitboard.b_all;
 
-    if state.player == def::PLAYER_W {
-        if cas_rights & 0b1000 != 0 {
-            if bitboard.w_all & CAS_WK_OCCUPY_MASK == CAS_WK_OCCUPY_MASK
-            && bitboard.w_rook & CAS_WK_R_MASK == CAS_WK_R_MASK
-            && all_mask & CAS_WK_EMPTY_MASK == 0
-            && !is_under_attack(state, def::CAS_SQUARE_WK, def::PLAYER_W)
-            && !is_under_attack(state, def::CAS_SQUARE_WK - 1, def::PLAYER_W)
-            && !is_under_attack(state, def::CAS_SQUARE_WK - 2, def::PLAYER_W) {
-                add_mov(def::CAS_SQUARE_WK - 2, def::CAS_SQUARE_WK, def::MOV_CAS, 0);
-            }
+    let (king_index, rook, back_rank_index, cas_index_range) = if player == def::PLAYER_W {
+        (state.wk_index, def::WR, 0, 0..2)
+    } else {
+        (state.bk_index, def::BR, 7, 2..4)
+    };
+
+    if king_index / def::DIM_SIZE != back_rank_index {
+        return
+    }
+
+    for cas_index in cas_index_range {
+        if state.cas_rights & def::CAS_RIGHT_BITS[cas_index] == 0 {
+            continue
         }
 
-        if cas_rights & 0b0100 != 0 {
-            if bitboard.w_all & CAS_WQ_OCCUPY_MASK == CAS_WQ_OCCUPY_MASK
-            && bitboard.w_rook & CAS_WQ_R_MASK == CAS_WQ_R_MASK
-            && all_mask & CAS_WQ_EMPTY_MASK == 0
-            && !is_under_attack(state, def::CAS_SQUARE_WQ, def::PLAYER_W)
-            && !is_under_attack(state, def::CAS_SQUARE_WQ + 1, def::PLAYER_W)
-            && !is_under_attack(state, def::CAS_SQUARE_WQ + 2, def::PLAYER_W) {
-                add_mov(def::CAS_SQUARE_WQ + 2, def::CAS_SQUARE_WQ, def::MOV_CAS, 0);
-            }
+        let rook_from = state.cas_rook_indexes[cas_index];
+        if state.squares[rook_from] != rook {
+            continue
         }
+
+        let king_to = def::CAS_KING_SQUARES[cas_index];
+        let rook_to = def::CAS_ROOK_SQUARES[cas_index];
+        let cas_pieces_mask = index_masks[king_index] | index_masks[rook_from];
+
+        let path_mask = (get_rank_span_mask(king_index, king_to) | get_rank_span_mask(rook_from, rook_to)) & !cas_pieces_mask;
+        if occupy_mask & path_mask != 0 {
+            continue
+        }
+
+        if (king_index.min(king_to)..=king_index.max(king_to)).any(|index| is_under_attack(state, index, player)) {
+            continue
+        }
+
+        // in Chess960 the castling rook itself may be shielding the king's destination from a slider on the back rank
+        if get_attacker_mask(state, king_to, player, (occupy_mask ^ cas_pieces_mask) | index_masks[king_to] | index_masks[rook_to]) != 0 {
+            continue
+        }
+
+        add_mov(king_index, king_to, def::MOV_CAS, 0);
+    }
+}
+
+#[inline]
+fn get_rank_span_mask(from: usize, to: usize) -> u64 {
+    (!0u64 >> (63 - from.max(to))) & (!0u64 << from.min(to))
+}
+
+/// Generates only the moves that do not leave the side to move in check. Pins and check evasions
+/// are resolved from the king's position, so no move has to be made and unmade to be filtered.
+pub fn gen_strict_mov_list(state: &State, mov_list: &mut [u32; def::MAX_MOV_COUNT]) {
+    let player = state.player;
+    let bitboard = state.bitboard;
+    let bitmask = state.bitmask;
+
+    let occupy_mask = bitboard.w_all | bitboard.b_all;
+
+    let mut mov_count = 0;
+
+    let mut add_mov = |from: usize, to: usize, tp: u8, promo: u8| {
+        mov_list[mov_count] = util::encode_u32_mov(from, to, tp, promo);
+        mov_count += 1;
+    };
+
+    let (self_mask, opponent_mask, k_index) = if player == def::PLAYER_W {
+        (bitboard.w_all, bitboard.b_all, state.wk_index)
     } else {
-        if cas_rights & 0b0010 != 0 {
-            if bitboard.b_all & CAS_BK_OCCUPY_MASK == CAS_BK_OCCUPY_MASK
-            && bitboard.b_rook & CAS_BK_R_MASK == CAS_BK_R_MASK
-            && all_mask & CAS_BK_EMPTY_MASK == 0
-            && !is_under_attack(state, def::CAS_SQUARE_BK, def::PLAYER_B)
-            && !is_under_attack(state, def::CAS_SQUARE_BK - 1, def::PLAYER_B)
-            && !is_under_attack(state, def::CAS_SQUARE_BK - 2, def::PLAYER_B) {
-                add_mov(def::CAS_SQUARE_BK - 2, def::CAS_SQUARE_BK, def::MOV_CAS, 0);
+        (bitboard.b_all, bitboard.w_all, state.bk_index)
+    };
+
+    let (opponent_bq_mask, opponent_rq_mask) = if player == def::PLAYER_W {
+        (bitboard.b_bishop | bitboard.b_queen, bitboard.b_rook | bitboard.b_queen)
+    } else {
+        (bitboard.w_bishop | bitboard.w_queen, bitboard.w_rook | bitboard.w_queen)
+    };
+
+    let k_mask = bitmask.index_masks[k_index];
+
+    let mut k_mov_mask = bitmask.k_attack_masks[k_index] & !self_mask;
+    while k_mov_mask != 0 {
+        let to_index = get_lowest_index(k_mov_mask);
+        k_mov_mask &= k_mov_mask - 1;
+
+        if get_attacker_mask(state, to_index, player, occupy_mask ^ k_mask) == 0 {
+            add_mov(k_index, to_index, def::MOV_REG, 0);
+        }
+    }
+
+    let checker_mask = get_attacker_mask(state, k_index, player, occupy_mask);
+
+    if checker_mask.count_ones() > 1 {
+        return
+    }
+
+    let target_mask = if checker_mask != 0 {
+        checker_mask | get_between_mask(bitmask, k_index, get_lowest_index(checker_mask))
+    } else {
+        !0
+    };
+
+    let mut pin_list = [(0, 0); 8];
+    let mut pin_count = 0;
+
+    let mut pinner_mask = (bitmask.get_b_attack_mask(k_index, opponent_mask) & opponent_bq_mask)
+        | (bitmask.get_r_attack_mask(k_index, opponent_mask) & opponent_rq_mask);
+
+    while pinner_mask != 0 {
+        let pinner_index = get_lowest_index(pinner_mask);
+        pinner_mask &= pinner_mask - 1;
+
+        let between_mask = get_between_mask(bitmask, k_index, pinner_index);
+        let blocker_mask = between_mask & occupy_mask;
+
+        if blocker_mask.count_ones() == 1 && blocker_mask & self_mask != 0 {
+            pin_list[pin_count] = (get_lowest_index(blocker_mask), between_mask | bitmask.index_masks[pinner_index]);
+            pin_count += 1;
+        }
+    }
+
+    let mut piece_mask = self_mask ^ k_mask;
+    while piece_mask != 0 {
+        let from_index = get_lowest_index(piece_mask);
+        piece_mask &= piece_mask - 1;
+
+        let moving_piece = state.squares[from_index];
+
+        let mut allowed_mask = target_mask & !self_mask;
+        for &(pinned_index, pin_ray_mask) in &pin_list[..pin_count] {
+            if pinned_index == from_index {
+                allowed_mask &= pin_ray_mask;
             }
         }
 
-        if cas_rights & 0b0001 != 0 {
-            if bitboard.b_all & CAS_BQ_OCCUPY_MASK == CAS_BQ_OCCUPY_MASK
-            && bitboard.b_rook & CAS_BQ_R_MASK == CAS_BQ_R_MASK
-            && all_mask & CAS_BQ_EMPTY_MASK == 0
-            && !is_under_attack(state, def::CAS_SQUARE_BQ, def::PLAYER_B)
-            && !is_under_attack(state, def::CAS_SQUARE_BQ + 1, def::PLAYER_B)
-            && !is_under_attack(state, def::CAS_SQUARE_BQ + 2, def::PLAYER_B) {
-                add_mov(def::CAS_SQUARE_BQ + 2, def::CAS_SQUARE_BQ, def::MOV_CAS, 0);
+        if def::is_p(moving_piece) {
+            let (mov_mask, init_mov_mask, attack_mask, forward_offset, promo_list) = if player == def::PLAYER_W {
+                (bitmask.wp_mov_masks[from_index], bitmask.wp_init_mov_masks[from_index], bitmask.wp_attack_masks[from_index], 8, [def::WQ, def::WR, def::WB, def::WN])
+            } else {
+                (bitmask.bp_mov_masks[from_index], bitmask.bp_init_mov_masks[from_index], bitmask.bp_attack_masks[from_index], -8, [def::BQ, def::BR, def::BB, def::BN])
+            };
+
+            let mut to_mask = mov_mask & !occupy_mask;
+            if to_mask != 0 && init_mov_mask & !occupy_mask != 0 {
+                to_mask |= init_mov_mask;
             }
+
+            to_mask |= attack_mask & opponent_mask;
+            to_mask &= allowed_mask;
+
+            while to_mask != 0 {
+                let to_index = get_lowest_index(to_mask);
+                to_mask &= to_mask - 1;
+
+                if !(8..=55).contains(&to_index) {
+                    for &promo in &promo_list {
+                        add_mov(from_index, to_index, def::MOV_PROMO, promo);
+                    }
+                } else if to_index as isize - from_index as isize == forward_offset * 2 {
+                    add_mov(from_index, to_index, def::MOV_CR_ENP, 0);
+                } else {
+                    add_mov(from_index, to_index, def::MOV_REG, 0);
+                }
+            }
+
+            let enp_square_index = state.enp_square;
+            if enp_square_index != 0 && attack_mask & bitmask.index_masks[enp_square_index] != 0 {
+                let cap_index = (enp_square_index as isize - forward_offset) as usize;
+                let cap_mask = bitmask.index_masks[cap_index];
+                let enp_occupy_mask = occupy_mask ^ bitmask.index_masks[from_index] ^ cap_mask ^ bitmask.index_masks[enp_square_index];
+
+                if get_attacker_mask(state, k_index, player, enp_occupy_mask) & !cap_mask == 0 {
+                    add_mov(from_index, enp_square_index, def::MOV_ENP, 0);
+                }
+            }
+
+            continue
+        }
+
+        let mut to_mask = if def::is_n(moving_piece) {
+            bitmask.n_attack_masks[from_index]
+        } else if def::is_b(moving_piece) {
+            bitmask.get_b_attack_mask(from_index, occupy_mask)
+        } else if def::is_r(moving_piece) {
+            bitmask.get_r_attack_mask(from_index, occupy_mask)
+        } else {
+            bitmask.get_q_attack_mask(from_index, occupy_mask)
+        } & allowed_mask;
+
+        while to_mask != 0 {
+            let to_index = get_lowest_index(to_mask);
+            to_mask &= to_mask - 1;
+
+            add_mov(from_index, to_index, def::MOV_REG, 0);
+        }
+    }
+
+    if checker_mask == 0 {
+        gen_cas_mov_list(state, &mut add_mov);
+    }
+}
+
+fn get_attacker_mask(state: &State, index: usize, player: u8, occupy_mask: u64) -> u64 {
+    let bitboard = state.bitboard;
+    let bitmask = state.bitmask;
+
+    let b_attack_mask = bitmask.get_b_attack_mask(index, occupy_mask);
+    let r_attack_mask = bitmask.get_r_attack_mask(index, occupy_mask);
+
+    if player == def::PLAYER_W {
+        (bitmask.n_attack_masks[index] & bitboard.b_knight)
+            | (bitmask.wp_attack_masks[index] & bitboard.b_pawn)
+            | (bitmask.k_attack_masks[index] & bitmask.index_masks[state.bk_index])
+            | (b_attack_mask & (bitboard.b_bishop | bitboard.b_queen))
+            | (r_attack_mask & (bitboard.b_rook | bitboard.b_queen))
+    } else {
+        (bitmask.n_attack_masks[index] & bitboard.w_knight)
+            | (bitmask.bp_attack_masks[index] & bitboard.w_pawn)
+            | (bitmask.k_attack_masks[index] & bitmask.index_masks[state.wk_index])
+            | (b_attack_mask & (bitboard.w_bishop | bitboard.w_queen))
+            | (r_attack_mask & (bitboard.w_rook | bitboard.w_queen))
+    }
+}
+
+fn get_between_mask(bitmask: &BitMask, from: usize, to: usize) -> u64 {
+    let to_mask = bitmask.index_masks[to];
+
+    for ray_masks in [
+        &bitmask.up_attack_masks, &bitmask.down_attack_masks, &bitmask.left_attack_masks, &bitmask.right_attack_masks,
+        &bitmask.up_left_attack_masks, &bitmask.up_right_attack_masks, &bitmask.down_left_attack_masks, &bitmask.down_right_attack_masks,
+    ] {
+        if ray_masks[from] & to_mask != 0 {
+            return (ray_masks[from] ^ ray_masks[to]) & !to_mask
         }
     }
+
+    0
 }
 
 pub fn gen_capture_list(state: &State, cap_list: &mut [u32; def::MAX_CAP_COUNT]) {
@@ -514,37 +578,7 @@ pub fn gen_capture_list(state: &State, cap_list: &mut [u32; def::MAX_CAP_COUNT])
                 }
             }
         } else if def::is_b(moving_piece) {
-            let mut mov_mask = 0;
-
-            let up_left_attack_mask = bitmask.up_left_attack_masks[from_index];
-            mov_mask ^= up_left_attack_mask;
-            if up_left_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_left_attack_masks[lowest_blocker_index];
-            }
-
-            let up_right_attack_mask = bitmask.up_right_attack_masks[from_index];
-            mov_mask ^= up_right_attack_mask;
-            if up_right_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_right_attack_masks[lowest_blocker_index];
-            }
-
-            let down_left_attack_mask = bitmask.down_left_attack_masks[from_index];
-            mov_mask ^= down_left_attack_mask;
-            if down_left_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_left_attack_masks[highest_blocker_index];
-            }
-
-            let down_right_attack_mask = bitmask.down_right_attack_masks[from_index];
-            mov_mask ^= down_right_attack_mask;
-            if down_right_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_right_attack_masks[highest_blocker_index];
-            }
-
-            let mut attack_mask = mov_mask & opponent_mask;
+            let mut attack_mask = bitmask.get_b_attack_mask(from_index, occupy_mask) & opponent_mask;
 
             if attack_mask != 0 {
                 let start_index = get_lowest_index(attack_mask);
@@ -561,37 +595,7 @@ pub fn gen_capture_list(state: &State, cap_list: &mut [u32; def::MAX_CAP_COUNT])
                 }
             }
         } else if def::is_r(moving_piece) {
-            let mut mov_mask = 0;
-
-            let up_attack_mask = bitmask.up_attack_masks[from_index];
-            mov_mask ^= up_attack_mask;
-            if up_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_attack_masks[lowest_blocker_index];
-            }
-
-            let right_attack_mask = bitmask.right_attack_masks[from_index];
-            mov_mask ^= right_attack_mask;
-            if right_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.right_attack_masks[lowest_blocker_index];
-            }
-
-            let down_attack_mask = bitmask.down_attack_masks[from_index];
-            mov_mask ^= down_attack_mask;
-            if down_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_attack_masks[highest_blocker_index];
-            }
-
-            let left_attack_mask = bitmask.left_attack_masks[from_index];
-            mov_mask ^= left_attack_mask;
-            if left_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.left_attack_masks[highest_blocker_index];
-            }
-
-            let mut attack_mask = mov_mask & opponent_mask;
+            let mut attack_mask = bitmask.get_r_attack_mask(from_index, occupy_mask) & opponent_mask;
 
             if attack_mask != 0 {
                 let start_index = get_lowest_index(attack_mask);
@@ -608,65 +612,7 @@ pub fn gen_capture_list(state: &State, cap_list: &mut [u32; def::MAX_CAP_COUNT])
                 }
             }
         } else if def::is_q(moving_piece) {
-            let mut mov_mask = 0;
-
-            let up_left_attack_mask = bitmask.up_left_attack_masks[from_index];
-            mov_mask ^= up_left_attack_mask;
-            if up_left_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_left_attack_masks[lowest_blocker_index];
-            }
-
-            let up_right_attack_mask = bitmask.up_right_attack_masks[from_index];
-            mov_mask ^= up_right_attack_mask;
-            if up_right_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_right_attack_masks[lowest_blocker_index];
-            }
-
-            let down_left_attack_mask = bitmask.down_left_attack_masks[from_index];
-            mov_mask ^= down_left_attack_mask;
-            if down_left_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_left_attack_masks[highest_blocker_index];
-            }
-
-            let down_right_attack_mask = bitmask.down_right_attack_masks[from_index];
-            mov_mask ^= down_right_attack_mask;
-            if down_right_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_right_attack_masks[highest_blocker_index];
-            }
-
-            let up_attack_mask = bitmask.up_attack_masks[from_index];
-            mov_mask ^= up_attack_mask;
-            if up_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.up_attack_masks[lowest_blocker_index];
-            }
-
-            let right_attack_mask = bitmask.right_attack_masks[from_index];
-            mov_mask ^= right_attack_mask;
-            if right_attack_mask & occupy_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(right_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.right_attack_masks[lowest_blocker_index];
-            }
-
-            let down_attack_mask = bitmask.down_attack_masks[from_index];
-            mov_mask ^= down_attack_mask;
-            if down_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.down_attack_masks[highest_blocker_index];
-            }
-
-            let left_attack_mask = bitmask.left_attack_masks[from_index];
-            mov_mask ^= left_attack_mask;
-            if left_attack_mask & occupy_mask != 0 {
-                let highest_blocker_index = get_highest_index(left_attack_mask & occupy_mask);
-                mov_mask &= !bitmask.left_attack_masks[highest_blocker_index];
-            }
-
-            let mut attack_mask = mov_mask & opponent_mask;
+            let mut attack_mask = bitmask.get_q_attack_mask(from_index, occupy_mask) & opponent_mask;
 
             if attack_mask != 0 {
                 let start_index = get_lowest_index(attack_mask);
@@ -703,6 +649,14 @@ pub fn gen_capture_list(state: &State, cap_list: &mut [u32; def::MAX_CAP_COUNT])
     }
 }
 
+/// Returns the moves in the current position that do not leave the side to move in check.
+pub fn gen_legal_mov_list(state: &State) -> Vec<u32> {
+    let mut mov_list = [0; def::MAX_MOV_COUNT];
+    gen_strict_mov_list(state, &mut mov_list);
+
+    mov_list.iter().copied().take_while(|mov| *mov != 0).collect()
+}
+
 pub fn is_in_check(state: &State, player: u8) -> bool {
     let k_index = if player == def::PLAYER_W {
         state.wk_index
@@ -713,6 +667,7 @@ pub fn is_in_check(state: &State, player: u8) -> bool {
     is_under_attack(state, k_index, player)
 }
 
+#[allow(clippy::if_same_then_else)]
 pub fn is_under_attack(state: &State, index: usize, player: u8) -> bool {
     let bitboard = state.bitboard;
     let bitmask = state.bitmask;
@@ -751,38 +706,8 @@ pub fn is_under_attack(state: &State, index: usize, player: u8) -> bool {
         bitboard.w_rook | bitboard.w_queen
     };
 
-    if opponent_rq_mask != 0 {
-        let up_attack_mask = bitmask.up_attack_masks[index];
-        if up_attack_mask & opponent_rq_mask != 0 && up_attack_mask & blocker_mask != 0 {
-            let lowest_blocker_index = get_lowest_index(up_attack_mask & blocker_mask);
-            if (up_attack_mask ^ bitmask.up_attack_masks[lowest_blocker_index]) & opponent_rq_mask != 0 {
-                return true
-            }
-        }
-
-        let right_attack_mask = bitmask.right_attack_masks[index];
-        if right_attack_mask & opponent_rq_mask != 0 && right_attack_mask & blocker_mask != 0 {
-            let lowest_blocker_index = get_lowest_index(right_attack_mask & blocker_mask);
-            if (right_attack_mask ^ bitmask.right_attack_masks[lowest_blocker_index]) & opponent_rq_mask != 0 {
-                return true
-            }
-        }
-
-        let down_attack_mask = bitmask.down_attack_masks[index];
-        if down_attack_mask & opponent_rq_mask != 0 && down_attack_mask & blocker_mask != 0 {
-            let highest_blocker_index = get_highest_index(down_attack_mask & blocker_mask);
-            if (down_attack_mask ^ bitmask.down_attack_masks[highest_blocker_index]) & opponent_rq_mask != 0 {
-                return true
-            }
-        }
-
-        let left_attack_mask = bitmask.left_attack_masks[index];
-        if left_attack_mask & opponent_rq_mask != 0 && left_attack_mask & blocker_mask != 0 {
-            let highest_blocker_index = get_highest_index(left_attack_mask & blocker_mask);
-            if (left_attack_mask ^ bitmask.left_attack_masks[highest_blocker_index]) & opponent_rq_mask != 0 {
-                return true
-            }
-        }
+    if opponent_rq_mask & bitmask.get_r_attack_mask(index, blocker_mask) != 0 {
+        return true
     }
 
     let opponent_bq_mask = if player == def::PLAYER_W {
@@ -791,41 +716,7 @@ pub fn is_under_attack(state: &State, index: usize, player: u8) -> bool {
         bitboard.w_bishop | bitboard.w_queen
     };
 
-    if opponent_bq_mask != 0 {
-        let up_left_attack_mask = bitmask.up_left_attack_masks[index];
-        if up_left_attack_mask & opponent_bq_mask != 0 && up_left_attack_mask & blocker_mask != 0 {
-            let lowest_blocker_index = get_lowest_index(up_left_attack_mask & blocker_mask);
-            if (up_left_attack_mask ^ bitmask.up_left_attack_masks[lowest_blocker_index]) & opponent_bq_mask != 0 {
-                return true
-            }
-        }
-
-        let up_right_attack_mask = bitmask.up_right_attack_masks[index];
-        if up_right_attack_mask & opponent_bq_mask != 0 && up_right_attack_mask & blocker_mask != 0 {
-            let lowest_blocker_index = get_lowest_index(up_right_attack_mask & blocker_mask);
-            if (up_right_attack_mask ^ bitmask.up_right_attack_masks[lowest_blocker_index]) & opponent_bq_mask != 0 {
-                return true
-            }
-        }
-
-        let down_left_attack_mask = bitmask.down_left_attack_masks[index];
-        if down_left_attack_mask & opponent_bq_mask != 0 && down_left_attack_mask & blocker_mask != 0 {
-            let highest_blocker_index = get_highest_index(down_left_attack_mask & blocker_mask);
-            if (down_left_attack_mask ^ bitmask.down_left_attack_masks[highest_blocker_index]) & opponent_bq_mask != 0 {
-                return true
-            }
-        }
-
-        let down_right_attack_mask = bitmask.down_right_attack_masks[index];
-        if down_right_attack_mask & opponent_bq_mask != 0 && down_right_attack_mask & blocker_mask != 0 {
-            let highest_blocker_index = get_highest_index(down_right_attack_mask & blocker_mask);
-            if (down_right_attack_mask ^ bitmask.down_right_attack_masks[highest_blocker_index]) & opponent_bq_mask != 0 {
-                return true
-            }
-        }
-    }
-
-    false
+    opponent_bq_mask & bitmask.get_b_attack_mask(index, blocker_mask) != 0
 }
 
 pub fn get_smallest_attacker_index(state: &State, index: usize) -> (u8, u8, u8, usize) {
@@ -864,48 +755,7 @@ pub fn get_smallest_attacker_index(state: &State, index: usize) -> (u8, u8, u8,
             attack_index += 1;
         }
 
-        let bq_mask = bitboard.w_bishop | bitboard.w_queen;
-        let mut bq_attack_mask = 0;
-
-        let mut up_left_attack_mask = bitmask.up_left_attack_masks[index];
-        if up_left_attack_mask != 0 && up_left_attack_mask & bq_mask != 0 {
-            if up_left_attack_mask & all_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_left_attack_mask & all_mask);
-                up_left_attack_mask &= !bitmask.up_left_attack_masks[lowest_blocker_index];
-            }
-
-            bq_attack_mask ^= up_left_attack_mask;
-        }
-
-        let mut up_right_attack_mask = bitmask.up_right_attack_masks[index];
-        if up_right_attack_mask != 0 && up_right_attack_mask & bq_mask != 0 {
-            if up_right_attack_mask & all_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_right_attack_mask & all_mask);
-                up_right_attack_mask &= !bitmask.up_right_attack_masks[lowest_blocker_index];
-            }
-
-            bq_attack_mask ^= up_right_attack_mask;
-        }
-
-        let mut down_left_attack_mask = bitmask.down_left_attack_masks[index];
-        if down_left_attack_mask != 0 && down_left_attack_mask & bq_mask != 0 {
-            if down_left_attack_mask & all_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_left_attack_mask & all_mask);
-                down_left_attack_mask &= !bitmask.down_left_attack_masks[highest_blocker_index];
-            }
-
-            bq_attack_mask ^= down_left_attack_mask;
-        }
-
-        let mut down_right_attack_mask = bitmask.down_right_attack_masks[index];
-        if down_right_attack_mask != 0 && down_right_attack_mask & bq_mask != 0 {
-            if down_right_attack_mask & all_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_right_attack_mask & all_mask);
-                down_right_attack_mask &= !bitmask.down_right_attack_masks[highest_blocker_index];
-            }
-
-            bq_attack_mask ^= down_right_attack_mask;
-        }
+        let bq_attack_mask = bitmask.get_b_attack_mask(index, all_mask);
 
         let mut attack_mask = bq_attack_mask & bitboard.w_bishop;
         let mut attack_index = 0;
@@ -918,48 +768,7 @@ pub fn get_smallest_attacker_index(state: &State, index: usize) -> (u8, u8, u8,
             attack_index += 1;
         }
 
-        let rq_mask = bitboard.w_rook | bitboard.w_queen;
-        let mut rq_attack_mask = 0;
-
-        let mut up_attack_mask = bitmask.up_attack_masks[index];
-        if up_attack_mask != 0 && up_attack_mask & rq_mask != 0 {
-            if up_attack_mask & all_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_attack_mask & all_mask);
-                up_attack_mask &= !bitmask.up_attack_masks[lowest_blocker_index];
-            }
-
-            rq_attack_mask ^= up_attack_mask;
-        }
-
-        let mut right_attack_mask = bitmask.right_attack_masks[index];
-        if right_attack_mask != 0 && right_attack_mask & rq_mask != 0 {
-            if right_attack_mask & all_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(right_attack_mask & all_mask);
-                right_attack_mask &= !bitmask.right_attack_masks[lowest_blocker_index];
-            }
-
-            rq_attack_mask ^= right_attack_mask;
-        }
-
-        let mut down_attack_mask = bitmask.down_attack_masks[index];
-        if down_attack_mask != 0 && down_attack_mask & rq_mask != 0 {
-            if down_attack_mask & all_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_attack_mask & all_mask);
-                down_attack_mask &= !bitmask.down_attack_masks[highest_blocker_index];
-            }
-
-            rq_attack_mask ^= down_attack_mask;
-        }
-
-        let mut left_attack_mask = bitmask.left_attack_masks[index];
-        if left_attack_mask != 0 && left_attack_mask & rq_mask != 0 {
-            if left_attack_mask & all_mask != 0 {
-                let highest_blocker_index = get_highest_index(left_attack_mask & all_mask);
-                left_attack_mask &= !bitmask.left_attack_masks[highest_blocker_index];
-            }
-
-            rq_attack_mask ^= left_attack_mask;
-        }
+        let rq_attack_mask = bitmask.get_r_attack_mask(index, all_mask);
 
         let mut attack_mask = rq_attack_mask & bitboard.w_rook;
         let mut attack_index = 0;
@@ -1017,48 +826,7 @@ pub fn get_smallest_attacker_index(state: &State, index: usize) -> (u8, u8, u8,
             attack_index += 1;
         }
 
-        let bq_mask = bitboard.b_bishop | bitboard.b_queen;
-        let mut bq_attack_mask = 0;
-
-        let mut up_left_attack_mask = bitmask.up_left_attack_masks[index];
-        if up_left_attack_mask != 0 && up_left_attack_mask & bq_mask != 0 {
-            if up_left_attack_mask & all_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_left_attack_mask & all_mask);
-                up_left_attack_mask &= !bitmask.up_left_attack_masks[lowest_blocker_index];
-            }
-
-            bq_attack_mask ^= up_left_attack_mask;
-        }
-
-        let mut up_right_attack_mask = bitmask.up_right_attack_masks[index];
-        if up_right_attack_mask != 0 && up_right_attack_mask & bq_mask != 0 {
-            if up_right_attack_mask & all_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_right_attack_mask & all_mask);
-                up_right_attack_mask &= !bitmask.up_right_attack_masks[lowest_blocker_index];
-            }
-
-            bq_attack_mask ^= up_right_attack_mask;
-        }
-
-        let mut down_left_attack_mask = bitmask.down_left_attack_masks[index];
-        if down_left_attack_mask != 0 && down_left_attack_mask & bq_mask != 0 {
-            if down_left_attack_mask & all_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_left_attack_mask & all_mask);
-                down_left_attack_mask &= !bitmask.down_left_attack_masks[highest_blocker_index];
-            }
-
-            bq_attack_mask ^= down_left_attack_mask;
-        }
-
-        let mut down_right_attack_mask = bitmask.down_right_attack_masks[index];
-        if down_right_attack_mask != 0 && down_right_attack_mask & bq_mask != 0 {
-            if down_right_attack_mask & all_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_right_attack_mask & all_mask);
-                down_right_attack_mask &= !bitmask.down_right_attack_masks[highest_blocker_index];
-            }
-
-            bq_attack_mask ^= down_right_attack_mask;
-        }
+        let bq_attack_mask = bitmask.get_b_attack_mask(index, all_mask);
 
         let mut attack_mask = bq_attack_mask & bitboard.b_bishop;
         let mut attack_index = 0;
@@ -1071,48 +839,7 @@ pub fn get_smallest_attacker_index(state: &State, index: usize) -> (u8, u8, u8,
             attack_index += 1;
         }
 
-        let rq_mask = bitboard.b_rook | bitboard.b_queen;
-        let mut rq_attack_mask = 0;
-
-        let mut up_attack_mask = bitmask.up_attack_masks[index];
-        if up_attack_mask != 0 && up_attack_mask & rq_mask != 0 {
-            if up_attack_mask & all_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(up_attack_mask & all_mask);
-                up_attack_mask &= !bitmask.up_attack_masks[lowest_blocker_index];
-            }
-
-            rq_attack_mask ^= up_attack_mask;
-        }
-
-        let mut right_attack_mask = bitmask.right_attack_masks[index];
-        if right_attack_mask != 0 && right_attack_mask & rq_mask != 0 {
-            if right_attack_mask & all_mask != 0 {
-                let lowest_blocker_index = get_lowest_index(right_attack_mask & all_mask);
-                right_attack_mask &= !bitmask.right_attack_masks[lowest_blocker_index];
-            }
-
-            rq_attack_mask ^= right_attack_mask;
-        }
-
-        let mut down_attack_mask = bitmask.down_attack_masks[index];
-        if down_attack_mask != 0 && down_attack_mask & rq_mask != 0 {
-            if down_attack_mask & all_mask != 0 {
-                let highest_blocker_index = get_highest_index(down_attack_mask & all_mask);
-                down_attack_mask &= !bitmask.down_attack_masks[highest_blocker_index];
-            }
-
-            rq_attack_mask ^= down_attack_mask;
-        }
-
-        let mut left_attack_mask = bitmask.left_attack_masks[index];
-        if left_attack_mask != 0 && left_attack_mask & rq_mask != 0 {
-            if left_attack_mask & all_mask != 0 {
-                let highest_blocker_index = get_highest_index(left_attack_mask & all_mask);
-                left_attack_mask &= !bitmask.left_attack_masks[highest_blocker_index];
-            }
-
-            rq_attack_mask ^= left_attack_mask;
-        }
+        let rq_attack_mask = bitmask.get_r_attack_mask(index, all_mask);
 
         let mut attack_mask = rq_attack_mask & bitboard.b_rook;
         let mut attack_index = 0;
@@ -1157,6 +884,7 @@ mod tests {
         util,
     };
 
+    #[allow(clippy::needless_range_loop, clippy::assertions_on_constants)]
     fn gen_reg_movs_test_helper(fen: &str, expected_mov_list: Vec<&str>, debug: bool) {
         let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
         let bitmask = BitMask::new();
@@ -1386,4 +1114,69 @@ mod tests {
         assert!(is_in_check(&state, def::PLAYER_W));
         assert!(is_in_check(&state, def::PLAYER_B));
     }
+
+    #[test]
+    fn test_gen_strict_movs() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let fen_list = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "8/8/8/KPp4r/8/8/8/7k w - c6 0 1",
+            "8/8/8/8/k2Pp2Q/8/8/3K4 b - d3 0 1",
+            "8/8/3k4/8/2pP4/8/B7/3K4 b - d3 0 1",
+            "4k3/8/8/8/1b6/8/3N4/4K2R w K - 0 1",
+            "4k3/4r3/8/8/8/8/3PPP2/r3K2R w K - 0 1",
+            "3qk3/8/8/1b6/8/8/3B4/r2QK2n w - - 0 1",
+            "r1bqkbnr/pppp1ppp/2n5/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 0 1",
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            "rk5r/8/8/8/8/8/8/RK5R b HAha - 0 1",
+        ];
+
+        for fen in fen_list {
+            let mut state = State::new(fen, &zob_keys, &bitmask);
+            let player = state.player;
+
+            let mut mov_list = [0; def::MAX_MOV_COUNT];
+            gen_reg_mov_list(&state, &mut mov_list);
+
+            let mut expected_mov_list = Vec::new();
+            for mov in mov_list.iter().copied().take_while(|mov| *mov != 0) {
+                let (from, to, tp, promo) = util::decode_u32_mov(mov);
+                state.do_mov(from, to, tp, promo);
+
+                if !is_in_check(&state, player) {
+                    expected_mov_list.push(util::format_mov(mov));
+                }
+
+                state.undo_mov(from, to, tp);
+            }
+
+            let mut strict_mov_list: Vec<String> = gen_legal_mov_list(&state).into_iter().map(util::format_mov).collect();
+
+            expected_mov_list.sort();
+            strict_mov_list.sort();
+
+            assert_eq!(expected_mov_list, strict_mov_list, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn test_gen_chess960_cas_movs() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let cas_mov_list = |fen: &str| {
+            let state = State::new(fen, &zob_keys, &bitmask);
+            let mut cas_mov_list: Vec<String> = gen_legal_mov_list(&state).into_iter().filter(|mov| util::decode_u32_mov(*mov).2 == def::MOV_CAS).map(util::format_mov).collect();
+            cas_mov_list.sort();
+            cas_mov_list
+        };
+
+        assert_eq!(vec!["b1c1", "b1g1"], cas_mov_list("rk5r/8/8/8/8/8/8/RK5R w HAha - 0 1"));
+        assert_eq!(vec!["f1c1", "f1g1"], cas_mov_list("5k2/8/8/8/8/8/8/4RKR1 w GE - 0 1"));
+        assert_eq!(Vec::<String>::new(), cas_mov_list("7k/8/8/8/8/8/8/qRK5 w B - 0 1"));
+        assert_eq!(Vec::<String>::new(), cas_mov_list("1r4k1/8/8/8/8/8/8/RK5R w HA - 0 1"));
+    }
 }
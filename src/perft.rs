@@ -0,0 +1,260 @@
+/*
+ * Copyright (C) 2020 Zixiao Han
+ */
+
+use crate::{
+    bitboard::BitMask,
+    def,
+    hashtable::PerftHashTable,
+    mov_table,
+    state::{FenError, State},
+    util,
+};
+
+use std::fmt;
+
+pub const PERFT_HASH_SIZE_UNIT: usize = 1048576;
+
+#[allow(clippy::enum_variant_names)]
+#[derive(PartialEq, Debug)]
+pub enum PerftSuiteError {
+    InvalidLine(usize, String),
+    InvalidDepth(usize, String),
+    InvalidNodeCount(usize, String),
+}
+
+impl fmt::Display for PerftSuiteError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PerftSuiteError::InvalidLine(line_number, line) => write!(formatter, "line {}: invalid perft entry {}", line_number, line),
+            PerftSuiteError::InvalidDepth(line_number, depth) => write!(formatter, "line {}: invalid depth {}", line_number, depth),
+            PerftSuiteError::InvalidNodeCount(line_number, node_count) => write!(formatter, "line {}: invalid node count {}", line_number, node_count),
+        }
+    }
+}
+
+/// One position of an EPD perft suite, e.g. `<fen> ;D1 20 ;D2 400`.
+#[derive(PartialEq, Debug)]
+pub struct PerftCase {
+    pub fen: String,
+    pub expected_count_list: Vec<(u8, usize)>,
+}
+
+#[derive(PartialEq, Debug)]
+pub struct PerftMismatch {
+    pub depth: u8,
+    pub expected_count: usize,
+    pub node_count: usize,
+}
+
+/// Counts the leaf nodes at `depth`; the last ply is bulk counted from the legal move list.
+pub fn perft(state: &mut State, depth: u8) -> usize {
+    if depth == 0 {
+        return 1
+    }
+
+    let mut mov_list = [0; def::MAX_MOV_COUNT];
+    mov_table::gen_strict_mov_list(state, &mut mov_list);
+
+    if depth == 1 {
+        return mov_list.iter().take_while(|mov| **mov != 0).count()
+    }
+
+    let mut node_count = 0;
+
+    for mov in mov_list.iter().copied().take_while(|mov| *mov != 0) {
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+        state.do_mov(from, to, tp, promo);
+        node_count += perft(state, depth - 1);
+        state.undo_mov(from, to, tp);
+    }
+
+    node_count
+}
+
+/// Returns the leaf node count under each legal root move, in generation order.
+pub fn perft_divide(state: &mut State, depth: u8) -> Vec<(u32, usize)> {
+    if depth == 0 {
+        return Vec::new()
+    }
+
+    mov_table::gen_legal_mov_list(state).into_iter().map(|mov| {
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+        state.do_mov(from, to, tp, promo);
+        let node_count = perft(state, depth - 1);
+        state.undo_mov(from, to, tp);
+
+        (mov, node_count)
+    }).collect()
+}
+
+/// Same as `perft`, but sub-tree counts are cached so transpositions are only counted once.
+pub fn perft_hashed(state: &mut State, depth: u8, perft_hash_table: &mut PerftHashTable) -> usize {
+    if depth <= 1 {
+        return perft(state, depth)
+    }
+
+    if let Some(node_count) = perft_hash_table.get(state.hash_key, depth) {
+        return node_count
+    }
+
+    let mut mov_list = [0; def::MAX_MOV_COUNT];
+    mov_table::gen_strict_mov_list(state, &mut mov_list);
+
+    let mut node_count = 0;
+
+    for mov in mov_list.iter().copied().take_while(|mov| *mov != 0) {
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+        state.do_mov(from, to, tp, promo);
+        node_count += perft_hashed(state, depth - 1, perft_hash_table);
+        state.undo_mov(from, to, tp);
+    }
+
+    perft_hash_table.set(state.hash_key, depth, node_count);
+
+    node_count
+}
+
+/// Parses an EPD perft suite with one position per line; blank lines and lines starting with `#` are skipped.
+pub fn parse_perft_suite(suite_str: &str) -> Result<Vec<PerftCase>, PerftSuiteError> {
+    let mut case_list = Vec::new();
+
+    for (line_index, line) in suite_str.lines().enumerate() {
+        let line = line.trim();
+        let line_number = line_index + 1;
+
+        if line.is_empty() || line.starts_with('#') {
+            continue
+        }
+
+        let mut segment_list = line.split(';');
+        let fen = segment_list.next().unwrap_or("").trim();
+
+        let mut expected_count_list = Vec::new();
+
+        for segment in segment_list {
+            let segment = segment.trim();
+            if segment.is_empty() {
+                continue
+            }
+
+            let (depth_str, count_str) = match segment.split_once(char::is_whitespace) {
+                Some((depth_str, count_str)) if depth_str.starts_with('D') => (depth_str, count_str.trim()),
+                _ => return Err(PerftSuiteError::InvalidLine(line_number, line.to_owned())),
+            };
+
+            let depth = depth_str[1..].parse::<u8>().map_err(|_| PerftSuiteError::InvalidDepth(line_number, depth_str.to_owned()))?;
+            let expected_count = count_str.parse::<usize>().map_err(|_| PerftSuiteError::InvalidNodeCount(line_number, count_str.to_owned()))?;
+
+            expected_count_list.push((depth, expected_count));
+        }
+
+        if fen.is_empty() || expected_count_list.is_empty() {
+            return Err(PerftSuiteError::InvalidLine(line_number, line.to_owned()))
+        }
+
+        case_list.push(PerftCase {
+            fen: fen.to_owned(),
+            expected_count_list,
+        });
+    }
+
+    Ok(case_list)
+}
+
+/// Runs every depth of `perft_case` up to `max_depth` and returns the depths whose counts differ from the suite.
+pub fn run_perft_case(perft_case: &PerftCase, max_depth: u8, zob_keys: &Vec<Vec<u64>>, bitmask: &BitMask) -> Result<Vec<PerftMismatch>, FenError> {
+    let mut state = State::from_fen(&perft_case.fen, zob_keys, bitmask)?;
+
+    Ok(perft_case.expected_count_list.iter().filter(|(depth, _)| *depth <= max_depth).filter_map(|&(depth, expected_count)| {
+        let node_count = perft(&mut state, depth);
+
+        if node_count == expected_count {
+            None
+        } else {
+            Some(PerftMismatch {
+                depth,
+                expected_count,
+                node_count,
+            })
+        }
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prng::XorshiftPrng,
+        uci,
+    };
+
+    #[test]
+    fn test_perft_divide() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", &zob_keys, &bitmask);
+
+        let divide_list = perft_divide(&mut state, 3);
+        assert_eq!(48, divide_list.len());
+        assert_eq!(97862, divide_list.iter().map(|(_, node_count)| node_count).sum::<usize>());
+
+        let e1g1_count = divide_list.iter().find(|(mov, _)| util::format_mov(*mov) == "e1g1").map(|(_, node_count)| *node_count);
+        assert_eq!(Some(2059), e1g1_count);
+    }
+
+    #[test]
+    fn test_perft_hashed() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut perft_hash_table = PerftHashTable::new(65536);
+
+        let mut state = State::new(uci::FEN_START_POS, &zob_keys, &bitmask);
+        assert_eq!(197281, perft_hashed(&mut state, 4, &mut perft_hash_table));
+        assert_eq!(4865609, perft_hashed(&mut state, 5, &mut perft_hash_table));
+
+        let mut state = State::new("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1", &zob_keys, &bitmask);
+        assert_eq!(674624, perft_hashed(&mut state, 5, &mut perft_hash_table));
+    }
+
+    #[test]
+    fn test_perft_chess960() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let suite_str = "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9 ;D1 21 ;D2 528 ;D3 12189\n\
+            2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9 ;D1 21 ;D2 807 ;D3 18002\n\
+            b1q1rrkb/pppppppp/3nn3/8/P7/1PPP4/4PPPP/BQNNRKRB w GE - 1 9 ;D1 20 ;D2 479 ;D3 10471\n\
+            qbbnnrkr/2pp2pp/p7/1p2pp2/8/P3PP2/1PPP1KPP/QBBNNR1R w hf - 0 9 ;D1 22 ;D2 593 ;D3 13440\n\
+            1nbbnrkr/p1p1ppp1/3p4/1p3P1p/3Pq2P/8/PPP1P1P1/QNBBNRKR w HFhf - 0 9 ;D1 28 ;D2 1120 ;D3 31058\n";
+
+        for perft_case in parse_perft_suite(suite_str).unwrap() {
+            assert_eq!(Ok(Vec::new()), run_perft_case(&perft_case, 3, &zob_keys, &bitmask), "{}", perft_case.fen);
+        }
+    }
+
+    #[test]
+    fn test_perft_suite() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let suite_str = "# standard positions\n\
+            rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - ;D1 20 ;D2 400 ;D3 8902\n\
+            \n\
+            8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - ;D1 14 ;D2 190 ;D3 2812\n";
+
+        let case_list = parse_perft_suite(suite_str).unwrap();
+        assert_eq!(2, case_list.len());
+        assert_eq!(vec![(1, 20), (2, 400), (3, 8902)], case_list[0].expected_count_list);
+
+        assert_eq!(Ok(Vec::new()), run_perft_case(&case_list[0], 3, &zob_keys, &bitmask));
+        assert_eq!(Ok(vec![PerftMismatch { depth: 2, expected_count: 190, node_count: 191 }]), run_perft_case(&case_list[1], 2, &zob_keys, &bitmask));
+
+        assert_eq!(Err(PerftSuiteError::InvalidLine(1, "8/8/8/8/8/8/8/8 w - -".to_owned())), parse_perft_suite("8/8/8/8/8/8/8/8 w - -"));
+        assert_eq!(Err(PerftSuiteError::InvalidDepth(1, "Dx".to_owned())), parse_perft_suite("8/8/8/8/8/8/8/8 w - - ;Dx 1"));
+        assert_eq!(Err(PerftSuiteError::InvalidNodeCount(1, "-1".to_owned())), parse_perft_suite("8/8/8/8/8/8/8/8 w - - ;D1 -1"));
+    }
+}
@@ -0,0 +1,574 @@
+/*
+ * Copyright (C) 2020 Zixiao Han
+ */
+
+use crate::{
+    bitboard::BitMask,
+    eval,
+    san::{self, SanError},
+    state::{FenError, State},
+    uci,
+    util,
+};
+
+use std::fmt;
+
+const MAX_LINE_LEN: usize = 80;
+
+const RESULT_WHITE_WIN: &str = "1-0";
+const RESULT_BLACK_WIN: &str = "0-1";
+const RESULT_DRAW: &str = "1/2-1/2";
+const RESULT_UNKNOWN: &str = "*";
+
+#[derive(PartialEq, Debug)]
+pub enum PgnError {
+    InvalidTag(String),
+    InvalidFen(FenError),
+    InvalidMov(SanError),
+    UnterminatedComment,
+    UnmatchedVariation,
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PgnError::InvalidTag(tag) => write!(formatter, "invalid tag {}", tag),
+            PgnError::InvalidFen(error) => write!(formatter, "invalid fen: {}", error),
+            PgnError::InvalidMov(error) => write!(formatter, "{}", error),
+            PgnError::UnterminatedComment => write!(formatter, "unterminated comment"),
+            PgnError::UnmatchedVariation => write!(formatter, "unmatched variation"),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug)]
+enum PgnToken {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    San(String),
+    VariationStart,
+    VariationEnd,
+    GameResult(String),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PgnMov {
+    pub mov: u32,
+    pub san: String,
+    pub nag_list: Vec<u8>,
+    pub comment: Option<String>,
+    pub variation_list: Vec<Vec<PgnMov>>,
+}
+
+impl PgnMov {
+    fn new(mov: u32, san: String) -> Self {
+        PgnMov {
+            mov,
+            san,
+            nag_list: Vec::new(),
+            comment: None,
+            variation_list: Vec::new(),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct PgnGame {
+    pub tag_list: Vec<(String, String)>,
+    pub comment: Option<String>,
+    pub mov_list: Vec<PgnMov>,
+    pub result: String,
+}
+
+impl PgnGame {
+    /// Creates an empty game starting from `fen`; the `SetUp` and `FEN` tags are added unless it is the standard start position.
+    pub fn new(fen: &str) -> Self {
+        let mut tag_list = Vec::new();
+
+        if fen != uci::FEN_START_POS {
+            tag_list.push(("SetUp".to_owned(), "1".to_owned()));
+            tag_list.push(("FEN".to_owned(), fen.to_owned()));
+        }
+
+        PgnGame {
+            tag_list,
+            comment: None,
+            mov_list: Vec::new(),
+            result: RESULT_UNKNOWN.to_owned(),
+        }
+    }
+
+    pub fn get_tag(&self, name: &str) -> Option<&str> {
+        self.tag_list.iter().find(|(tag_name, _)| tag_name == name).map(|(_, tag_value)| tag_value.as_str())
+    }
+
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tag_list.iter_mut().find(|(tag_name, _)| tag_name == name) {
+            Some((_, tag_value)) => *tag_value = value.to_owned(),
+            None => self.tag_list.push((name.to_owned(), value.to_owned())),
+        }
+    }
+
+    pub fn get_start_fen(&self) -> &str {
+        self.get_tag("FEN").unwrap_or(uci::FEN_START_POS)
+    }
+
+    /// Appends a move played from `state` to the main line and applies it to `state`.
+    pub fn add_mov(&mut self, state: &mut State, mov: u32, comment: Option<String>) {
+        let mut pgn_mov = PgnMov::new(mov, san::format_san(state, mov));
+        pgn_mov.comment = comment;
+        self.mov_list.push(pgn_mov);
+
+        let (from, to, tp, promo) = util::decode_u32_mov(mov);
+        state.do_mov(from, to, tp, promo);
+    }
+
+    /// Replays the main line, returning each position together with the move played from it.
+    pub fn get_state_mov_list<'state>(&self, zob_keys: &'state Vec<Vec<u64>>, bitmask: &'state BitMask) -> Result<Vec<(State<'state>, u32)>, PgnError> {
+        let mut state = State::from_fen(self.get_start_fen(), zob_keys, bitmask).map_err(PgnError::InvalidFen)?;
+        let mut state_mov_list = Vec::new();
+
+        for pgn_mov in &self.mov_list {
+            state_mov_list.push((state.clone(), pgn_mov.mov));
+
+            let (from, to, tp, promo) = util::decode_u32_mov(pgn_mov.mov);
+            state.do_mov(from, to, tp, promo);
+        }
+
+        Ok(state_mov_list)
+    }
+}
+
+impl fmt::Display for PgnGame {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for (tag_name, tag_value) in &self.tag_list {
+            writeln!(formatter, "[{} \"{}\"]", tag_name, tag_value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+
+        if self.get_tag("Result").is_none() {
+            writeln!(formatter, "[Result \"{}\"]", self.result)?;
+        }
+
+        writeln!(formatter)?;
+
+        let mut token_list = Vec::new();
+
+        if let Some(comment) = &self.comment {
+            token_list.push(format!("{{{}}}", comment));
+        }
+
+        let fen_segment_list: Vec<&str> = self.get_start_fen().split_whitespace().collect();
+        let is_white_to_move = fen_segment_list.get(1) != Some(&"b");
+        let full_mov_count = fen_segment_list.get(5).and_then(|count_str| count_str.parse::<usize>().ok()).unwrap_or(1);
+
+        append_mov_tokens(&mut token_list, &self.mov_list, is_white_to_move, full_mov_count);
+        token_list.push(self.result.clone());
+
+        let mut line = String::new();
+
+        for token in token_list {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LEN {
+                writeln!(formatter, "{}", line)?;
+                line.clear();
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+
+            line.push_str(&token);
+        }
+
+        writeln!(formatter, "{}", line)
+    }
+}
+
+fn append_mov_tokens(token_list: &mut Vec<String>, mov_list: &[PgnMov], mut is_white_to_move: bool, mut full_mov_count: usize) {
+    let mut needs_mov_number = true;
+
+    for pgn_mov in mov_list {
+        if is_white_to_move {
+            token_list.push(format!("{}.", full_mov_count));
+        } else if needs_mov_number {
+            token_list.push(format!("{}...", full_mov_count));
+        }
+
+        token_list.push(pgn_mov.san.clone());
+        needs_mov_number = false;
+
+        for nag in &pgn_mov.nag_list {
+            token_list.push(format!("${}", nag));
+        }
+
+        if let Some(comment) = &pgn_mov.comment {
+            token_list.push(format!("{{{}}}", comment));
+            needs_mov_number = true;
+        }
+
+        for variation in &pgn_mov.variation_list {
+            let mut variation_token_list = Vec::new();
+            append_mov_tokens(&mut variation_token_list, variation, is_white_to_move, full_mov_count);
+
+            if let Some(first_token) = variation_token_list.first_mut() {
+                first_token.insert(0, '(');
+            }
+
+            if let Some(last_token) = variation_token_list.last_mut() {
+                last_token.push(')');
+            }
+
+            token_list.append(&mut variation_token_list);
+            needs_mov_number = true;
+        }
+
+        if !is_white_to_move {
+            full_mov_count += 1;
+        }
+
+        is_white_to_move = !is_white_to_move;
+    }
+}
+
+/// Formats a search score and depth as a PGN comment body, such as `+0.35/18` or `-M3/22`.
+pub fn format_eval_comment(score: i32, depth: u8) -> String {
+    if score.abs() > eval::TERM_VAL {
+        let mate_mov_count = (eval::MATE_VAL - score.abs() + 1) / 2;
+        let sign = if score > 0 { '+' } else { '-' };
+        format!("{}M{}/{}", sign, mate_mov_count, depth)
+    } else {
+        format!("{:+.2}/{}", score as f64 / 100., depth)
+    }
+}
+
+/// Parses a comment written by `format_eval_comment` back into a score and depth.
+pub fn parse_eval_comment(comment: &str) -> Option<(i32, u8)> {
+    let (score_str, depth_str) = comment.trim().split_once('/')?;
+    let depth = depth_str.trim().parse::<u8>().ok()?;
+
+    let (sign, abs_score_str) = match score_str.chars().next()? {
+        '+' => (1, &score_str[1..]),
+        '-' => (-1, &score_str[1..]),
+        _ => (1, score_str),
+    };
+
+    let abs_score = match abs_score_str.strip_prefix('M') {
+        Some(mate_mov_count_str) => eval::MATE_VAL - mate_mov_count_str.parse::<i32>().ok()? * 2 + 1,
+        None => (abs_score_str.parse::<f64>().ok()? * 100.).round() as i32,
+    };
+
+    Some((sign * abs_score, depth))
+}
+
+/// Parses every game in `pgn_str`; moves are resolved against the position given by each game's `FEN` tag.
+pub fn parse_pgn(pgn_str: &str, zob_keys: &Vec<Vec<u64>>, bitmask: &BitMask) -> Result<Vec<PgnGame>, PgnError> {
+    let token_list = tokenize(pgn_str)?;
+    let mut game_list = Vec::new();
+    let mut token_index = 0;
+
+    while token_index < token_list.len() {
+        let mut game = PgnGame::new(uci::FEN_START_POS);
+
+        while let Some(PgnToken::Tag(tag_name, tag_value)) = token_list.get(token_index) {
+            game.set_tag(tag_name, tag_value);
+            token_index += 1;
+        }
+
+        let mut state = State::from_fen(game.get_start_fen(), zob_keys, bitmask).map_err(PgnError::InvalidFen)?;
+
+        if let Some(PgnToken::Comment(comment)) = token_list.get(token_index) {
+            game.comment = Some(comment.clone());
+            token_index += 1;
+        }
+
+        game.mov_list = parse_mov_line(&token_list, &mut token_index, &mut state, false)?;
+
+        match token_list.get(token_index) {
+            Some(PgnToken::GameResult(result)) => {
+                game.result = result.clone();
+                token_index += 1;
+            },
+            _ => game.result = game.get_tag("Result").unwrap_or(RESULT_UNKNOWN).to_owned(),
+        }
+
+        game_list.push(game);
+    }
+
+    Ok(game_list)
+}
+
+fn parse_mov_line(token_list: &[PgnToken], token_index: &mut usize, state: &mut State, is_variation: bool) -> Result<Vec<PgnMov>, PgnError> {
+    let mut mov_list: Vec<PgnMov> = Vec::new();
+
+    while let Some(token) = token_list.get(*token_index) {
+        match token {
+            PgnToken::San(san_str) => {
+                let mov = san::parse_san(state, san_str).map_err(PgnError::InvalidMov)?;
+                mov_list.push(PgnMov::new(mov, san::format_san(state, mov)));
+
+                let (from, to, tp, promo) = util::decode_u32_mov(mov);
+                state.do_mov(from, to, tp, promo);
+            },
+            PgnToken::Nag(nag) => {
+                if let Some(pgn_mov) = mov_list.last_mut() {
+                    pgn_mov.nag_list.push(*nag);
+                }
+            },
+            PgnToken::Comment(comment) => {
+                if let Some(pgn_mov) = mov_list.last_mut() {
+                    pgn_mov.comment = Some(match pgn_mov.comment.take() {
+                        Some(existing_comment) => format!("{} {}", existing_comment, comment),
+                        None => comment.clone(),
+                    });
+                }
+            },
+            PgnToken::VariationStart => {
+                let pgn_mov = mov_list.last_mut().ok_or(PgnError::UnmatchedVariation)?;
+                let (from, to, tp, promo) = util::decode_u32_mov(pgn_mov.mov);
+
+                state.undo_mov(from, to, tp);
+                let mut variation_state = state.clone();
+                state.do_mov(from, to, tp, promo);
+
+                *token_index += 1;
+                let variation = parse_mov_line(token_list, token_index, &mut variation_state, true)?;
+
+                if token_list.get(*token_index) != Some(&PgnToken::VariationEnd) {
+                    return Err(PgnError::UnmatchedVariation)
+                }
+
+                pgn_mov.variation_list.push(variation);
+            },
+            PgnToken::VariationEnd => {
+                if !is_variation {
+                    return Err(PgnError::UnmatchedVariation)
+                }
+
+                return Ok(mov_list)
+            },
+            PgnToken::Tag(_, _) | PgnToken::GameResult(_) => break,
+        }
+
+        *token_index += 1;
+    }
+
+    if is_variation {
+        return Err(PgnError::UnmatchedVariation)
+    }
+
+    Ok(mov_list)
+}
+
+fn tokenize(pgn_str: &str) -> Result<Vec<PgnToken>, PgnError> {
+    let mut token_list = Vec::new();
+    let char_list: Vec<char> = pgn_str.chars().collect();
+    let mut char_index = 0;
+
+    while char_index < char_list.len() {
+        let char_code = char_list[char_index];
+
+        match char_code {
+            '[' => {
+                let tag_end_index = find_char(&char_list, char_index, ']').ok_or_else(|| PgnError::InvalidTag(char_list[char_index..].iter().collect()))?;
+                token_list.push(parse_tag(&char_list[char_index+1..tag_end_index])?);
+                char_index = tag_end_index + 1;
+            },
+            '{' => {
+                let comment_end_index = find_char(&char_list, char_index, '}').ok_or(PgnError::UnterminatedComment)?;
+                let comment: String = char_list[char_index+1..comment_end_index].iter().collect();
+                token_list.push(PgnToken::Comment(comment.split_whitespace().collect::<Vec<&str>>().join(" ")));
+                char_index = comment_end_index + 1;
+            },
+            ';' => {
+                char_index = find_char(&char_list, char_index, '\n').unwrap_or(char_list.len());
+            },
+            '%' if char_index == 0 || char_list[char_index-1] == '\n' => {
+                char_index = find_char(&char_list, char_index, '\n').unwrap_or(char_list.len());
+            },
+            '(' => {
+                token_list.push(PgnToken::VariationStart);
+                char_index += 1;
+            },
+            ')' => {
+                token_list.push(PgnToken::VariationEnd);
+                char_index += 1;
+            },
+            _ if char_code.is_whitespace() => {
+                char_index += 1;
+            },
+            _ => {
+                let word_end_index = (char_index..char_list.len()).find(|index| {
+                    let word_char = char_list[*index];
+                    word_char.is_whitespace() || "[]{}();".contains(word_char)
+                }).unwrap_or(char_list.len());
+
+                let word: String = char_list[char_index..word_end_index].iter().collect();
+                append_word_tokens(&mut token_list, &word);
+                char_index = word_end_index;
+            },
+        }
+    }
+
+    Ok(token_list)
+}
+
+fn find_char(char_list: &[char], start_index: usize, target_char: char) -> Option<usize> {
+    (start_index..char_list.len()).find(|index| char_list[*index] == target_char)
+}
+
+fn parse_tag(tag_char_list: &[char]) -> Result<PgnToken, PgnError> {
+    let tag_str: String = tag_char_list.iter().collect();
+    let invalid_tag_error = || PgnError::InvalidTag(tag_str.clone());
+
+    let (tag_name, quoted_value) = tag_str.trim().split_once(char::is_whitespace).ok_or_else(invalid_tag_error)?;
+    let quoted_value = quoted_value.trim();
+
+    if quoted_value.len() < 2 || !quoted_value.starts_with('"') || !quoted_value.ends_with('"') {
+        return Err(invalid_tag_error())
+    }
+
+    let mut tag_value = String::new();
+    let mut is_escaped = false;
+
+    for char_code in quoted_value[1..quoted_value.len()-1].chars() {
+        if char_code == '\\' && !is_escaped {
+            is_escaped = true;
+            continue
+        }
+
+        tag_value.push(char_code);
+        is_escaped = false;
+    }
+
+    Ok(PgnToken::Tag(tag_name.to_owned(), tag_value))
+}
+
+fn append_word_tokens(token_list: &mut Vec<PgnToken>, word: &str) {
+    if [RESULT_WHITE_WIN, RESULT_BLACK_WIN, RESULT_DRAW, RESULT_UNKNOWN].contains(&word) {
+        token_list.push(PgnToken::GameResult(word.to_owned()));
+        return
+    }
+
+    if let Some(nag_str) = word.strip_prefix('$') {
+        if let Ok(nag) = nag_str.parse::<u8>() {
+            token_list.push(PgnToken::Nag(nag));
+        }
+
+        return
+    }
+
+    // move numbers such as `12.` or `12...` may be glued to the move that follows
+    let word = if word.starts_with(|char_code: char| char_code.is_ascii_digit()) && word.contains('.') {
+        word.trim_start_matches(|char_code: char| char_code.is_ascii_digit()).trim_start_matches('.')
+    } else {
+        word
+    };
+
+    let san_str = word.trim_end_matches(['!', '?']);
+    let suffix = &word[san_str.len()..];
+
+    if !san_str.is_empty() {
+        token_list.push(PgnToken::San(san_str.to_owned()));
+    }
+
+    let suffix_nag = match suffix {
+        "!" => 1,
+        "?" => 2,
+        "!!" => 3,
+        "??" => 4,
+        "!?" => 5,
+        "?!" => 6,
+        _ => 0,
+    };
+
+    if suffix_nag != 0 {
+        token_list.push(PgnToken::Nag(suffix_nag));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        def,
+        prng::XorshiftPrng,
+    };
+
+    #[test]
+    fn test_parse_pgn() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let pgn_str = r#"
+[Event "Test \"Match\""]
+[White "FoxSEE"]
+[Black "Opponent"]
+[Result "1-0"]
+
+{Opening comment} 1. e4 e5 2.Nf3 {+0.35/18} Nc6 (2... d6 3. d4 (3. Bc4) 3... exd4) 3. Bb5! $14 a6?! ; line comment
+4. Ba4 Nf6 5. O-O 1-0
+
+[Event "Second"]
+[SetUp "1"]
+[FEN "6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 40"]
+
+40. Ra8# 1-0
+"#;
+
+        let game_list = parse_pgn(pgn_str, &zob_keys, &bitmask).unwrap();
+        assert_eq!(2, game_list.len());
+
+        let game = &game_list[0];
+        assert_eq!(Some("Test \"Match\""), game.get_tag("Event"));
+        assert_eq!(Some("Opening comment".to_owned()), game.comment);
+        assert_eq!("1-0", game.result);
+        assert_eq!(9, game.mov_list.len());
+        assert_eq!("e1g1", util::format_mov(game.mov_list[8].mov));
+        assert_eq!(Some((35, 18)), parse_eval_comment(game.mov_list[2].comment.as_ref().unwrap()));
+        assert_eq!(vec![1, 14], game.mov_list[4].nag_list);
+        assert_eq!(vec![6], game.mov_list[5].nag_list);
+
+        let variation = &game.mov_list[3].variation_list[0];
+        assert_eq!(vec!["d6", "d4", "exd4"], variation.iter().map(|pgn_mov| pgn_mov.san.as_str()).collect::<Vec<&str>>());
+        assert_eq!("Bc4", variation[1].variation_list[0][0].san);
+
+        let state_mov_list = game.get_state_mov_list(&zob_keys, &bitmask).unwrap();
+        assert_eq!(9, state_mov_list.len());
+        assert_eq!("r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3", state_mov_list[5].0.to_fen());
+
+        let game = &game_list[1];
+        assert_eq!(1, game.mov_list.len());
+        assert_eq!("Ra8#", game.mov_list[0].san);
+
+        assert!(parse_pgn("1. e4 e5 (2. Nf3", &zob_keys, &bitmask).is_err());
+        assert!(parse_pgn("1. e4 e5 2. Nf6", &zob_keys, &bitmask).is_err());
+        assert!(parse_pgn("1. e4 {unterminated", &zob_keys, &bitmask).is_err());
+    }
+
+    #[test]
+    fn test_write_pgn() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 12";
+        let mut state = State::new(fen, &zob_keys, &bitmask);
+        let mut game = PgnGame::new(fen);
+        game.set_tag("White", "FoxSEE");
+
+        let mov = san::parse_san(&mut state, "O-O").unwrap();
+        game.add_mov(&mut state, mov, Some(format_eval_comment(-35, 18)));
+
+        let mov = san::parse_san(&mut state, "Rxa8").unwrap();
+        game.add_mov(&mut state, mov, Some(format_eval_comment(eval::MATE_VAL - 5, 20)));
+        game.result = RESULT_DRAW.to_owned();
+
+        let pgn_str = game.to_string();
+        assert_eq!("[SetUp \"1\"]\n[FEN \"r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 12\"]\n[White \"FoxSEE\"]\n[Result \"1/2-1/2\"]\n\n12... O-O {-0.35/18} 13. Rxa8 {+M3/20} 1/2-1/2\n", pgn_str);
+
+        let game_list = parse_pgn(&pgn_str, &zob_keys, &bitmask).unwrap();
+        assert_eq!(1, game_list.len());
+        assert_eq!(game.mov_list, game_list[0].mov_list);
+        assert_eq!(Some((-35, 18)), parse_eval_comment("-0.35/18"));
+        assert_eq!(Some((eval::MATE_VAL - 5, 20)), parse_eval_comment("+M3/20"));
+    }
+}
@@ -9,6 +9,7 @@ use crate::{
 static SEED_C89: u64 = 0b10110110_00101111_10100100_01011000_00001000_01100100_11010111_11111010;
 static SEED_A86: u64 = 0b10111001_11010011_00111100_00010100_00110000_00100110_11001111_10110110;
 
+#[allow(clippy::manual_rotate)]
 const fn rotate(x: u64, k: usize) -> u64 {
     (x << k) | (x >> (64 - k))
 }
@@ -17,6 +18,12 @@ pub struct XorshiftPrng {
     state: [u64; 2],
 }
 
+impl Default for XorshiftPrng {
+    fn default() -> Self {
+        XorshiftPrng::new()
+    }
+}
+
 impl XorshiftPrng {
     pub fn new() -> XorshiftPrng {
         XorshiftPrng {
@@ -24,11 +31,18 @@ impl XorshiftPrng {
         }
     }
 
-    fn gen_rand(&mut self) -> u64 {
+    /// Seeds the generator from `seed`, e.g. to vary book moves between runs.
+    pub fn with_seed(seed: u64) -> XorshiftPrng {
+        XorshiftPrng {
+            state: [SEED_C89 ^ seed, SEED_A86],
+        }
+    }
+
+    pub fn gen_rand(&mut self) -> u64 {
         let s0 = self.state[0];
         let mut s1 = self.state[1];
 
-        let next_rand = rotate(s0 * 5, 7) * 9;
+        let next_rand = rotate(s0.wrapping_mul(5), 7).wrapping_mul(9);
         s1 ^= s0;
 
         self.state[0] = rotate(s0, 24) ^ s1 ^ (s1 << 16);
@@ -37,6 +51,7 @@ impl XorshiftPrng {
         next_rand
     }
 
+    #[allow(clippy::needless_range_loop)]
     pub fn create_prn_table(&mut self, fst_dim: usize, snd_dim: usize) -> Vec<Vec<u64>> {
         let mut prn_table = vec![vec![0; snd_dim]; fst_dim];
 
@@ -71,6 +86,7 @@ mod tests {
     use crate::def;
 
     #[test]
+    #[allow(clippy::needless_range_loop)]
     fn test_gen_rand() {
         let mut rand = XorshiftPrng::new();
         let rand_table = rand.create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
@@ -0,0 +1,272 @@
+/*
+ * Copyright (C) 2020 Zixiao Han
+ */
+
+use crate::{
+    def,
+    mov_table,
+    state::State,
+    util,
+};
+
+use std::fmt;
+
+#[derive(PartialEq, Debug)]
+pub enum SanError {
+    InvalidSan(String),
+    IllegalMov(String),
+    AmbiguousMov(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SanError::InvalidSan(san) => write!(formatter, "invalid move notation {}", san),
+            SanError::IllegalMov(san) => write!(formatter, "illegal move {}", san),
+            SanError::AmbiguousMov(san) => write!(formatter, "ambiguous move {}", san),
+        }
+    }
+}
+
+#[inline]
+fn get_piece_type(piece_code: u8) -> u8 {
+    piece_code & !def::PLAYER_SWITCH
+}
+
+fn map_piece_type_to_char(piece_type: u8) -> char {
+    match piece_type {
+        def::K => 'K',
+        def::Q => 'Q',
+        def::R => 'R',
+        def::B => 'B',
+        def::N => 'N',
+        _ => '\0',
+    }
+}
+
+fn map_char_to_piece_type(piece_char: char) -> Option<u8> {
+    match piece_char {
+        'K' => Some(def::K),
+        'Q' => Some(def::Q),
+        'R' => Some(def::R),
+        'B' => Some(def::B),
+        'N' => Some(def::N),
+        _ => None,
+    }
+}
+
+/// Formats a legal move in `state` in Standard Algebraic Notation, including the check or mate suffix.
+pub fn format_san(state: &mut State, mov: u32) -> String {
+    let legal_mov_list = mov_table::gen_legal_mov_list(state);
+    let mut san = format_san_without_suffix(state, mov, &legal_mov_list);
+
+    let (from, to, tp, promo) = util::decode_u32_mov(mov);
+    state.do_mov(from, to, tp, promo);
+
+    if mov_table::is_in_check(state, state.player) {
+        if mov_table::gen_legal_mov_list(state).is_empty() {
+            san.push('#');
+        } else {
+            san.push('+');
+        }
+    }
+
+    state.undo_mov(from, to, tp);
+
+    san
+}
+
+fn format_san_without_suffix(state: &State, mov: u32, legal_mov_list: &[u32]) -> String {
+    let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+    if tp == def::MOV_CAS {
+        return if to % def::DIM_SIZE == def::CAS_SQUARE_WK {
+            "O-O".to_owned()
+        } else {
+            "O-O-O".to_owned()
+        }
+    }
+
+    let piece_type = get_piece_type(state.squares[from]);
+    let is_cap = state.squares[to] != 0 || tp == def::MOV_ENP;
+    let from_sqr = util::map_index_to_sqr_notation(from);
+    let to_sqr = util::map_index_to_sqr_notation(to);
+
+    let mut san = String::new();
+
+    if piece_type == def::P {
+        if is_cap {
+            san.push_str(&from_sqr[0..1]);
+            san.push('x');
+        }
+
+        san.push_str(&to_sqr);
+
+        if promo != 0 {
+            san.push('=');
+            san.push(map_piece_type_to_char(get_piece_type(promo)));
+        }
+
+        return san
+    }
+
+    san.push(map_piece_type_to_char(piece_type));
+
+    let rival_from_list: Vec<usize> = legal_mov_list.iter().map(|rival_mov| util::decode_u32_mov(*rival_mov)).filter(|(rival_from, rival_to, _, _)| {
+        *rival_to == to && *rival_from != from && get_piece_type(state.squares[*rival_from]) == piece_type
+    }).map(|(rival_from, _, _, _)| rival_from).collect();
+
+    if !rival_from_list.is_empty() {
+        if rival_from_list.iter().all(|rival_from| rival_from % def::DIM_SIZE != from % def::DIM_SIZE) {
+            san.push_str(&from_sqr[0..1]);
+        } else if rival_from_list.iter().all(|rival_from| rival_from / def::DIM_SIZE != from / def::DIM_SIZE) {
+            san.push_str(&from_sqr[1..2]);
+        } else {
+            san.push_str(&from_sqr);
+        }
+    }
+
+    if is_cap {
+        san.push('x');
+    }
+
+    san.push_str(&to_sqr);
+
+    san
+}
+
+/// Resolves a move in Standard Algebraic Notation to the matching legal move in `state`.
+/// Check and annotation suffixes are ignored, and redundant disambiguation is accepted.
+pub fn parse_san(state: &mut State, san: &str) -> Result<u32, SanError> {
+    let san_body = san.trim_end_matches(|char_code| "+#!?".contains(char_code));
+    let legal_mov_list = mov_table::gen_legal_mov_list(state);
+
+    if ["O-O", "0-0", "O-O-O", "0-0-0"].contains(&san_body) {
+        let cas_file = if san_body.len() == 3 { def::CAS_SQUARE_WK } else { def::CAS_SQUARE_WQ };
+
+        return legal_mov_list.iter().copied().find(|mov| {
+            let (_, to, tp, _) = util::decode_u32_mov(*mov);
+            tp == def::MOV_CAS && to % def::DIM_SIZE == cas_file
+        }).ok_or_else(|| SanError::IllegalMov(san.to_owned()))
+    }
+
+    let mut san_chars: Vec<char> = san_body.chars().filter(|char_code| *char_code != 'x' && *char_code != '-' && *char_code != '=').collect();
+
+    let promo_type = match san_chars.last().copied().and_then(map_char_to_piece_type) {
+        Some(promo_type) if promo_type != def::K => {
+            san_chars.pop();
+            promo_type
+        },
+        _ => 0,
+    };
+
+    let piece_type = match san_chars.first().copied().and_then(map_char_to_piece_type) {
+        Some(piece_type) => {
+            san_chars.remove(0);
+            piece_type
+        },
+        None => def::P,
+    };
+
+    if san_chars.len() < 2 || san_chars.len() > 4 {
+        return Err(SanError::InvalidSan(san.to_owned()))
+    }
+
+    let to_chars = san_chars.split_off(san_chars.len() - 2);
+    let to_sqr: String = to_chars.iter().collect();
+    if !is_valid_sqr(&to_chars) {
+        return Err(SanError::InvalidSan(san.to_owned()))
+    }
+
+    let to = util::map_sqr_notation_to_index(&to_sqr);
+
+    let mut from_file = None;
+    let mut from_rank = None;
+
+    for char_code in san_chars {
+        match char_code {
+            'a'..='h' if from_file.is_none() && from_rank.is_none() => from_file = Some(char_code as usize - 'a' as usize),
+            '1'..='8' if from_rank.is_none() => from_rank = Some(char_code as usize - '1' as usize),
+            _ => return Err(SanError::InvalidSan(san.to_owned())),
+        }
+    }
+
+    let matched_mov_list: Vec<u32> = legal_mov_list.iter().copied().filter(|mov| {
+        let (mov_from, mov_to, tp, promo) = util::decode_u32_mov(*mov);
+
+        tp != def::MOV_CAS
+            && mov_to == to
+            && get_piece_type(state.squares[mov_from]) == piece_type
+            && get_piece_type(promo) == promo_type
+            && from_file.is_none_or(|file| mov_from % def::DIM_SIZE == file)
+            && from_rank.is_none_or(|rank| mov_from / def::DIM_SIZE == rank)
+    }).collect();
+
+    match matched_mov_list.len() {
+        0 => Err(SanError::IllegalMov(san.to_owned())),
+        1 => Ok(matched_mov_list[0]),
+        _ => Err(SanError::AmbiguousMov(san.to_owned())),
+    }
+}
+
+fn is_valid_sqr(sqr_chars: &[char]) -> bool {
+    ('a'..='h').contains(&sqr_chars[0]) && ('1'..='8').contains(&sqr_chars[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitboard::BitMask,
+        prng::XorshiftPrng,
+    };
+
+    #[test]
+    fn test_format_san() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let mut state = State::new("r3k2r/1P3ppp/8/3pP3/8/1N3N2/5PPP/R3K2R w KQkq d6 0 1", &zob_keys, &bitmask);
+        let mut san_list: Vec<String> = mov_table::gen_legal_mov_list(&state).iter().map(|mov| format_san(&mut state, *mov)).collect();
+        san_list.sort();
+
+        for san in ["O-O", "O-O-O", "exd6", "e6", "b8=Q+", "bxa8=N", "Nbd4", "Nfd4", "Nbd2", "Rxa8+", "Kd1"] {
+            assert!(san_list.contains(&san.to_owned()), "{} not in {:?}", san, san_list);
+        }
+
+        let mut state = State::new("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", &zob_keys, &bitmask);
+        let mov = parse_san(&mut state, "Ra8").unwrap();
+        assert_eq!("Ra8#", format_san(&mut state, mov));
+
+        let mut state = State::new("k7/8/8/8/8/8/8/R4RK1 w - - 0 1", &zob_keys, &bitmask);
+        let mov = parse_san(&mut state, "Rad1").unwrap();
+        assert_eq!("Rad1", format_san(&mut state, mov));
+
+        let mut state = State::new("7k/8/8/8/R7/8/8/R3K3 w - - 0 1", &zob_keys, &bitmask);
+        let mov = parse_san(&mut state, "R1a3").unwrap();
+        assert_eq!("R1a3", format_san(&mut state, mov));
+    }
+
+    #[test]
+    fn test_parse_san() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let mut state = State::new("r3k2r/1P3ppp/8/3pP3/8/1N3N2/5PPP/R3K2R w KQkq d6 0 1", &zob_keys, &bitmask);
+
+        assert_eq!("e1g1", util::format_mov(parse_san(&mut state, "O-O").unwrap()));
+        assert_eq!("e1c1", util::format_mov(parse_san(&mut state, "0-0-0").unwrap()));
+        assert_eq!("e5d6", util::format_mov(parse_san(&mut state, "exd6").unwrap()));
+        assert_eq!("b7b8q", util::format_mov(parse_san(&mut state, "b8=Q+").unwrap()));
+        assert_eq!("b7a8n", util::format_mov(parse_san(&mut state, "bxa8N").unwrap()));
+        assert_eq!("f3d4", util::format_mov(parse_san(&mut state, "Nf3d4").unwrap()));
+        assert_eq!("b3d2", util::format_mov(parse_san(&mut state, "Nbd2").unwrap()));
+        assert_eq!("a1a8", util::format_mov(parse_san(&mut state, "Rxa8+!?").unwrap()));
+
+        assert_eq!(Err(SanError::AmbiguousMov("Nd4".to_owned())), parse_san(&mut state, "Nd4"));
+        assert_eq!(Err(SanError::IllegalMov("b8".to_owned())), parse_san(&mut state, "b8"));
+        assert_eq!(Err(SanError::IllegalMov("Qd1".to_owned())), parse_san(&mut state, "Qd1"));
+        assert_eq!(Err(SanError::InvalidSan("Nz9".to_owned())), parse_san(&mut state, "Nz9"));
+        assert_eq!(Err(SanError::InvalidSan("".to_owned())), parse_san(&mut state, ""));
+    }
+}
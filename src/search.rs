@@ -4,11 +4,16 @@
 
 use crate::{
     def,
-    eval,
-    hashtable::{AlwaysReplaceHashTable, DepthPreferredHashTable, LookupResult, HASH_TYPE_ALPHA, HASH_TYPE_BETA, HASH_TYPE_EXACT},
+    eval::{self, PawnEntry},
+    eval_params::EvalParams,
+    hashtable::{AlwaysReplaceHashTable, DepthPreferredHashTable, LookupResult, PawnHashTable, HASH_TYPE_ALPHA, HASH_TYPE_BETA, HASH_TYPE_EXACT},
+    material::MaterialTable,
     mov_table,
+    perft,
     state::State,
+    syzygy::{self, SyzygyTablebase},
     time_control::TimeCapacity,
+    uci,
     util,
 };
 
@@ -38,34 +43,216 @@ const FUTILITY_MARGIN: [i32; FP_DEPTH as usize + 1] = [0, 420, 540, 660, 780, 90
 
 const TIME_CHECK_INTEVAL: u64 = 4095;
 
-static mut NODE_COUNT: u64 = 0;
-static mut SEL_DEPTH: u8 = 0;
+const TB_WIN_VAL: i32 = eval::TERM_VAL - 1000;
+const TB_HASH_DEPTH_BONUS: u8 = 6;
 
-pub static mut ABORT_SEARCH: bool = false;
+const MATERIAL_TABLE_SIZE: usize = 8192;
+const PAWN_HASH_TABLE_SIZE: usize = 8192;
 
-use std::time::Instant;
+use std::{sync::{atomic::{AtomicBool, AtomicU64, AtomicU8, Ordering}, Arc}, thread, time::{Duration, Instant}};
 use LookupResult::*;
 
+/// Handle to the search run by a `SearchEngine`, shared by all of its search threads.
+/// Clones refer to the same search and can be used from other threads to stop it or poll its statistics.
+/// A stop request is cleared once the search it applies to returns, so a request made just before the
+/// search starts is not lost.
+#[derive(Clone, Default)]
+pub struct SearchControl {
+    abort_search: Arc<AtomicBool>,
+    ponder_hit: Arc<AtomicBool>,
+    node_count: Arc<AtomicU64>,
+    tb_hit_count: Arc<AtomicU64>,
+    sel_depth: Arc<AtomicU8>,
+}
+
+impl SearchControl {
+    pub fn new() -> Self {
+        SearchControl::default()
+    }
+
+    pub fn stop(&self) {
+        self.abort_search.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.abort_search.load(Ordering::Relaxed)
+    }
+
+    pub fn clear_stop(&self) {
+        self.abort_search.store(false, Ordering::Relaxed);
+    }
+
+    /// Turns a running ponder search into a timed one.
+    pub fn ponder_hit(&self) {
+        self.ponder_hit.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_ponder_hit(&self) -> bool {
+        self.ponder_hit.load(Ordering::Relaxed)
+    }
+
+    pub fn clear_ponder_hit(&self) {
+        self.ponder_hit.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns the nodes searched by all threads since the current search started.
+    pub fn get_node_count(&self) -> u64 {
+        self.node_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the tablebase probes made by all threads since the current search started.
+    pub fn get_tb_hit_count(&self) -> u64 {
+        self.tb_hit_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns the selective depth reached in the current iteration.
+    pub fn get_sel_depth(&self) -> u8 {
+        self.sel_depth.load(Ordering::Relaxed)
+    }
+
+    fn reset_search_stats(&self) {
+        self.node_count.store(0, Ordering::Relaxed);
+        self.tb_hit_count.store(0, Ordering::Relaxed);
+        self.sel_depth.store(0, Ordering::Relaxed);
+    }
+
+    fn add_search_stats(&self, node_count: u64, tb_hit_count: u64, sel_depth: u8) {
+        self.node_count.fetch_add(node_count, Ordering::Relaxed);
+        self.tb_hit_count.fetch_add(tb_hit_count, Ordering::Relaxed);
+        self.sel_depth.fetch_max(sel_depth, Ordering::Relaxed);
+    }
+}
+
+type InfoSink = Box<dyn Fn(&str) + Send>;
+
+/// The limits of one search, all applied together; the search stops at whichever is reached first.
+/// A mate limit also reports when no mate within that many moves was found.
+pub struct SearchLimits {
+    pub time_capacity: TimeCapacity,
+    pub max_depth: u8,
+    pub max_node_count: u64,
+    pub mate_mov_count: Option<u8>,
+}
+
+impl Default for SearchLimits {
+    fn default() -> Self {
+        SearchLimits {
+            time_capacity: TimeCapacity {
+                main_time_millis: u128::MAX,
+                extra_time_millis: 0,
+            },
+            max_depth: MAX_DEPTH,
+            max_node_count: u64::MAX,
+            mate_mov_count: None,
+        }
+    }
+}
+
 pub struct SearchEngine {
-    depth_preferred_hash_table: DepthPreferredHashTable,
-    always_replace_hash_table: AlwaysReplaceHashTable,
+    depth_preferred_hash_table: Arc<DepthPreferredHashTable>,
+    always_replace_hash_table: Arc<AlwaysReplaceHashTable>,
+    material_table: MaterialTable,
+    pawn_hash_table: PawnHashTable,
+    eval_params: Arc<EvalParams>,
     primary_killer_table: [(i32, u32); PV_TRACK_LENGTH],
     secondary_killer_table: [(i32, u32); PV_TRACK_LENGTH],
     index_history_table: [[i32; def::BOARD_SIZE]; def::BOARD_SIZE],
     time_tracker: Instant,
+    // the time budget runs from the start of the search, or from the ponder hit when pondering
+    budget_tracker: Instant,
     max_time_millis: u128,
+    extra_time_millis: u128,
+    ponder_time_capacity: Option<TimeCapacity>,
+    max_node_count: u64,
+    root_mov_list: Vec<u32>,
+    excluded_root_mov_list: Vec<u32>,
+    root_best_mov: u32,
+    multi_pv: usize,
+    ponder_mov: u32,
+    thread_count: usize,
+    search_control: SearchControl,
+    tablebase: Arc<SyzygyTablebase>,
+    tb_probe_depth: u8,
+    tb_probe_limit: usize,
+    tb_cardinality: usize,
+    node_count: u64,
+    reported_node_count: u64,
+    tb_hit_count: u64,
+    reported_tb_hit_count: u64,
+    sel_depth: u8,
+    info_sink: Option<InfoSink>,
 }
 
 impl SearchEngine {
     pub fn new(hash_size: usize) -> Self {
         SearchEngine {
-            depth_preferred_hash_table: DepthPreferredHashTable::new(hash_size >> 1),
-            always_replace_hash_table: AlwaysReplaceHashTable::new(hash_size >> 1),
+            depth_preferred_hash_table: Arc::new(DepthPreferredHashTable::new(hash_size >> 1)),
+            always_replace_hash_table: Arc::new(AlwaysReplaceHashTable::new(hash_size >> 1)),
+            material_table: MaterialTable::new(MATERIAL_TABLE_SIZE),
+            pawn_hash_table: PawnHashTable::new(PAWN_HASH_TABLE_SIZE),
+            eval_params: Arc::new(EvalParams::default()),
             primary_killer_table: [(0, 0); PV_TRACK_LENGTH],
             secondary_killer_table: [(0, 0); PV_TRACK_LENGTH],
             index_history_table: [[0; def::BOARD_SIZE]; def::BOARD_SIZE],
             time_tracker: Instant::now(),
+            budget_tracker: Instant::now(),
             max_time_millis: 0,
+            extra_time_millis: 0,
+            ponder_time_capacity: None,
+            max_node_count: 0,
+            root_mov_list: Vec::new(),
+            excluded_root_mov_list: Vec::new(),
+            root_best_mov: 0,
+            multi_pv: 1,
+            ponder_mov: 0,
+            thread_count: 1,
+            search_control: SearchControl::new(),
+            tablebase: Arc::new(SyzygyTablebase::new()),
+            tb_probe_depth: 1,
+            tb_probe_limit: syzygy::TB_MAX_PIECE_COUNT,
+            tb_cardinality: 0,
+            node_count: 0,
+            reported_node_count: 0,
+            tb_hit_count: 0,
+            reported_tb_hit_count: 0,
+            sel_depth: 0,
+            info_sink: None,
+        }
+    }
+
+    fn create_helper(&self) -> Self {
+        SearchEngine {
+            depth_preferred_hash_table: Arc::clone(&self.depth_preferred_hash_table),
+            always_replace_hash_table: Arc::clone(&self.always_replace_hash_table),
+            material_table: MaterialTable::new(MATERIAL_TABLE_SIZE),
+            pawn_hash_table: PawnHashTable::new(PAWN_HASH_TABLE_SIZE),
+            eval_params: Arc::clone(&self.eval_params),
+            primary_killer_table: [(0, 0); PV_TRACK_LENGTH],
+            secondary_killer_table: [(0, 0); PV_TRACK_LENGTH],
+            index_history_table: [[0; def::BOARD_SIZE]; def::BOARD_SIZE],
+            time_tracker: self.time_tracker,
+            budget_tracker: self.time_tracker,
+            max_time_millis: u128::MAX,
+            extra_time_millis: 0,
+            ponder_time_capacity: None,
+            max_node_count: self.max_node_count,
+            root_mov_list: self.root_mov_list.clone(),
+            excluded_root_mov_list: Vec::new(),
+            root_best_mov: 0,
+            multi_pv: 1,
+            ponder_mov: 0,
+            thread_count: 1,
+            search_control: self.search_control.clone(),
+            tablebase: Arc::clone(&self.tablebase),
+            tb_probe_depth: self.tb_probe_depth,
+            tb_probe_limit: self.tb_probe_limit,
+            tb_cardinality: self.tb_cardinality,
+            node_count: 0,
+            reported_node_count: 0,
+            tb_hit_count: 0,
+            reported_tb_hit_count: 0,
+            sel_depth: 0,
+            info_sink: None,
         }
     }
 
@@ -75,81 +262,253 @@ impl SearchEngine {
     }
 
     pub fn set_hash_size(&mut self, hash_size: usize) {
-        self.depth_preferred_hash_table = DepthPreferredHashTable::new(hash_size >> 1);
-        self.always_replace_hash_table = AlwaysReplaceHashTable::new(hash_size >> 1);
+        self.depth_preferred_hash_table = Arc::new(DepthPreferredHashTable::new(hash_size >> 1));
+        self.always_replace_hash_table = Arc::new(AlwaysReplaceHashTable::new(hash_size >> 1));
     }
 
-    pub fn perft(&self, state: &mut State, depth: u8) -> usize {
-        if mov_table::is_in_check(state, def::get_opposite_player(state.player)) {
-            return 0
-        }
+    /// Sets the number of threads used by the next search; with more than one thread, helper
+    /// searchers share the transposition table with the main search (Lazy SMP).
+    pub fn set_thread_count(&mut self, thread_count: usize) {
+        self.thread_count = thread_count.max(1);
+    }
 
-        if depth == 0 {
-            return 1
+    /// Sets the receiver of the UCI `info` lines produced while searching; without one, searches are silent.
+    pub fn set_info_sink(&mut self, info_sink: impl Fn(&str) + Send + 'static) {
+        self.info_sink = Some(Box::new(info_sink));
+    }
+
+    fn report_info(&self, info: &str) {
+        if let Some(info_sink) = &self.info_sink {
+            info_sink(info);
         }
+    }
 
-        let mut node_count = 0;
+    pub fn get_search_control(&self) -> SearchControl {
+        self.search_control.clone()
+    }
 
-        let mut mov_list = [0; def::MAX_MOV_COUNT];
+    pub fn set_multi_pv(&mut self, multi_pv: usize) {
+        self.multi_pv = multi_pv.max(1);
+    }
 
-        mov_table::gen_reg_mov_list(state, &mut mov_list);
+    /// Restricts the root moves considered by the next search; an empty list means all moves.
+    pub fn set_root_mov_list(&mut self, root_mov_list: Vec<u32>) {
+        self.root_mov_list = root_mov_list;
+    }
 
-        for mov_index in 0..def::MAX_MOV_COUNT {
-            let mov = mov_list[mov_index];
+    pub fn set_tablebase(&mut self, tablebase: SyzygyTablebase) {
+        self.tablebase = Arc::new(tablebase);
+    }
 
-            if mov == 0 {
-                break
-            }
+    /// Sets the minimum remaining depth at which positions with `tb_probe_limit` pieces are probed during search.
+    pub fn set_tb_probe_depth(&mut self, tb_probe_depth: u8) {
+        self.tb_probe_depth = tb_probe_depth;
+    }
 
-            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+    /// Sets the largest number of pieces, kings included, probed during search; 0 disables probing.
+    pub fn set_tb_probe_limit(&mut self, tb_probe_limit: usize) {
+        self.tb_probe_limit = tb_probe_limit;
+    }
 
-            if def::is_k(state.squares[to]) {
-                return 0
-            }
+    /// Replaces the evaluation weights; the material and pawn hash tables are cleared, as their entries
+    /// depend on them. Searched states are switched to the new weights when a search starts.
+    pub fn set_eval_params(&mut self, eval_params: EvalParams) {
+        self.eval_params = Arc::new(eval_params);
+        self.material_table = MaterialTable::new(MATERIAL_TABLE_SIZE);
+        self.pawn_hash_table = PawnHashTable::new(PAWN_HASH_TABLE_SIZE);
+    }
 
-            state.do_mov(from, to, tp, promo);
-            node_count += self.perft(state, depth - 1);
-            state.undo_mov(from, to, tp);
-        }
+    /// Returns the pawn hash table probes and hits of the main search thread in the last search.
+    pub fn get_pawn_hash_stats(&self) -> (u64, u64) {
+        self.pawn_hash_table.get_stats()
+    }
 
-        node_count
+    /// Returns the reply expected after the best move of the last search, or 0 if the PV was too short.
+    pub fn get_ponder_mov(&self) -> u32 {
+        self.ponder_mov
+    }
+
+    pub fn perft(&self, state: &mut State, depth: u8) -> usize {
+        perft::perft(state, depth)
     }
 
     pub fn search(&mut self, state: &mut State, time_capacity: TimeCapacity, max_depth: u8) -> u32 {
+        self.search_with_limits(state, SearchLimits { time_capacity, max_depth, ..SearchLimits::default() })
+    }
+
+    pub fn search_with_limits(&mut self, state: &mut State, search_limits: SearchLimits) -> u32 {
+        let max_depth = get_max_depth(&search_limits);
+        let (best_mov, best_score) = self.iterative_search(state, search_limits.time_capacity, max_depth, search_limits.max_node_count);
+
+        self.check_mate_found(best_score, search_limits.mate_mov_count);
+
+        best_mov
+    }
+
+    /// Searches without a clock until `SearchControl::ponder_hit` is called, then continues with the time
+    /// capacity of `search_limits`; its other limits apply from the start.
+    pub fn ponder(&mut self, state: &mut State, search_limits: SearchLimits) -> u32 {
+        let max_depth = get_max_depth(&search_limits);
+        self.ponder_time_capacity = Some(search_limits.time_capacity);
+
+        let (best_mov, best_score) = self.iterative_search(state, TimeCapacity { main_time_millis: u128::MAX, extra_time_millis: 0 }, max_depth, search_limits.max_node_count);
+
+        self.ponder_time_capacity = None;
+
+        self.search_control.clear_ponder_hit();
+
+        self.check_mate_found(best_score, search_limits.mate_mov_count);
+
+        best_mov
+    }
+
+    fn check_mate_found(&self, best_score: i32, mate_mov_count: Option<u8>) {
+        if let Some(mate_mov_count) = mate_mov_count {
+            if best_score <= eval::TERM_VAL || (eval::MATE_VAL - best_score + 1) / 2 > mate_mov_count as i32 {
+                self.report_info(&format!("info string no mate in {} found", mate_mov_count));
+            }
+        }
+    }
+
+    fn iterative_search(&mut self, state: &mut State, time_capacity: TimeCapacity, max_depth: u8, max_node_count: u64) -> (u32, i32) {
         self.time_tracker = Instant::now();
+        self.budget_tracker = self.time_tracker;
         self.max_time_millis = time_capacity.main_time_millis;
+        self.extra_time_millis = time_capacity.extra_time_millis;
 
         self.primary_killer_table = [(0, 0); PV_TRACK_LENGTH];
         self.secondary_killer_table = [(0, 0); PV_TRACK_LENGTH];
         self.index_history_table = [[0; def::BOARD_SIZE]; def::BOARD_SIZE];
+        self.root_best_mov = 0;
+        self.ponder_mov = 0;
+        self.node_count = 0;
+        self.reported_node_count = 0;
+        self.tb_hit_count = 0;
+        self.reported_tb_hit_count = 0;
+        self.max_node_count = max_node_count;
+        self.pawn_hash_table.reset_stats();
+
+        self.search_control.reset_search_stats();
+
+        if !Arc::ptr_eq(&state.eval_params, &self.eval_params) {
+            state.set_eval_params(Arc::clone(&self.eval_params));
+        }
+
+        self.filter_root_movs_by_tablebase(state);
+
+        let search_result = if self.thread_count > 1 {
+            let helper_list: Vec<SearchEngine> = (1..self.thread_count).map(|_| self.create_helper()).collect();
+
+            thread::scope(|scope| {
+                for (helper_index, mut helper) in helper_list.into_iter().enumerate() {
+                    let mut helper_state = state.clone();
+                    scope.spawn(move || helper.helper_search(&mut helper_state, max_depth, helper_index));
+                }
+
+                let search_result = self.iterative_deepening(state, max_depth);
+                self.search_control.stop();
+
+                search_result
+            })
+        } else {
+            self.iterative_deepening(state, max_depth)
+        };
+
+        self.root_mov_list.clear();
+        self.search_control.clear_stop();
+
+        search_result
+    }
+
+    /// Restricts the root moves to those preserving the tablebase result. In-search probing is only
+    /// needed if the root could not be resolved with DTZ tables and the root position is won.
+    fn filter_root_movs_by_tablebase(&mut self, state: &mut State) {
+        self.tb_cardinality = self.tb_probe_limit.min(self.tablebase.get_max_piece_count());
+
+        if self.tb_cardinality == 0 || !self.tablebase.is_probable(state) || (state.bitboard.w_all | state.bitboard.b_all).count_ones() as usize > self.tb_cardinality {
+            return
+        }
+
+        let legal_mov_list: Vec<u32> = mov_table::gen_legal_mov_list(state).into_iter().filter(|mov| self.is_root_mov_allowed(*mov)).collect();
+
+        if legal_mov_list.is_empty() {
+            return
+        }
+
+        let (tb_mov_list, root_wdl, is_dtz) = match self.tablebase.filter_root_movs_by_dtz(state, &legal_mov_list) {
+            Some((tb_mov_list, root_wdl)) => (tb_mov_list, root_wdl, true),
+            None => match self.tablebase.filter_root_movs_by_wdl(state, &legal_mov_list) {
+                Some((tb_mov_list, root_wdl)) => (tb_mov_list, root_wdl, false),
+                None => return,
+            },
+        };
+
+        self.tb_hit_count += legal_mov_list.len() as u64;
+
+        if tb_mov_list.is_empty() {
+            return
+        }
 
-        unsafe {
-            ABORT_SEARCH = false;
+        self.root_mov_list = tb_mov_list;
+
+        if is_dtz || root_wdl <= syzygy::WDL_DRAW {
+            self.tb_cardinality = 0;
         }
+    }
 
+    fn helper_search(&mut self, state: &mut State, max_depth: u8, helper_index: usize) {
         let in_check = mov_table::is_in_check(state, state.player);
 
+        let mut depth = 1 + (helper_index % 2) as u8;
+
+        while depth <= max_depth {
+            self.ab_search(state, in_check, false, -eval::MATE_VAL, eval::MATE_VAL, depth, 0);
+
+            if self.search_control.is_stopped() {
+                break
+            }
+
+            depth += 1;
+        }
+
+        self.report_search_stats();
+    }
+
+    fn iterative_deepening(&mut self, state: &mut State, max_depth: u8) -> (u32, i32) {
+        let in_check = mov_table::is_in_check(state, state.player);
+
+        let multi_pv = if self.multi_pv > 1 {
+            let root_mov_count = mov_table::gen_legal_mov_list(state).into_iter().filter(|mov| self.is_root_mov_allowed(*mov)).count();
+            self.multi_pv.min(root_mov_count)
+        } else {
+            1
+        };
+
         let mut alpha = -eval::MATE_VAL;
         let mut beta = eval::MATE_VAL;
 
         let mut depth = 1;
         let mut best_mov = 0;
+        let mut best_score = 0;
         let mut accumulated_time_taken = 0;
         let mut extra_time_used = false;
         let mut window_extended = false;
 
         loop {
-            unsafe {
-                NODE_COUNT = 0;
-                SEL_DEPTH = 0;
-            }
+            self.report_search_stats();
+            self.node_count = 0;
+            self.reported_node_count = 0;
+            self.sel_depth = 0;
+            self.search_control.sel_depth.store(0, Ordering::Relaxed);
+
+            let iter_start_node_count = self.search_control.get_node_count();
+
+            self.check_ponder_hit();
 
             let score = self.ab_search(state, in_check, false, alpha, beta, depth, 0);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    break
-                }
+            if self.search_control.is_stopped() {
+                break
             }
 
             if score <= alpha {
@@ -160,8 +519,8 @@ impl SearchEngine {
                     alpha = -eval::MATE_VAL;
                 }
 
-                if !extra_time_used && score < alpha && self.time_tracker.elapsed().as_millis() > self.max_time_millis / 2 {
-                    self.max_time_millis += time_capacity.extra_time_millis;
+                if !extra_time_used && score < alpha && self.budget_tracker.elapsed().as_millis() > self.max_time_millis / 2 {
+                    self.max_time_millis = self.max_time_millis.saturating_add(self.extra_time_millis);
                     extra_time_used = true;
                 }
 
@@ -179,42 +538,115 @@ impl SearchEngine {
                 continue
             }
 
-            let mut pv_table = [0; PV_TRACK_LENGTH];
-            self.retrieve_pv(state, &mut pv_table, 0);
+            let pv_table = self.retrieve_root_pv(state);
 
             let checkmate = score.abs() > eval::TERM_VAL;
 
-            let total_time_taken = self.time_tracker.elapsed().as_millis();
-
             if pv_table[0] != 0 {
                 best_mov = pv_table[0];
+                best_score = score;
+                self.ponder_mov = pv_table[1];
+
+                let mut multi_pv_list = vec![(score, pv_table)];
+
+                if multi_pv > 1 {
+                    self.excluded_root_mov_list.push(pv_table[0]);
+
+                    // the earlier lines are excluded, so the next line scores no higher than the last one
+                    let mut line_alpha = (score - WINDOW_SIZE).max(-eval::MATE_VAL);
+                    let mut line_beta = (score + 1).min(eval::MATE_VAL);
+                    let mut line_window_extended = false;
+
+                    while multi_pv_list.len() < multi_pv {
+                        let line_score = self.ab_search(state, in_check, false, line_alpha, line_beta, depth, 0);
+
+                        if self.search_control.is_stopped() {
+                            break
+                        }
+
+                        if line_score <= line_alpha && line_alpha > -eval::MATE_VAL {
+                            if !line_window_extended {
+                                line_alpha = (line_score - EXTENDED_WINDOW_SIZE).max(-eval::MATE_VAL);
+                                line_window_extended = true;
+                            } else {
+                                line_alpha = -eval::MATE_VAL;
+                            }
+
+                            continue
+                        }
+
+                        if line_score >= line_beta && line_beta < eval::MATE_VAL {
+                            line_beta = eval::MATE_VAL;
+                            continue
+                        }
 
-                unsafe {
-                    let iter_time_taken_millis = total_time_taken - accumulated_time_taken;
-                    let nps = NODE_COUNT as u128 / (iter_time_taken_millis / 1000).max(1);
+                        let pv_table = self.retrieve_root_pv(state);
 
-                    if checkmate {
-                        let mate_score = if score > 0 {
+                        if pv_table[0] == 0 {
+                            break
+                        }
+
+                        self.excluded_root_mov_list.push(pv_table[0]);
+                        multi_pv_list.push((line_score, pv_table));
+
+                        line_alpha = (line_score - WINDOW_SIZE).max(-eval::MATE_VAL);
+                        line_beta = (line_score + 1).min(eval::MATE_VAL);
+                        line_window_extended = false;
+                    }
+
+                    self.excluded_root_mov_list.clear();
+                    self.root_best_mov = best_mov;
+                }
+
+                let total_time_taken = self.time_tracker.elapsed().as_millis();
+
+                self.report_search_stats();
+
+                let node_count = self.search_control.get_node_count() - iter_start_node_count;
+                let sel_depth = self.search_control.get_sel_depth();
+                let tb_hit_count = self.search_control.get_tb_hit_count();
+
+                let iter_time_taken_millis = total_time_taken - accumulated_time_taken;
+                let nps = node_count as u128 / (iter_time_taken_millis / 1000).max(1);
+
+                for (multi_pv_index, (score, pv_table)) in multi_pv_list.iter().enumerate() {
+                    let multi_pv_info = if multi_pv > 1 {
+                        format!("multipv {} ", multi_pv_index + 1)
+                    } else {
+                        String::new()
+                    };
+
+                    if score.abs() > eval::TERM_VAL {
+                        let mate_score = if *score > 0 {
                             (eval::MATE_VAL - score + 1) / 2
                         } else {
                             (-eval::MATE_VAL - score - 1) / 2
                         };
 
-                        println!("info score mate {} depth {} seldepth {} nodes {} nps {} time {} pv {}", mate_score, depth, SEL_DEPTH, NODE_COUNT, nps, total_time_taken, util::format_pv(&pv_table));
+                        self.report_info(&format!("info {}score mate {} depth {} seldepth {} nodes {} nps {} tbhits {} time {} pv {}", multi_pv_info, mate_score, depth, sel_depth, node_count, nps, tb_hit_count, total_time_taken, uci::format_pv(state, pv_table)));
                     } else {
-                        println!("info score cp {} depth {} seldepth {} nodes {} nps {} time {} pv {}", score, depth, SEL_DEPTH, NODE_COUNT, nps, total_time_taken, util::format_pv(&pv_table));
+                        self.report_info(&format!("info {}score cp {} depth {} seldepth {} nodes {} nps {} tbhits {} time {} pv {}", multi_pv_info, score, depth, sel_depth, node_count, nps, tb_hit_count, total_time_taken, uci::format_pv(state, pv_table)));
                     }
                 }
 
-                if checkmate {
+                if self.search_control.is_stopped() {
+                    break
+                }
+
+                if checkmate && multi_pv == 1 {
                     break
                 }
     
-                if total_time_taken - accumulated_time_taken > self.max_time_millis / 2 {
+                // time spent pondering before the ponder hit is not taken from the budget
+                let iter_budget_time_taken = (total_time_taken - accumulated_time_taken).min(self.budget_tracker.elapsed().as_millis());
+
+                if iter_budget_time_taken > self.max_time_millis / 2 {
                     break
                 }
             }
 
+            let total_time_taken = self.time_tracker.elapsed().as_millis();
+
             depth += 1;
             accumulated_time_taken = total_time_taken;
 
@@ -227,21 +659,36 @@ impl SearchEngine {
             window_extended = false;
         }
 
-        best_mov
+        self.report_search_stats();
+        self.wait_for_ponder_hit();
+
+        if best_mov == 0 {
+            best_mov = mov_table::gen_legal_mov_list(state).into_iter().find(|mov| self.is_root_mov_allowed(*mov)).unwrap_or(0);
+        }
+
+        (best_mov, best_score)
     }
 
+    #[allow(clippy::too_many_arguments, clippy::single_match, clippy::needless_range_loop, clippy::needless_borrow, clippy::collapsible_if)]
     fn ab_search(&mut self, state: &mut State, in_check: bool, on_extend: bool, mut alpha: i32, mut beta: i32, depth: u8, ply: u8) -> i32 {
-        unsafe {
-            if ABORT_SEARCH {
-                return alpha
-            }
+        if self.search_control.is_stopped() {
+            return alpha
+        }
+
+        if self.is_node_limit_reached() {
+            self.search_control.stop();
+            return alpha
         }
 
-        unsafe {
-            NODE_COUNT += 1;
+        self.node_count += 1;
 
-            if (NODE_COUNT & TIME_CHECK_INTEVAL == 0) && self.time_tracker.elapsed().as_millis() > self.max_time_millis {
-                ABORT_SEARCH = true;
+        if self.node_count & TIME_CHECK_INTEVAL == 0 {
+            self.report_search_stats();
+
+            self.check_ponder_hit();
+
+            if self.budget_tracker.elapsed().as_millis() > self.max_time_millis {
+                self.search_control.stop();
                 return alpha
             }
         }
@@ -317,6 +764,26 @@ impl SearchEngine {
             _ => (),
         }
 
+        if ply > 0 && state.non_cap_mov_count == 0 && state.cas_rights == 0 {
+            let piece_count = (state.bitboard.w_all | state.bitboard.b_all).count_ones() as usize;
+
+            if piece_count < self.tb_cardinality || (piece_count == self.tb_cardinality && depth >= self.tb_probe_depth) {
+                if let Some(wdl) = self.tablebase.probe_wdl(state) {
+                    self.tb_hit_count += 1;
+
+                    let (score, hash_flag) = match wdl {
+                        syzygy::WDL_WIN => (TB_WIN_VAL - ply as i32, HASH_TYPE_BETA),
+                        syzygy::WDL_LOSS => (-TB_WIN_VAL + ply as i32, HASH_TYPE_ALPHA),
+                        _ => (wdl, HASH_TYPE_EXACT),
+                    };
+
+                    self.set_hash(state, depth.saturating_add(TB_HASH_DEPTH_BONUS).min(MAX_DEPTH), hash_flag, score, 0);
+
+                    return score
+                }
+            }
+        }
+
         if depth == 0 {
             return self.q_search(state, alpha, beta, ply)
         }
@@ -324,14 +791,16 @@ impl SearchEngine {
         let in_endgame = in_endgame(state);
 
         if ply > 0 && !on_extend && !in_check && depth <= FP_DEPTH && !in_endgame {
-            let (score, is_draw) = eval::eval_materials(state);
+            let material_entry = self.material_table.get(state.material_key, &self.eval_params);
+            let (score, is_draw) = eval::eval_materials(state, &material_entry);
 
             if is_draw {
                 return 0
             }
 
             if score - FUTILITY_MARGIN[depth as usize] > beta {
-                let score = eval::eval_state(state, score);
+                let pawn_entry = self.get_pawn_entry(state);
+                let score = eval::eval_state(state, &self.eval_params, &material_entry, &pawn_entry, score);
 
                 if score - FUTILITY_MARGIN[depth as usize] > beta {
                     return beta
@@ -350,10 +819,8 @@ impl SearchEngine {
             let scout_score = -self.ab_search(state, false, false, -beta, -beta+1, depth - depth_reduction - 1, ply + 1);
             state.undo_null_mov();
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha
-                }
+            if self.search_control.is_stopped() {
+                return alpha
             }
 
             if scout_score >= beta {
@@ -364,10 +831,8 @@ impl SearchEngine {
         if on_pv && pv_mov == 0 && depth >= IID_DEPTH {
             self.ab_search(state, in_check, on_extend, alpha, beta, depth - IID_R, ply);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha
-                }
+            if self.search_control.is_stopped() {
+                return alpha
             }
 
             match self.get_hash(state, depth) {
@@ -378,6 +843,13 @@ impl SearchEngine {
             }
         }
 
+        if ply == 0 && (!self.is_root_mov_allowed(pv_mov) || !mov_table::gen_legal_mov_list(state).contains(&pv_mov)) {
+            pv_mov = 0;
+        }
+
+        // secondary multipv lines must not replace the root entry of the first line
+        let hash_writable = ply > 0 || self.excluded_root_mov_list.is_empty();
+
         let mut mov_count = 0;
         let mut best_score = -eval::MATE_VAL;
         let mut best_mov = pv_mov;
@@ -388,7 +860,7 @@ impl SearchEngine {
 
             let (from, to, tp, promo) = util::decode_u32_mov(pv_mov);
 
-            let is_capture = state.squares[to] != 0;
+            let is_capture = tp != def::MOV_CAS && state.squares[to] != 0;
 
             state.do_mov(from, to, tp, promo);
 
@@ -408,10 +880,8 @@ impl SearchEngine {
 
             state.undo_mov(from, to, tp);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha
-                }
+            if self.search_control.is_stopped() {
+                return alpha
             }
 
             if score >= beta {
@@ -420,7 +890,9 @@ impl SearchEngine {
                     self.update_killer_table(score, ply, pv_mov);
                 }
 
-                self.set_hash(state, depth, HASH_TYPE_BETA, score, pv_mov);
+                if hash_writable {
+                    self.set_hash(state, depth, HASH_TYPE_BETA, score, pv_mov);
+                }
 
                 return score
             }
@@ -438,7 +910,11 @@ impl SearchEngine {
 
         let mut mov_list = [0; def::MAX_MOV_COUNT];
 
-        mov_table::gen_reg_mov_list(state, &mut mov_list);
+        if ply == 0 {
+            mov_table::gen_strict_mov_list(state, &mut mov_list);
+        } else {
+            mov_table::gen_reg_mov_list(state, &mut mov_list);
+        }
 
         let (primary_killer, secondary_killer) = self.get_killer_mov(ply);
 
@@ -455,12 +931,16 @@ impl SearchEngine {
                 continue
             }
 
+            if ply == 0 && !self.is_root_mov_allowed(mov) {
+                continue
+            }
+
             let (from, to, tp, promo) = util::decode_u32_mov(mov);
 
-            if state.squares[to] != 0 {
+            if tp != def::MOV_CAS && state.squares[to] != 0 {
                 ordered_mov_list.push((MAX_NON_CAP_SCORE + see(state, from, to, tp, promo), mov));
             } else if promo != 0 {
-                ordered_mov_list.push((MAX_NON_CAP_SCORE + eval::val_of(promo), mov));
+                ordered_mov_list.push((MAX_NON_CAP_SCORE + eval::val_of(&self.eval_params, promo), mov));
             } else if mov == primary_killer {
                 ordered_mov_list.push((MAX_NON_CAP_SCORE + PRIMARY_KILLER_SCORE, mov));
             } else if mov == secondary_killer {
@@ -474,10 +954,8 @@ impl SearchEngine {
             score_b.partial_cmp(&score_a).unwrap()
         });
 
-        unsafe {
-            if ABORT_SEARCH {
-                return alpha
-            }
+        if self.search_control.is_stopped() {
+            return alpha
         }
 
         for (score, mov) in ordered_mov_list {
@@ -485,7 +963,7 @@ impl SearchEngine {
 
             let (from, to, tp, promo) = util::decode_u32_mov(mov);
 
-            let is_capture = state.squares[to] != 0;
+            let is_capture = tp != def::MOV_CAS && state.squares[to] != 0;
             let is_good_capture = is_capture && score >= MAX_NON_CAP_SCORE;
 
             state.do_mov(from, to, tp, promo);
@@ -535,10 +1013,8 @@ impl SearchEngine {
 
             state.undo_mov(from, to, tp);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha
-                }
+            if self.search_control.is_stopped() {
+                return alpha
             }
 
             if score >= beta {
@@ -547,7 +1023,9 @@ impl SearchEngine {
                     self.update_killer_table(score, ply, mov);
                 }
 
-                self.set_hash(state, depth, HASH_TYPE_BETA, score, mov);
+                if hash_writable {
+                    self.set_hash(state, depth, HASH_TYPE_BETA, score, mov);
+                }
 
                 return score
             }
@@ -563,15 +1041,24 @@ impl SearchEngine {
             }
         }
 
-        if alpha > original_alpha {
-            self.set_hash(state, depth, HASH_TYPE_EXACT, alpha, best_mov);
-        } else {
-            self.set_hash(state, depth, HASH_TYPE_ALPHA, best_score, best_mov);
+        if ply == 0 {
+            self.root_best_mov = best_mov;
+        }
+
+        if hash_writable {
+            if alpha > original_alpha {
+                self.set_hash(state, depth, HASH_TYPE_EXACT, alpha, best_mov);
+            } else {
+                self.set_hash(state, depth, HASH_TYPE_ALPHA, best_score, best_mov);
+            }
         }
 
         if best_score < -eval::TERM_VAL {
             if !in_check && self.in_stale_mate(state) {
-                self.set_hash(state, MAX_DEPTH, HASH_TYPE_EXACT, 0, 0);
+                if hash_writable {
+                    self.set_hash(state, MAX_DEPTH, HASH_TYPE_EXACT, 0, 0);
+                }
+
                 return 0
             }
         }
@@ -579,15 +1066,28 @@ impl SearchEngine {
         alpha
     }
 
+    #[allow(clippy::needless_range_loop, clippy::needless_borrow)]
     fn q_search(&mut self, state: &mut State, mut alpha: i32, beta: i32, ply: u8) -> i32 {
-        unsafe {
-            if ABORT_SEARCH {
-                return alpha
-            }
+        if self.search_control.is_stopped() {
+            return alpha
         }
 
-        unsafe {
-            NODE_COUNT += 1;
+        if self.is_node_limit_reached() {
+            self.search_control.stop();
+            return alpha
+        }
+
+        self.node_count += 1;
+
+        if self.node_count & TIME_CHECK_INTEVAL == 0 {
+            self.report_search_stats();
+
+            self.check_ponder_hit();
+
+            if self.budget_tracker.elapsed().as_millis() > self.max_time_millis {
+                self.search_control.stop();
+                return alpha
+            }
         }
 
         if mov_table::is_in_check(state, def::get_opposite_player(state.player)) {
@@ -598,13 +1098,12 @@ impl SearchEngine {
             return self.ab_search(state, true, true, alpha, beta, 1, ply)
         }
 
-        unsafe {
-            if ply > SEL_DEPTH {
-                SEL_DEPTH = ply;
-            }
+        if ply > self.sel_depth {
+            self.sel_depth = ply;
         }
 
-        let (material_score, is_draw) = eval::eval_materials(state);
+        let material_entry = self.material_table.get(state.material_key, &self.eval_params);
+        let (material_score, is_draw) = eval::eval_materials(state, &material_entry);
 
         if is_draw {
             return 0
@@ -614,7 +1113,8 @@ impl SearchEngine {
             return beta
         }
 
-        let score = eval::eval_state(state, material_score);
+        let pawn_entry = self.get_pawn_entry(state);
+        let score = eval::eval_state(state, &self.eval_params, &material_entry, &pawn_entry, material_score);
 
         if score >= beta {
             return score
@@ -646,7 +1146,7 @@ impl SearchEngine {
 
             let (from, to, tp, promo) = util::decode_u32_mov(cap);
 
-            let gain = eval::val_of(squares[to]) + eval::val_of(promo);
+            let gain = eval::val_of(&self.eval_params, squares[to]) + eval::val_of(&self.eval_params, promo);
 
             if gain < delta {
                 continue
@@ -670,10 +1170,8 @@ impl SearchEngine {
             let score = -self.q_search(state, -beta, -alpha, ply + 1);
             state.undo_mov(from, to, tp);
 
-            unsafe {
-                if ABORT_SEARCH {
-                    return alpha
-                }
+            if self.search_control.is_stopped() {
+                return alpha
             }
 
             if score >= beta {
@@ -726,11 +1224,71 @@ impl SearchEngine {
         state.undo_mov(from, to, tp);
     }
 
+    fn retrieve_root_pv(&self, state: &mut State) -> [u32; PV_TRACK_LENGTH] {
+        let mut pv_table = [0; PV_TRACK_LENGTH];
+        self.retrieve_pv(state, &mut pv_table, 0);
+
+        if pv_table[0] != self.root_best_mov && self.root_best_mov != 0 {
+            pv_table = [0; PV_TRACK_LENGTH];
+            pv_table[0] = self.root_best_mov;
+
+            let (from, to, tp, promo) = util::decode_u32_mov(self.root_best_mov);
+            state.do_mov(from, to, tp, promo);
+            self.retrieve_pv(state, &mut pv_table, 1);
+            state.undo_mov(from, to, tp);
+        } else if !self.is_root_mov_allowed(pv_table[0]) {
+            pv_table = [0; PV_TRACK_LENGTH];
+        }
+
+        pv_table
+    }
+
+    /// Checks the node limit against the nodes of all search threads; nodes of the other threads are
+    /// only counted once reported, so the limit can be exceeded by a few thousand nodes per helper.
+    #[inline]
+    fn is_node_limit_reached(&self) -> bool {
+        self.search_control.get_node_count() + self.node_count - self.reported_node_count >= self.max_node_count
+    }
+
+    fn report_search_stats(&mut self) {
+        self.search_control.add_search_stats(self.node_count - self.reported_node_count, self.tb_hit_count - self.reported_tb_hit_count, self.sel_depth);
+        self.reported_node_count = self.node_count;
+        self.reported_tb_hit_count = self.tb_hit_count;
+    }
+
+    fn check_ponder_hit(&mut self) {
+        if !self.search_control.is_ponder_hit() {
+            return
+        }
+
+        if let Some(TimeCapacity { main_time_millis, extra_time_millis }) = self.ponder_time_capacity.take() {
+            self.budget_tracker = Instant::now();
+            self.max_time_millis = main_time_millis;
+            self.extra_time_millis = extra_time_millis;
+        }
+    }
+
+    fn wait_for_ponder_hit(&mut self) {
+        while self.ponder_time_capacity.is_some() {
+            if self.search_control.is_stopped() {
+                break
+            }
+
+            self.check_ponder_hit();
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[inline]
+    fn is_root_mov_allowed(&self, mov: u32) -> bool {
+        (self.root_mov_list.is_empty() || self.root_mov_list.contains(&mov)) && !self.excluded_root_mov_list.contains(&mov)
+    }
+
     #[inline]
     fn get_hash(&self, state: &State, depth: u8) -> LookupResult {
-        match self.depth_preferred_hash_table.get(state.hash_key, state.player, depth, state.cas_rights, state.enp_square) {
+        match self.depth_preferred_hash_table.get(state.hash_key, depth) {
             NoMatch => {
-                self.always_replace_hash_table.get(state.hash_key, state.player, depth, state.cas_rights, state.enp_square)
+                self.always_replace_hash_table.get(state.hash_key, depth)
             },
             matched => matched
         }
@@ -738,11 +1296,23 @@ impl SearchEngine {
 
     #[inline]
     fn set_hash(&mut self, state: &State, depth: u8, hash_flag: u8, score: i32, mov: u32) {
-        if !self.depth_preferred_hash_table.set(state.hash_key, state.player, depth, state.cas_rights, state.enp_square, hash_flag, score, mov) {
-            self.always_replace_hash_table.set(state.hash_key, state.player, depth, state.cas_rights, state.enp_square, hash_flag, score, mov);
+        if !self.depth_preferred_hash_table.set(state.hash_key, depth, hash_flag, score, mov) {
+            self.always_replace_hash_table.set(state.hash_key, depth, hash_flag, score, mov);
         }
     }
 
+    #[inline]
+    fn get_pawn_entry(&mut self, state: &State) -> PawnEntry {
+        if let Some(pawn_entry) = self.pawn_hash_table.get(state.pawn_key) {
+            return pawn_entry
+        }
+
+        let pawn_entry = eval::extract_pawn_features(state, &self.eval_params);
+        self.pawn_hash_table.set(state.pawn_key, pawn_entry);
+
+        pawn_entry
+    }
+
     #[inline]
     fn update_killer_table(&mut self, score: i32, ply: u8, mov: u32) {
         let ply_index = ply as usize;
@@ -802,6 +1372,7 @@ impl SearchEngine {
     }
 
     #[inline]
+    #[allow(clippy::needless_range_loop)]
     fn in_stale_mate(&self, state: &mut State) -> bool {
         let player = state.player;
 
@@ -856,7 +1427,7 @@ fn is_passed_pawn(state: &State, moving_piece: u8, to_index: usize) -> bool {
 }
 
 fn see(state: &mut State, from: usize, to: usize, tp: u8, promo: u8) -> i32 {
-    let initial_gain = eval::val_of(state.squares[to]) + eval::val_of(promo);
+    let initial_gain = eval::val_of(&state.eval_params, state.squares[to]) + eval::val_of(&state.eval_params, promo);
 
     state.do_mov(from, to, tp, promo);
 
@@ -876,13 +1447,21 @@ fn see_exchange(state: &mut State, to: usize, last_attacker: u8) -> i32 {
 
     state.do_mov(attack_from, to, tp, promo);
 
-    let score = (eval::val_of(last_attacker) + eval::val_of(promo) - see_exchange(state, to, attacker)).max(0);
+    let score = (eval::val_of(&state.eval_params, last_attacker) + eval::val_of(&state.eval_params, promo) - see_exchange(state, to, attacker)).max(0);
 
     state.undo_mov(attack_from, to, tp);
 
     score
 }
 
+/// A mate in n moves is found at a depth of 2n - 1 plies, so deeper iterations are not needed.
+fn get_max_depth(search_limits: &SearchLimits) -> u8 {
+    match search_limits.mate_mov_count {
+        Some(mate_mov_count) => search_limits.max_depth.min((mate_mov_count.max(1) as usize * 2 - 1).min(MAX_DEPTH as usize) as u8),
+        None => search_limits.max_depth,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -913,6 +1492,368 @@ mod tests {
         assert!(search_engine.in_stale_mate(&mut state));
     }
 
+    #[test]
+    fn test_search_to_mate() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 10", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+
+        let search_limits = SearchLimits {
+            time_capacity: TimeCapacity {
+                main_time_millis: 5000,
+                extra_time_millis: 0,
+            },
+            mate_mov_count: Some(2),
+            ..SearchLimits::default()
+        };
+
+        let best_mov = search_engine.search_with_limits(&mut state, search_limits);
+
+        let (from, to, _, _) = util::decode_u32_mov(best_mov);
+        assert_eq!(from, util::map_sqr_notation_to_index("d5"));
+        assert_eq!(to, util::map_sqr_notation_to_index("f6"));
+    }
+
+    #[test]
+    fn test_search_multi_pv() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+        search_engine.set_multi_pv(3);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: 5000,
+            extra_time_millis: 0,
+        };
+
+        let best_mov = search_engine.search(&mut state, time_capacity, 4);
+
+        let (from, to, _, _) = util::decode_u32_mov(best_mov);
+        assert_eq!(from, util::map_sqr_notation_to_index("d1"));
+        assert_eq!(to, util::map_sqr_notation_to_index("d8"));
+    }
+
+    #[test]
+    fn test_search_multi_pv_lines() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("2k2r2/pp2br2/1np1p2q/2NpP2p/2PP2p1/1P1N4/P3Q1PP/3R1R1K b - - 8 27", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+        search_engine.set_multi_pv(3);
+
+        let info_list = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink_info_list = Arc::clone(&info_list);
+        search_engine.set_info_sink(move |info| sink_info_list.lock().unwrap().push(info.to_owned()));
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: 50000,
+            extra_time_millis: 0,
+        };
+
+        let best_mov = search_engine.search(&mut state, time_capacity, 5);
+
+        match search_engine.get_hash(&state, MAX_DEPTH) {
+            Match(_, _, mov) | MovOnly(mov) => assert_eq!(best_mov, mov),
+            NoMatch => panic!("root position not in hash"),
+        }
+
+        let info_list = info_list.lock().unwrap();
+        let line_list: Vec<Vec<&str>> = info_list.iter().filter(|info| info.starts_with("info multipv ")).map(|info| info.split_whitespace().collect()).collect();
+        assert_eq!(15, line_list.len());
+
+        for depth_line_list in line_list.chunks(3) {
+            let score_list: Vec<i32> = depth_line_list.iter().map(|line| line[5].parse().unwrap()).collect();
+            assert!(score_list[0] >= score_list[1] && score_list[1] >= score_list[2], "{:?}", score_list);
+
+            let pv_mov_list: Vec<&str> = depth_line_list.iter().map(|line| line[line.iter().position(|token| *token == "pv").unwrap() + 1]).collect();
+            assert!(pv_mov_list[0] != pv_mov_list[1] && pv_mov_list[1] != pv_mov_list[2] && pv_mov_list[0] != pv_mov_list[2]);
+        }
+
+        assert_eq!(util::format_mov(best_mov), line_list[12][line_list[12].iter().position(|token| *token == "pv").unwrap() + 1]);
+    }
+
+    #[test]
+    fn test_search_info_sink() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+        search_engine.set_multi_pv(3);
+
+        let info_list = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink_info_list = Arc::clone(&info_list);
+        search_engine.set_info_sink(move |info| sink_info_list.lock().unwrap().push(info.to_owned()));
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: 5000,
+            extra_time_millis: 0,
+        };
+
+        search_engine.search(&mut state, time_capacity, 2);
+
+        let info_list = info_list.lock().unwrap();
+        assert_eq!(6, info_list.len());
+        assert!(info_list[3].starts_with("info multipv 1 score mate 1 depth 2 "));
+        assert!(info_list[5].starts_with("info multipv 3 "));
+    }
+
+    #[test]
+    fn test_search_searchmoves() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+
+        let info_list = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let sink_info_list = Arc::clone(&info_list);
+        search_engine.set_info_sink(move |info| sink_info_list.lock().unwrap().push(info.to_owned()));
+
+        let root_mov_list = vec![
+            util::encode_u32_mov(util::map_sqr_notation_to_index("h2"), util::map_sqr_notation_to_index("h3"), def::MOV_REG, 0),
+            util::encode_u32_mov(util::map_sqr_notation_to_index("d1"), util::map_sqr_notation_to_index("d2"), def::MOV_REG, 0),
+        ];
+        search_engine.set_root_mov_list(root_mov_list.clone());
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: 5000,
+            extra_time_millis: 0,
+        };
+
+        let best_mov = search_engine.search(&mut state, time_capacity, 6);
+        assert!(root_mov_list.contains(&best_mov));
+
+        let info_list = info_list.lock().unwrap();
+        let pv_info_list: Vec<&String> = info_list.iter().filter(|info| info.contains(" pv ")).collect();
+        assert!(!pv_info_list.is_empty());
+
+        for info in pv_info_list {
+            let pv_mov_str = info.split(" pv ").nth(1).unwrap().split_whitespace().next().unwrap();
+            assert!(pv_mov_str == "h2h3" || pv_mov_str == "d1d2", "{}", info);
+        }
+    }
+
+    #[test]
+    fn test_search_multi_thread() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("r2qkb1r/pp2nppp/3p4/2pNN1B1/2BnP3/3P4/PPP2PPP/R2bK2R w KQkq - 1 10", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+        search_engine.set_thread_count(4);
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: 5000,
+            extra_time_millis: 0,
+        };
+
+        let best_mov = search_engine.search(&mut state, time_capacity, 8);
+
+        let (from, to, _, _) = util::decode_u32_mov(best_mov);
+        assert_eq!(from, util::map_sqr_notation_to_index("d5"));
+        assert_eq!(to, util::map_sqr_notation_to_index("f6"));
+    }
+
+    #[test]
+    fn test_search_node_limit() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+        let search_control = search_engine.get_search_control();
+
+        let search_limits = SearchLimits {
+            time_capacity: TimeCapacity {
+                main_time_millis: 60000,
+                extra_time_millis: 0,
+            },
+            max_node_count: 100000,
+            ..SearchLimits::default()
+        };
+
+        let best_mov = search_engine.search_with_limits(&mut state, search_limits);
+
+        assert!(mov_table::gen_legal_mov_list(&state).contains(&best_mov));
+        assert!(search_control.get_node_count() >= 100000);
+        assert!(search_control.get_node_count() < 100000 + TIME_CHECK_INTEVAL + 1);
+    }
+
+    #[test]
+    fn test_search_node_limit_multi_thread() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+        let search_control = search_engine.get_search_control();
+        search_engine.set_thread_count(4);
+
+        let max_node_count = 200000;
+        let best_mov = search_engine.search_with_limits(&mut state, SearchLimits { max_node_count, ..SearchLimits::default() });
+
+        assert!(mov_table::gen_legal_mov_list(&state).contains(&best_mov));
+        assert!(search_control.get_node_count() >= max_node_count);
+        assert!(search_control.get_node_count() < max_node_count + 4 * (TIME_CHECK_INTEVAL + 1));
+    }
+
+    #[test]
+    fn test_search_eval_params() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut search_engine = SearchEngine::new(131072);
+
+        let mut state = State::new("3qk3/8/8/8/8/K7/8/3R3n w - - 0 1", &zob_keys, &bitmask);
+        let best_mov = search_engine.search(&mut state, TimeCapacity { main_time_millis: 5000, extra_time_millis: 0 }, 1);
+        assert_eq!(util::map_sqr_notation_to_index("d8"), util::decode_u32_mov(best_mov).1);
+
+        search_engine.set_eval_params(EvalParams { q_val: 100, ..EvalParams::default() });
+
+        let best_mov = search_engine.search(&mut state, TimeCapacity { main_time_millis: 5000, extra_time_millis: 0 }, 1);
+        assert_eq!(util::map_sqr_notation_to_index("h1"), util::decode_u32_mov(best_mov).1);
+        assert_eq!(100, state.eval_params.q_val);
+        assert!(state.has_consistent_eval_terms());
+    }
+
+    #[test]
+    fn test_search_pawn_hash_stats() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("8/pp3k2/2p1p1p1/3pP1P1/3P4/2P2K2/PP6/8 w - - 0 1", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+
+        assert_eq!((0, 0), search_engine.get_pawn_hash_stats());
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: 5000,
+            extra_time_millis: 0,
+        };
+
+        search_engine.search(&mut state, time_capacity, 6);
+
+        let (probe_count, hit_count) = search_engine.get_pawn_hash_stats();
+        assert!(probe_count > 0);
+        assert!(hit_count * 2 > probe_count);
+    }
+
+    #[test]
+    fn test_search_control_stop() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+        let search_control = search_engine.get_search_control();
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: u128::MAX,
+            extra_time_millis: 0,
+        };
+
+        let best_mov = std::thread::scope(|scope| {
+            let search_handle = scope.spawn(|| search_engine.search(&mut state, time_capacity, 128));
+
+            std::thread::sleep(Duration::from_millis(200));
+            search_control.stop();
+
+            search_handle.join().unwrap()
+        });
+
+        assert!(!search_control.is_stopped());
+        assert!(search_control.get_node_count() > 0);
+        assert!(mov_table::gen_legal_mov_list(&state).contains(&best_mov));
+    }
+
+    #[test]
+    fn test_search_ponder_hit() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+        let search_control = search_engine.get_search_control();
+
+        let search_limits = SearchLimits {
+            time_capacity: TimeCapacity {
+                main_time_millis: 400,
+                extra_time_millis: 0,
+            },
+            ..SearchLimits::default()
+        };
+
+        let (best_mov, time_after_ponder_hit) = std::thread::scope(|scope| {
+            let search_handle = scope.spawn(|| search_engine.ponder(&mut state, search_limits));
+
+            // pondering for longer than the budget must not use it up
+            std::thread::sleep(Duration::from_millis(600));
+            search_control.ponder_hit();
+            let ponder_hit_time = Instant::now();
+
+            let best_mov = search_handle.join().unwrap();
+            (best_mov, ponder_hit_time.elapsed().as_millis())
+        });
+
+        assert!(time_after_ponder_hit >= 200);
+        assert!(time_after_ponder_hit < 2000);
+        assert!(!search_control.is_ponder_hit());
+        assert!(mov_table::gen_legal_mov_list(&state).contains(&best_mov));
+    }
+
+    #[test]
+    fn test_search_ponder_stop() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3", &zob_keys, &bitmask);
+        let mut search_engine = SearchEngine::new(131072);
+        let search_control = search_engine.get_search_control();
+
+        let search_limits = SearchLimits {
+            time_capacity: TimeCapacity {
+                main_time_millis: 400,
+                extra_time_millis: 0,
+            },
+            ..SearchLimits::default()
+        };
+
+        let best_mov = std::thread::scope(|scope| {
+            let search_handle = scope.spawn(|| search_engine.ponder(&mut state, search_limits));
+
+            std::thread::sleep(Duration::from_millis(200));
+            search_control.stop();
+
+            search_handle.join().unwrap()
+        });
+
+        assert!(!search_control.is_stopped());
+        assert!(mov_table::gen_legal_mov_list(&state).contains(&best_mov));
+    }
+
+    #[test]
+    fn test_search_tablebase() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut tablebase = SyzygyTablebase::new();
+        tablebase.add_directory(std::path::Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/res/syzygy"))).unwrap();
+
+        let mut search_engine = SearchEngine::new(131072);
+        search_engine.set_tablebase(tablebase);
+        let search_control = search_engine.get_search_control();
+
+        let time_capacity = TimeCapacity {
+            main_time_millis: 5000,
+            extra_time_millis: 0,
+        };
+
+        let mut state = State::new("8/4r3/8/K7/4R3/8/8/5k2 w - - 0 1", &zob_keys, &bitmask);
+        let best_mov = search_engine.search(&mut state, time_capacity, 2);
+
+        let (from, to, _, _) = util::decode_u32_mov(best_mov);
+        assert_eq!(from, util::map_sqr_notation_to_index("e4"));
+        assert_eq!(to, util::map_sqr_notation_to_index("e7"));
+        assert_eq!(18, search_control.get_tb_hit_count());
+
+        let mut state = State::new("7r/8/4k3/3n4/8/8/3R4/4K3 w - - 0 1", &zob_keys, &bitmask);
+        search_engine.search(&mut state, TimeCapacity { main_time_millis: 5000, extra_time_millis: 0 }, 3);
+
+        assert!(search_control.get_tb_hit_count() > 0);
+    }
+
     #[test]
     fn test_search_1() {
         let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
@@ -5,58 +5,56 @@
 use crate::{
     bitboard::{BitMask, BitBoard},
     def,
+    eval,
+    eval_params::EvalParams,
+    material,
+    mov_table,
     util,
 };
 
-use std::fmt;
+use std::{
+    fmt,
+    sync::Arc,
+};
 
 const FEN_SQRS_INDEX: usize = 0;
 const FEN_PLAYER_INDEX: usize = 1;
 const FEN_CAS_RIGHTS_INDEX: usize = 2;
 const FEN_ENP_SQR_INDEX: usize = 3;
 const FEN_HALF_MOV_INDEX: usize = 4;
+const FEN_FULL_MOV_INDEX: usize = 5;
 const LAST_MOV_POS_INDEX: usize = 4;
 const MAX_NON_CAP_MOV_COUNT: usize = 100;
 
-const K_CAS_SQR_SIZE: usize = 4;
-const Q_CAS_SQR_SIZE: usize = 5;
-
-const WK_BEFORE_CAS_SQRS: [u8; K_CAS_SQR_SIZE] = [def::WK, 0, 0, def::WR];
-const WK_AFTER_CAS_SQRS: [u8; K_CAS_SQR_SIZE] = [0, def::WR, def::WK, 0];
-
-const BK_BEFORE_CAS_SQRS: [u8; K_CAS_SQR_SIZE] = [def::BK, 0, 0, def::BR];
-const BK_AFTER_CAS_SQRS: [u8; K_CAS_SQR_SIZE] = [0, def::BR, def::BK, 0];
-
-const WQ_BEFORE_CAS_SQRS: [u8; Q_CAS_SQR_SIZE] = [def::WR, 0, 0, 0, def::WK];
-const WQ_AFTER_CAS_SQRS: [u8; Q_CAS_SQR_SIZE] = [0, 0, def::WK, def::WR, 0];
-
-const BQ_BEFORE_CAS_SQRS: [u8; Q_CAS_SQR_SIZE] = [def::BR, 0, 0, 0, def::BK];
-const BQ_AFTER_CAS_SQRS: [u8; Q_CAS_SQR_SIZE] = [0, 0, def::BK, def::BR, 0];
-
-const WK_CAS_ALL_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_11110000;
-const WK_CAS_R_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_10100000;
-
-const BK_CAS_ALL_MASK: u64 = 0b11110000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-const BK_CAS_R_MASK: u64 = 0b10100000_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-
-const WQ_CAS_ALL_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00011101;
-const WQ_CAS_R_MASK: u64 = 0b00000000_00000000_00000000_00000000_00000000_00000000_00000000_00001001;
-
-const BQ_CAS_ALL_MASK: u64 = 0b00011101_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
-const BQ_CAS_R_MASK: u64 = 0b00001001_00000000_00000000_00000000_00000000_00000000_00000000_00000000;
+// piece codes below BP never occupy a square, so their zobrist columns are free for the non-piece keys
+const ZOB_PLAYER_CODE: usize = 0;
+const ZOB_CAS_RIGHTS_CODE: usize = 1;
+const ZOB_ENP_FILE_CODE: usize = 2;
 
+#[derive(Clone)]
 pub struct State<'state> {
     pub squares: [u8; def::BOARD_SIZE],
     pub player: u8,
     pub cas_rights: u8,
+    pub cas_rook_indexes: [usize; 4],
     pub enp_square: usize,
     pub non_cap_mov_count: u16,
+    pub full_mov_count: u16,
     pub hash_key: u64,
+    pub material_key: u64,
+    pub pawn_key: u64,
+
+    pub material_score: i32,
+    pub phase: i32,
+    pub midgame_sqr_point: i32,
+    pub endgame_sqr_point: i32,
+    pub eval_params: Arc<EvalParams>,
 
     pub wk_index: usize,
     pub bk_index: usize,
 
     pub cas_history: u8,
+    pub is_chess960: bool,
 
     pub bitboard: BitBoard,
     pub bitmask: &'state BitMask,
@@ -72,15 +70,37 @@ pub struct State<'state> {
 }
 
 impl <'state> State<'state> {
+    /// Panics if `fen_string` is malformed, but does not check that the position is legal; use `from_fen` for untrusted input.
     pub fn new(fen_string: &str, zob_keys: &'state Vec<Vec<u64>>, bitmask: &'state BitMask) -> Self {
-        let fen_segment_list: Vec<&str> = fen_string.split(" ").collect();
-        let player = get_player_from_fen(fen_segment_list[FEN_PLAYER_INDEX]);
-        let cas_rights = get_cas_rights_from_fen(fen_segment_list[FEN_CAS_RIGHTS_INDEX]);
-        let enp_square = get_enp_sqr_from_fen(fen_segment_list[FEN_ENP_SQR_INDEX]);
-        let non_cap_mov_count = fen_segment_list[FEN_HALF_MOV_INDEX].parse::<u16>().unwrap();
+        State::parse_fen(fen_string, zob_keys, bitmask).unwrap_or_else(|error| panic!("invalid fen {}: {}", fen_string, error))
+    }
+
+    /// Parses a FEN string and rejects illegal positions; the move counters may be omitted and default to `0 1`.
+    pub fn from_fen(fen_string: &str, zob_keys: &'state Vec<Vec<u64>>, bitmask: &'state BitMask) -> Result<Self, FenError> {
+        let state = State::parse_fen(fen_string, zob_keys, bitmask)?;
+        state.validate()?;
+
+        Ok(state)
+    }
+
+    #[allow(clippy::needless_range_loop)]
+    fn parse_fen(fen_string: &str, zob_keys: &'state Vec<Vec<u64>>, bitmask: &'state BitMask) -> Result<Self, FenError> {
+        let fen_segment_list: Vec<&str> = fen_string.split_whitespace().collect();
+        if fen_segment_list.len() < FEN_HALF_MOV_INDEX || fen_segment_list.len() > FEN_FULL_MOV_INDEX + 1 {
+            return Err(FenError::InvalidFieldCount(fen_segment_list.len()))
+        }
+
+        validate_sqrs_from_fen(fen_segment_list[FEN_SQRS_INDEX])?;
+
+        let player = get_player_from_fen(fen_segment_list[FEN_PLAYER_INDEX])?;
+        let enp_square = get_enp_sqr_from_fen(fen_segment_list[FEN_ENP_SQR_INDEX])?;
+        let non_cap_mov_count = get_mov_count_from_fen(fen_segment_list.get(FEN_HALF_MOV_INDEX), 0)?;
+        let full_mov_count = get_mov_count_from_fen(fen_segment_list.get(FEN_FULL_MOV_INDEX), 1)?;
 
         let mut squares = [0; def::BOARD_SIZE];
         let mut hash_key = 0;
+        let mut material_key = 0;
+        let mut pawn_key = 0;
         let mut wk_index = 0;
         let mut bk_index = 0;
         let mut bitboard = BitBoard::new();
@@ -101,6 +121,11 @@ impl <'state> State<'state> {
                     let piece = util::map_piece_char_to_code(char_code);
                     squares[index] = piece;
                     hash_key ^= zob_keys[index][piece as usize];
+                    material_key += material::get_key_unit(piece);
+
+                    if def::is_p(piece) {
+                        pawn_key ^= zob_keys[index][piece as usize];
+                    }
 
                     match piece {
                         def::WP => {
@@ -159,18 +184,35 @@ impl <'state> State<'state> {
             index -= 16;
         }
 
-        State {
+        let (cas_rights, cas_rook_indexes) = get_cas_rights_from_fen(fen_segment_list[FEN_CAS_RIGHTS_INDEX], &squares)?;
+        hash_key ^= get_pos_info_key(zob_keys, player, cas_rights, enp_square);
+
+        let eval_params = Arc::new(EvalParams::default());
+        let (material_score, phase, midgame_sqr_point, endgame_sqr_point) = get_eval_terms(&squares, &eval_params);
+
+        Ok(State {
             squares,
             player,
             cas_rights,
+            cas_rook_indexes,
             enp_square,
             non_cap_mov_count,
+            full_mov_count,
             hash_key,
+            material_key,
+            pawn_key,
+
+            material_score,
+            phase,
+            midgame_sqr_point,
+            endgame_sqr_point,
+            eval_params,
 
             wk_index,
             bk_index,
 
             cas_history: 0,
+            is_chess960: false,
 
             bitboard,
 
@@ -183,7 +225,141 @@ impl <'state> State<'state> {
 
             zob_keys,
             bitmask,
+        })
+    }
+
+    fn validate(&self) -> Result<(), FenError> {
+        for (player, king) in [(def::PLAYER_W, def::WK), (def::PLAYER_B, def::BK)] {
+            let king_count = self.squares.iter().filter(|piece| **piece == king).count();
+            if king_count != 1 {
+                return Err(FenError::InvalidKingCount(player, king_count))
+            }
+        }
+
+        for index in (0..def::DIM_SIZE).chain(def::BOARD_SIZE-def::DIM_SIZE..def::BOARD_SIZE) {
+            if def::is_p(self.squares[index]) {
+                return Err(FenError::PawnOnBackRank(util::map_index_to_sqr_notation(index)))
+            }
+        }
+
+        for cas_index in 0..def::CAS_RIGHT_BITS.len() {
+            if self.cas_rights & def::CAS_RIGHT_BITS[cas_index] == 0 {
+                continue
+            }
+
+            let (king_index, rook, back_rank_index) = if cas_index < 2 {
+                (self.wk_index, def::WR, 0)
+            } else {
+                (self.bk_index, def::BR, 7)
+            };
+
+            let rook_index = self.cas_rook_indexes[cas_index];
+            let is_rook_on_cas_side = if cas_index % 2 == 0 { rook_index > king_index } else { rook_index < king_index };
+
+            if king_index / def::DIM_SIZE != back_rank_index || rook_index / def::DIM_SIZE != back_rank_index || self.squares[rook_index] != rook || !is_rook_on_cas_side {
+                return Err(FenError::InvalidCasRights(self.format_cas_rights()))
+            }
+        }
+
+        if self.enp_square != 0 {
+            let (enp_rank_range, pushed_pawn_index, opponent_pawn) = if self.player == def::PLAYER_W {
+                (40..48, self.enp_square.wrapping_sub(8), def::BP)
+            } else {
+                (16..24, self.enp_square + 8, def::WP)
+            };
+
+            if !enp_rank_range.contains(&self.enp_square) || self.squares[self.enp_square] != 0 || self.squares[pushed_pawn_index] != opponent_pawn {
+                return Err(FenError::InvalidEnpSqr(util::map_index_to_sqr_notation(self.enp_square)))
+            }
+        }
+
+        if mov_table::is_in_check(self, def::get_opposite_player(self.player)) {
+            return Err(FenError::OpponentInCheck)
+        }
+
+        Ok(())
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut fen_string = String::new();
+
+        for rank_index in (0..def::DIM_SIZE).rev() {
+            let mut empty_sqr_count = 0;
+
+            for file_index in 0..def::DIM_SIZE {
+                let piece = self.squares[rank_index * def::DIM_SIZE + file_index];
+
+                if piece == 0 {
+                    empty_sqr_count += 1;
+                    continue
+                }
+
+                if empty_sqr_count != 0 {
+                    fen_string.push_str(&empty_sqr_count.to_string());
+                    empty_sqr_count = 0;
+                }
+
+                fen_string.push(util::map_piece_code_to_fen_char(piece));
+            }
+
+            if empty_sqr_count != 0 {
+                fen_string.push_str(&empty_sqr_count.to_string());
+            }
+
+            if rank_index != 0 {
+                fen_string.push('/');
+            }
+        }
+
+        let player_string = if self.player == def::PLAYER_W { "w" } else { "b" };
+
+        let enp_sqr_string = if self.enp_square == 0 {
+            "-".to_owned()
+        } else {
+            util::map_index_to_sqr_notation(self.enp_square)
+        };
+
+        format!("{} {} {} {} {} {}", fen_string, player_string, self.format_cas_rights(), enp_sqr_string, self.non_cap_mov_count, self.full_mov_count)
+    }
+
+    /// Formats castling rights as X-FEN: `KQkq` for the outermost rooks, the rook file otherwise.
+    fn format_cas_rights(&self) -> String {
+        if self.cas_rights == 0 {
+            return "-".to_owned()
+        }
+
+        let mut cas_rights_string = String::new();
+
+        for cas_index in 0..def::CAS_RIGHT_BITS.len() {
+            if self.cas_rights & def::CAS_RIGHT_BITS[cas_index] == 0 {
+                continue
+            }
+
+            let (rook, back_rank_start_index) = if cas_index < 2 { (def::WR, 0) } else { (def::BR, 56) };
+            let rook_index = self.cas_rook_indexes[cas_index];
+
+            let outer_sqr_range = if cas_index % 2 == 0 {
+                rook_index + 1..back_rank_start_index + def::DIM_SIZE
+            } else {
+                back_rank_start_index..rook_index
+            };
+
+            let cas_char = if outer_sqr_range.into_iter().any(|index| self.squares[index] == rook) {
+                (b'A' + (rook_index % def::DIM_SIZE) as u8) as char
+            } else if cas_index % 2 == 0 {
+                'K'
+            } else {
+                'Q'
+            };
+
+            if cas_index < 2 {
+                cas_rights_string.push(cas_char);
+            } else {
+                cas_rights_string.push(cas_char.to_ascii_lowercase());
+            }
         }
+
+        cas_rights_string
     }
 
     pub fn is_draw(&self) -> bool {
@@ -219,14 +395,18 @@ impl <'state> State<'state> {
     }
 
     pub fn do_null_mov(&mut self) {
+        self.hash_key ^= get_pos_info_key(self.zob_keys, self.player, self.cas_rights, self.enp_square);
         self.player = def::get_opposite_player(self.player);
         self.enp_sqr_stack.push(self.enp_square);
         self.enp_square = 0;
+        self.hash_key ^= get_pos_info_key(self.zob_keys, self.player, self.cas_rights, self.enp_square);
     }
 
     pub fn undo_null_mov(&mut self) {
+        self.hash_key ^= get_pos_info_key(self.zob_keys, self.player, self.cas_rights, self.enp_square);
         self.player = def::get_opposite_player(self.player);
         self.enp_square = self.enp_sqr_stack.pop().unwrap();
+        self.hash_key ^= get_pos_info_key(self.zob_keys, self.player, self.cas_rights, self.enp_square);
     }
 
     pub fn do_mov(&mut self, from: usize, to: usize, mov_type: u8, promo: u8) {
@@ -235,18 +415,24 @@ impl <'state> State<'state> {
         self.history_pos_stack.push(self.hash_key);
         self.non_cap_mov_count_stack.push(self.non_cap_mov_count);
         self.king_index_stack.push((self.wk_index, self.bk_index));
+        self.hash_key ^= get_pos_info_key(self.zob_keys, self.player, self.cas_rights, self.enp_square);
         self.enp_square = 0;
 
         match mov_type {
             def::MOV_REG => self.do_reg_mov(from, to),
             def::MOV_PROMO => self.do_promo_mov(from, to, promo),
-            def::MOV_CAS => self.do_cas_mov(to),
+            def::MOV_CAS => self.do_cas_mov(from, to),
             def::MOV_ENP => self.do_enp_mov(from, to),
             def::MOV_CR_ENP => self.do_cr_enp_mov(from, to),
             _ => panic!("invalid mov type {}", mov_type),
         }
 
+        if self.player == def::PLAYER_B {
+            self.full_mov_count += 1;
+        }
+
         self.player = def::get_opposite_player(self.player);
+        self.hash_key ^= get_pos_info_key(self.zob_keys, self.player, self.cas_rights, self.enp_square);
     }
 
     pub fn undo_mov(&mut self, from: usize, to: usize, mov_type: u8) {
@@ -260,10 +446,14 @@ impl <'state> State<'state> {
 
         self.player = def::get_opposite_player(self.player);
 
+        if self.player == def::PLAYER_B {
+            self.full_mov_count -= 1;
+        }
+
         match mov_type {
             def::MOV_REG => self.undo_reg_mov(from, to),
             def::MOV_PROMO => self.undo_promo_mov(from, to),
-            def::MOV_CAS => self.undo_cas_mov(to),
+            def::MOV_CAS => self.undo_cas_mov(from, to),
             def::MOV_ENP => self.undo_enp_mov(from, to),
             def::MOV_CR_ENP => self.undo_cr_enp_mov(from, to),
             _ => panic!("invalid mov type {}", mov_type),
@@ -278,6 +468,9 @@ impl <'state> State<'state> {
         let move_index_mask = from_index_mask ^ to_index_mask;
 
         self.hash_key ^= self.zob_keys[from][moving_piece as usize] ^ self.zob_keys[to][moving_piece as usize];
+        self.update_pawn_key(from, to, moving_piece, taken_piece);
+        self.update_eval_terms(moving_piece, from, -1);
+        self.update_eval_terms(moving_piece, to, 1);
 
         if def::on_same_side(def::PLAYER_W, moving_piece) {
             self.bitboard.w_all ^= move_index_mask;
@@ -297,12 +490,7 @@ impl <'state> State<'state> {
             },
             def::WR => {
                 self.bitboard.w_rook ^= move_index_mask;
-
-                if from == 0 {
-                    self.cas_rights &= 0b1011;
-                } else if from == 7 {
-                    self.cas_rights &= 0b0111;
-                }
+                self.clear_rook_cas_rights(from);
             },
             def::WQ => {
                 self.bitboard.w_queen ^= move_index_mask;
@@ -318,28 +506,17 @@ impl <'state> State<'state> {
             },
             def::BR => {
                 self.bitboard.b_rook ^= move_index_mask;
-
-                if from == 56 {
-                    self.cas_rights &= 0b1110;
-                } else if from == 63 {
-                    self.cas_rights &= 0b1101;
-                }
+                self.clear_rook_cas_rights(from);
             },
             def::BQ => {
                 self.bitboard.b_queen ^= move_index_mask;
             },
             def::WK => {
-                if from == 4 {
-                    self.cas_rights &= 0b0011;
-                }
-
+                self.cas_rights &= 0b0011;
                 self.wk_index = to;
             },
             def::BK => {
-                if from == 60 {
-                    self.cas_rights &= 0b1100;
-                }
-
+                self.cas_rights &= 0b1100;
                 self.bk_index = to;
             },
             _ => (),
@@ -354,6 +531,8 @@ impl <'state> State<'state> {
         } else {
             self.non_cap_mov_count = 0;
             self.hash_key ^= self.zob_keys[to][taken_piece as usize];
+            self.material_key -= material::get_key_unit(taken_piece);
+            self.update_eval_terms(taken_piece, to, -1);
 
             if def::on_same_side(def::PLAYER_W, taken_piece) {
                 self.bitboard.w_all ^= to_index_mask;
@@ -373,6 +552,7 @@ impl <'state> State<'state> {
                 },
                 def::WR => {
                     self.bitboard.w_rook ^= to_index_mask;
+                    self.clear_rook_cas_rights(to);
                 },
                 def::WQ => {
                     self.bitboard.w_queen ^= to_index_mask;
@@ -388,6 +568,7 @@ impl <'state> State<'state> {
                 },
                 def::BR => {
                     self.bitboard.b_rook ^= to_index_mask;
+                    self.clear_rook_cas_rights(to);
                 },
                 def::BQ => {
                     self.bitboard.b_queen ^= to_index_mask;
@@ -405,6 +586,10 @@ impl <'state> State<'state> {
         let moving_piece = self.squares[to];
         let taken_piece = self.taken_piece_stack.pop().unwrap();
 
+        self.update_pawn_key(from, to, moving_piece, taken_piece);
+        self.update_eval_terms(moving_piece, to, -1);
+        self.update_eval_terms(moving_piece, from, 1);
+
         self.squares[to] = taken_piece;
         self.squares[from] = moving_piece;
 
@@ -453,6 +638,9 @@ impl <'state> State<'state> {
         }
 
         if taken_piece != 0 {
+            self.material_key += material::get_key_unit(taken_piece);
+            self.update_eval_terms(taken_piece, to, 1);
+
             if def::on_same_side(def::PLAYER_W, taken_piece) {
                 self.bitboard.w_all ^= to_index_mask;
             } else {
@@ -541,9 +729,15 @@ impl <'state> State<'state> {
         }
 
         self.hash_key ^= self.zob_keys[from][moving_piece as usize] ^ self.zob_keys[to][promo as usize];
+        self.material_key = self.material_key - material::get_key_unit(moving_piece) + material::get_key_unit(promo);
+        self.pawn_key ^= self.zob_keys[from][moving_piece as usize];
+        self.update_eval_terms(moving_piece, from, -1);
+        self.update_eval_terms(promo, to, 1);
 
         if taken_piece != 0 {
             self.hash_key ^= self.zob_keys[to][taken_piece as usize];
+            self.material_key -= material::get_key_unit(taken_piece);
+            self.update_eval_terms(taken_piece, to, -1);
 
             if def::on_same_side(def::PLAYER_W, taken_piece) {
                 self.bitboard.w_all ^= to_index_mask;
@@ -560,6 +754,7 @@ impl <'state> State<'state> {
                 },
                 def::WR => {
                     self.bitboard.w_rook ^= to_index_mask;
+                    self.clear_rook_cas_rights(to);
                 },
                 def::WQ => {
                     self.bitboard.w_queen ^= to_index_mask;
@@ -572,6 +767,7 @@ impl <'state> State<'state> {
                 },
                 def::BR => {
                     self.bitboard.b_rook ^= to_index_mask;
+                    self.clear_rook_cas_rights(to);
                 },
                 def::BQ => {
                     self.bitboard.b_queen ^= to_index_mask;
@@ -600,6 +796,15 @@ impl <'state> State<'state> {
         self.squares[to] = taken_piece;
         self.squares[from] = moving_piece;
 
+        self.material_key = self.material_key + material::get_key_unit(moving_piece) + material::get_key_unit(taken_piece) - material::get_key_unit(promo);
+        self.pawn_key ^= self.zob_keys[from][moving_piece as usize];
+        self.update_eval_terms(promo, to, -1);
+        self.update_eval_terms(moving_piece, from, 1);
+
+        if taken_piece != 0 {
+            self.update_eval_terms(taken_piece, to, 1);
+        }
+
         let from_index_mask = self.bitmask.index_masks[from];
         let to_index_mask = self.bitmask.index_masks[to];
 
@@ -678,84 +883,140 @@ impl <'state> State<'state> {
         }
     }
 
-    fn do_cas_mov(&mut self, to: usize) {
-        self.non_cap_mov_count = 0;
+    fn do_cas_mov(&mut self, from: usize, to: usize) {
+        let cas_index = def::get_cas_index(to);
+        let rook_from = self.cas_rook_indexes[cas_index];
+        let rook_to = def::CAS_ROOK_SQUARES[cas_index];
+
+        let index_masks = &self.bitmask.index_masks;
+        let all_mask_change = (index_masks[from] | index_masks[rook_from]) ^ (index_masks[to] | index_masks[rook_to]);
+        let rook_mask_change = index_masks[rook_from] ^ index_masks[rook_to];
 
-        if to == def::CAS_SQUARE_WK {
+        let (king, rook) = if cas_index < 2 {
             self.cas_rights &= 0b0011;
             self.cas_history |= 0b1100;
             self.wk_index = to;
+            self.bitboard.w_all ^= all_mask_change;
+            self.bitboard.w_rook ^= rook_mask_change;
 
-            self.squares[def::CAS_SQUARE_WK-2..=def::CAS_SQUARE_WK+1].copy_from_slice(&WK_AFTER_CAS_SQRS);
-            self.hash_key ^= self.zob_keys[def::CAS_SQUARE_WK-2][def::WK as usize]
-                ^ self.zob_keys[def::CAS_SQUARE_WK][def::WK as usize]
-                ^ self.zob_keys[def::CAS_SQUARE_WK-1][def::WR as usize]
-                ^ self.zob_keys[def::CAS_SQUARE_WK+1][def::WR as usize];
-            self.bitboard.w_all ^= WK_CAS_ALL_MASK;
-            self.bitboard.w_rook ^= WK_CAS_R_MASK;
-        } else if to == def::CAS_SQUARE_BK {
+            (def::WK, def::WR)
+        } else {
             self.cas_rights &= 0b1100;
             self.cas_history |= 0b0011;
             self.bk_index = to;
+            self.bitboard.b_all ^= all_mask_change;
+            self.bitboard.b_rook ^= rook_mask_change;
 
-            self.squares[def::CAS_SQUARE_BK-2..=def::CAS_SQUARE_BK+1].copy_from_slice(&BK_AFTER_CAS_SQRS);
-            self.hash_key ^= self.zob_keys[def::CAS_SQUARE_BK-2][def::BK as usize]
-            ^ self.zob_keys[def::CAS_SQUARE_BK][def::BK as usize]
-            ^ self.zob_keys[def::CAS_SQUARE_BK-1][def::BR as usize]
-            ^ self.zob_keys[def::CAS_SQUARE_BK+1][def::BR as usize];
+            (def::BK, def::BR)
+        };
 
-            self.bitboard.b_all ^= BK_CAS_ALL_MASK;
-            self.bitboard.b_rook ^= BK_CAS_R_MASK;
-        } else if to == def::CAS_SQUARE_WQ {
-            self.cas_rights &= 0b0011;
-            self.cas_history |= 0b1100;
-            self.wk_index = to;
+        self.squares[from] = 0;
+        self.squares[rook_from] = 0;
+        self.squares[to] = king;
+        self.squares[rook_to] = rook;
 
-            self.squares[def::CAS_SQUARE_WQ-2..=def::CAS_SQUARE_WQ+2].copy_from_slice(&WQ_AFTER_CAS_SQRS);
-            self.hash_key ^= self.zob_keys[def::CAS_SQUARE_WQ+2][def::WK as usize]
-                ^ self.zob_keys[def::CAS_SQUARE_WQ][def::WK as usize]
-                ^ self.zob_keys[def::CAS_SQUARE_WQ-2][def::WR as usize]
-                ^ self.zob_keys[def::CAS_SQUARE_WQ+1][def::WR as usize];
+        self.hash_key ^= self.zob_keys[from][king as usize]
+            ^ self.zob_keys[to][king as usize]
+            ^ self.zob_keys[rook_from][rook as usize]
+            ^ self.zob_keys[rook_to][rook as usize];
 
-            self.bitboard.w_all ^= WQ_CAS_ALL_MASK;
-            self.bitboard.w_rook ^= WQ_CAS_R_MASK;
-        } else if to == def::CAS_SQUARE_BQ {
-            self.cas_rights &= 0b1100;
-            self.cas_history |= 0b0011;
-            self.bk_index = to;
-
-            self.squares[def::CAS_SQUARE_BQ-2..=def::CAS_SQUARE_BQ+2].copy_from_slice(&BQ_AFTER_CAS_SQRS);
-            self.hash_key ^= self.zob_keys[def::CAS_SQUARE_BQ+2][def::BK as usize]
-            ^ self.zob_keys[def::CAS_SQUARE_BQ][def::BK as usize]
-            ^ self.zob_keys[def::CAS_SQUARE_BQ-2][def::BR as usize]
-            ^ self.zob_keys[def::CAS_SQUARE_BQ+1][def::BR as usize];
+        self.update_eval_terms(king, from, -1);
+        self.update_eval_terms(rook, rook_from, -1);
+        self.update_eval_terms(king, to, 1);
+        self.update_eval_terms(rook, rook_to, 1);
 
-            self.bitboard.b_all ^= BQ_CAS_ALL_MASK;
-            self.bitboard.b_rook ^= BQ_CAS_R_MASK;
-        }
+        self.non_cap_mov_count = 0;
     }
 
-    fn undo_cas_mov(&mut self, to: usize) {
-        if to == def::CAS_SQUARE_WK {
-            self.squares[def::CAS_SQUARE_WK-2..=def::CAS_SQUARE_WK+1].copy_from_slice(&WK_BEFORE_CAS_SQRS);
-            self.bitboard.w_all ^= WK_CAS_ALL_MASK;
-            self.bitboard.w_rook ^= WK_CAS_R_MASK;
-            self.cas_history &= 0b0011;
-        } else if to == def::CAS_SQUARE_BK {
-            self.squares[def::CAS_SQUARE_BK-2..=def::CAS_SQUARE_BK+1].copy_from_slice(&BK_BEFORE_CAS_SQRS);
-            self.bitboard.b_all ^= BK_CAS_ALL_MASK;
-            self.bitboard.b_rook ^= BK_CAS_R_MASK;
-            self.cas_history &= 0b1100;
-        } else if to == def::CAS_SQUARE_WQ {
-            self.squares[def::CAS_SQUARE_WQ-2..=def::CAS_SQUARE_WQ+2].copy_from_slice(&WQ_BEFORE_CAS_SQRS);
-            self.bitboard.w_all ^= WQ_CAS_ALL_MASK;
-            self.bitboard.w_rook ^= WQ_CAS_R_MASK;
+    fn undo_cas_mov(&mut self, from: usize, to: usize) {
+        let cas_index = def::get_cas_index(to);
+        let rook_from = self.cas_rook_indexes[cas_index];
+        let rook_to = def::CAS_ROOK_SQUARES[cas_index];
+
+        let index_masks = &self.bitmask.index_masks;
+        let all_mask_change = (index_masks[from] | index_masks[rook_from]) ^ (index_masks[to] | index_masks[rook_to]);
+        let rook_mask_change = index_masks[rook_from] ^ index_masks[rook_to];
+
+        let (king, rook) = if cas_index < 2 {
             self.cas_history &= 0b0011;
-        } else if to == def::CAS_SQUARE_BQ {
-            self.squares[def::CAS_SQUARE_BQ-2..=def::CAS_SQUARE_BQ+2].copy_from_slice(&BQ_BEFORE_CAS_SQRS);
-            self.bitboard.b_all ^= BQ_CAS_ALL_MASK;
-            self.bitboard.b_rook ^= BQ_CAS_R_MASK;
+            self.bitboard.w_all ^= all_mask_change;
+            self.bitboard.w_rook ^= rook_mask_change;
+
+            (def::WK, def::WR)
+        } else {
             self.cas_history &= 0b1100;
+            self.bitboard.b_all ^= all_mask_change;
+            self.bitboard.b_rook ^= rook_mask_change;
+
+            (def::BK, def::BR)
+        };
+
+        self.squares[to] = 0;
+        self.squares[rook_to] = 0;
+        self.squares[from] = king;
+        self.squares[rook_from] = rook;
+
+        self.update_eval_terms(king, to, -1);
+        self.update_eval_terms(rook, rook_to, -1);
+        self.update_eval_terms(king, from, 1);
+        self.update_eval_terms(rook, rook_from, 1);
+    }
+
+    /// Toggles the pawn key for a regular move; applying it twice restores the key.
+    #[inline]
+    fn update_pawn_key(&mut self, from: usize, to: usize, moving_piece: u8, taken_piece: u8) {
+        if def::is_p(moving_piece) {
+            self.pawn_key ^= self.zob_keys[from][moving_piece as usize] ^ self.zob_keys[to][moving_piece as usize];
+        }
+
+        if def::is_p(taken_piece) {
+            self.pawn_key ^= self.zob_keys[to][taken_piece as usize];
+        }
+    }
+
+    /// Adds (`sign` 1) or removes (`sign` -1) the contribution of `piece` on `index` to the incrementally updated evaluation terms.
+    #[inline]
+    fn update_eval_terms(&mut self, piece: u8, index: usize, sign: i32) {
+        let side_sign = if def::on_same_side(def::PLAYER_W, piece) {
+            sign
+        } else {
+            -sign
+        };
+
+        let (midgame_sqr_point, endgame_sqr_point) = eval::get_sqr_points(&self.eval_params, piece, index);
+
+        self.midgame_sqr_point += midgame_sqr_point * side_sign;
+        self.endgame_sqr_point += endgame_sqr_point * side_sign;
+        self.phase += material::get_phase_weight(piece) * sign;
+
+        if !def::is_k(piece) {
+            self.material_score += eval::val_of(&self.eval_params, piece) * side_sign;
+        }
+    }
+
+    /// Switches the piece values and piece-square tables the evaluation terms are computed with, and
+    /// recomputes the terms from the board.
+    pub fn set_eval_params(&mut self, eval_params: Arc<EvalParams>) {
+        let (material_score, phase, midgame_sqr_point, endgame_sqr_point) = get_eval_terms(&self.squares, &eval_params);
+
+        self.material_score = material_score;
+        self.phase = phase;
+        self.midgame_sqr_point = midgame_sqr_point;
+        self.endgame_sqr_point = endgame_sqr_point;
+        self.eval_params = eval_params;
+    }
+
+    /// Checks the incrementally updated evaluation terms against a full recomputation from the board.
+    pub fn has_consistent_eval_terms(&self) -> bool {
+        get_eval_terms(&self.squares, &self.eval_params) == (self.material_score, self.phase, self.midgame_sqr_point, self.endgame_sqr_point)
+    }
+
+    #[inline]
+    fn clear_rook_cas_rights(&mut self, rook_index: usize) {
+        for cas_index in 0..def::CAS_RIGHT_BITS.len() {
+            if self.cas_rook_indexes[cas_index] == rook_index {
+                self.cas_rights &= !def::CAS_RIGHT_BITS[cas_index];
+            }
         }
     }
 
@@ -798,6 +1059,11 @@ impl <'state> State<'state> {
         }
 
         self.hash_key ^= self.zob_keys[from][moving_piece as usize] ^ self.zob_keys[to][moving_piece as usize] ^ self.zob_keys[taken_index][taken_piece as usize];
+        self.material_key -= material::get_key_unit(taken_piece);
+        self.pawn_key ^= self.zob_keys[from][moving_piece as usize] ^ self.zob_keys[to][moving_piece as usize] ^ self.zob_keys[taken_index][taken_piece as usize];
+        self.update_eval_terms(moving_piece, from, -1);
+        self.update_eval_terms(moving_piece, to, 1);
+        self.update_eval_terms(taken_piece, taken_index, -1);
 
         self.taken_piece_stack.push(taken_piece);
         self.squares[to] = moving_piece;
@@ -815,6 +1081,11 @@ impl <'state> State<'state> {
         let moving_piece = self.squares[to];
         let taken_piece = self.taken_piece_stack.pop().unwrap();
 
+        self.material_key += material::get_key_unit(taken_piece);
+        self.pawn_key ^= self.zob_keys[from][moving_piece as usize] ^ self.zob_keys[to][moving_piece as usize] ^ self.zob_keys[taken_index][taken_piece as usize];
+        self.update_eval_terms(moving_piece, to, -1);
+        self.update_eval_terms(moving_piece, from, 1);
+        self.update_eval_terms(taken_piece, taken_index, 1);
         self.squares[taken_index] = taken_piece;
         self.squares[from] = moving_piece;
         self.squares[to] = 0;
@@ -859,6 +1130,9 @@ impl <'state> State<'state> {
         let move_index_mask = self.bitmask.index_masks[from] ^ self.bitmask.index_masks[to];
 
         self.hash_key ^= self.zob_keys[from][moving_piece as usize] ^ self.zob_keys[to][moving_piece as usize];
+        self.pawn_key ^= self.zob_keys[from][moving_piece as usize] ^ self.zob_keys[to][moving_piece as usize];
+        self.update_eval_terms(moving_piece, from, -1);
+        self.update_eval_terms(moving_piece, to, 1);
 
         if def::on_same_side(def::PLAYER_W, moving_piece) {
             self.bitboard.w_all ^= move_index_mask;
@@ -875,6 +1149,10 @@ impl <'state> State<'state> {
     fn undo_cr_enp_mov(&mut self, from: usize, to: usize) {
         let moving_piece = self.squares[to];
 
+        self.pawn_key ^= self.zob_keys[from][moving_piece as usize] ^ self.zob_keys[to][moving_piece as usize];
+        self.update_eval_terms(moving_piece, to, -1);
+        self.update_eval_terms(moving_piece, from, 1);
+
         self.squares[from] = moving_piece;
         self.squares[to] = 0;
 
@@ -891,6 +1169,7 @@ impl <'state> State<'state> {
 }
 
 impl <'state> fmt::Display for State <'state> {
+    #[allow(clippy::single_char_add_str)]
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         let mut display_string = String::new();
 
@@ -914,46 +1193,179 @@ impl <'state> fmt::Display for State <'state> {
     }
 }
 
-fn get_player_from_fen(fen_player_string: &str) -> u8 {
-    match fen_player_string {
-        "w" => def::PLAYER_W,
-        "b" => def::PLAYER_B,
-        _ => panic!("invalid player {}", fen_player_string),
+#[derive(PartialEq, Debug)]
+pub enum FenError {
+    InvalidFieldCount(usize),
+    InvalidSqrs(String),
+    InvalidPlayer(String),
+    InvalidCasRights(String),
+    InvalidEnpSqr(String),
+    InvalidMovCount(String),
+    InvalidKingCount(u8, usize),
+    PawnOnBackRank(String),
+    OpponentInCheck,
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FenError::InvalidFieldCount(count) => write!(formatter, "expected 4 to 6 fields, found {}", count),
+            FenError::InvalidSqrs(sqrs_string) => write!(formatter, "invalid piece placement {}", sqrs_string),
+            FenError::InvalidPlayer(player_string) => write!(formatter, "invalid side to move {}", player_string),
+            FenError::InvalidCasRights(cas_rights_string) => write!(formatter, "invalid castling rights {}", cas_rights_string),
+            FenError::InvalidEnpSqr(enp_sqr_string) => write!(formatter, "invalid en passant square {}", enp_sqr_string),
+            FenError::InvalidMovCount(count_string) => write!(formatter, "invalid move counter {}", count_string),
+            FenError::InvalidKingCount(player, count) => {
+                let player_name = if *player == def::PLAYER_W { "white" } else { "black" };
+                write!(formatter, "{} has {} kings", player_name, count)
+            },
+            FenError::PawnOnBackRank(sqr_string) => write!(formatter, "pawn on back rank at {}", sqr_string),
+            FenError::OpponentInCheck => write!(formatter, "side not to move is in check"),
+        }
     }
 }
 
-fn get_cas_rights_from_fen(fen_cas_rights_player: &str) -> u8 {
-    if fen_cas_rights_player == "-" {
-        return 0
+/// Returns the material score, raw phase and midgame/endgame piece-square sums of the board, all from the point of view of white.
+fn get_eval_terms(squares: &[u8; def::BOARD_SIZE], eval_params: &EvalParams) -> (i32, i32, i32, i32) {
+    let mut material_score = 0;
+    let mut phase = 0;
+    let mut midgame_sqr_point = 0;
+    let mut endgame_sqr_point = 0;
+
+    for (index, &piece) in squares.iter().enumerate() {
+        if piece == 0 {
+            continue
+        }
+
+        let side_sign = if def::on_same_side(def::PLAYER_W, piece) {
+            1
+        } else {
+            -1
+        };
+
+        let (piece_midgame_sqr_point, piece_endgame_sqr_point) = eval::get_sqr_points(eval_params, piece, index);
+
+        midgame_sqr_point += piece_midgame_sqr_point * side_sign;
+        endgame_sqr_point += piece_endgame_sqr_point * side_sign;
+        phase += material::get_phase_weight(piece);
+
+        if !def::is_k(piece) {
+            material_score += eval::val_of(eval_params, piece) * side_sign;
+        }
     }
 
-    let mut cas_rights = 0;
+    (material_score, phase, midgame_sqr_point, endgame_sqr_point)
+}
+
+/// Side to move, castling rights and the en passant file, hashed on top of the piece-square keys.
+#[inline]
+fn get_pos_info_key(zob_keys: &[Vec<u64>], player: u8, cas_rights: u8, enp_square: usize) -> u64 {
+    let player_key = if player == def::PLAYER_B { zob_keys[0][ZOB_PLAYER_CODE] } else { 0 };
+    let enp_key = if enp_square == 0 { 0 } else { zob_keys[enp_square % def::DIM_SIZE][ZOB_ENP_FILE_CODE] };
 
-    if fen_cas_rights_player.contains("K") {
-        cas_rights |= 0b1000;
+    player_key ^ zob_keys[cas_rights as usize][ZOB_CAS_RIGHTS_CODE] ^ enp_key
+}
+
+fn validate_sqrs_from_fen(fen_sqrs_string: &str) -> Result<(), FenError> {
+    let rank_string_list: Vec<&str> = fen_sqrs_string.split('/').collect();
+    if rank_string_list.len() != def::DIM_SIZE {
+        return Err(FenError::InvalidSqrs(fen_sqrs_string.to_owned()))
     }
 
-    if fen_cas_rights_player.contains("Q") {
-        cas_rights |= 0b0100;
+    for rank_string in rank_string_list {
+        let mut file_count = 0;
+
+        for char_code in rank_string.chars() {
+            match char_code {
+                '1'..='8' => file_count += char_code.to_digit(10).unwrap() as usize,
+                'K' | 'Q' | 'R' | 'B' | 'N' | 'P' | 'k' | 'q' | 'r' | 'b' | 'n' | 'p' => file_count += 1,
+                _ => return Err(FenError::InvalidSqrs(fen_sqrs_string.to_owned())),
+            }
+        }
+
+        if file_count != def::DIM_SIZE {
+            return Err(FenError::InvalidSqrs(fen_sqrs_string.to_owned()))
+        }
     }
 
-    if fen_cas_rights_player.contains("k") {
-        cas_rights |= 0b0010;
+    Ok(())
+}
+
+fn get_player_from_fen(fen_player_string: &str) -> Result<u8, FenError> {
+    match fen_player_string {
+        "w" => Ok(def::PLAYER_W),
+        "b" => Ok(def::PLAYER_B),
+        _ => Err(FenError::InvalidPlayer(fen_player_string.to_owned())),
     }
+}
+
+/// Accepts `KQkq` as well as X-FEN/Shredder-FEN rook files; `K`/`Q` pick the outermost rook on that side of the king.
+fn get_cas_rights_from_fen(fen_cas_rights_string: &str, squares: &[u8; def::BOARD_SIZE]) -> Result<(u8, [usize; 4]), FenError> {
+    let mut cas_rook_indexes = def::DEFAULT_CAS_ROOK_INDEXES;
 
-    if fen_cas_rights_player.contains("q") {
-        cas_rights |= 0b0001;
+    if fen_cas_rights_string == "-" {
+        return Ok((0, cas_rook_indexes))
     }
 
-    cas_rights
+    let mut cas_rights = 0;
+
+    for char_code in fen_cas_rights_string.chars() {
+        let (king, rook, back_rank_start_index) = if char_code.is_ascii_uppercase() {
+            (def::WK, def::WR, 0)
+        } else {
+            (def::BK, def::BR, 56)
+        };
+
+        let back_rank_range = back_rank_start_index..back_rank_start_index + def::DIM_SIZE;
+        let king_index = back_rank_range.clone().find(|index| squares[*index] == king);
+
+        let (cas_index, rook_index) = match (char_code.to_ascii_uppercase(), king_index) {
+            ('K', Some(king_index)) => (0, (king_index + 1..back_rank_range.end).rev().find(|index| squares[*index] == rook)),
+            ('Q', Some(king_index)) => (1, (back_rank_range.start..king_index).find(|index| squares[*index] == rook)),
+            ('K', None) => (0, None),
+            ('Q', None) => (1, None),
+            ('A'..='H', Some(king_index)) => {
+                let rook_index = back_rank_start_index + (char_code.to_ascii_uppercase() as u8 - b'A') as usize;
+                (if rook_index > king_index { 0 } else { 1 }, Some(rook_index))
+            },
+            _ => return Err(FenError::InvalidCasRights(fen_cas_rights_string.to_owned())),
+        };
+
+        let cas_index = if king == def::WK { cas_index } else { cas_index + 2 };
+        let cas_bit = def::CAS_RIGHT_BITS[cas_index];
+
+        if cas_rights & cas_bit != 0 {
+            return Err(FenError::InvalidCasRights(fen_cas_rights_string.to_owned()))
+        }
+
+        cas_rights |= cas_bit;
+
+        if let Some(rook_index) = rook_index {
+            cas_rook_indexes[cas_index] = rook_index;
+        }
+    }
+
+    Ok((cas_rights, cas_rook_indexes))
 }
 
-fn get_enp_sqr_from_fen(fen_enp_sqr_string: &str) -> usize {
+fn get_enp_sqr_from_fen(fen_enp_sqr_string: &str) -> Result<usize, FenError> {
     if fen_enp_sqr_string == "-" {
-        return 0
+        return Ok(0)
     }
 
-    util::map_sqr_notation_to_index(fen_enp_sqr_string)
+    let sqr_chars: Vec<char> = fen_enp_sqr_string.chars().collect();
+    if sqr_chars.len() != 2 || !('a'..='h').contains(&sqr_chars[0]) || !('1'..='8').contains(&sqr_chars[1]) {
+        return Err(FenError::InvalidEnpSqr(fen_enp_sqr_string.to_owned()))
+    }
+
+    Ok(util::map_sqr_notation_to_index(fen_enp_sqr_string))
+}
+
+fn get_mov_count_from_fen(fen_mov_count_string: Option<&&str>, default_count: u16) -> Result<u16, FenError> {
+    match fen_mov_count_string {
+        Some(count_string) => count_string.parse::<u16>().map_err(|_| FenError::InvalidMovCount((*count_string).to_owned())),
+        None => Ok(default_count),
+    }
 }
 
 #[cfg(test)]
@@ -1016,7 +1428,7 @@ mod tests {
         let b_all = state.bitboard.b_all;
 
         state.do_mov(util::map_sqr_notation_to_index("b7"), util::map_sqr_notation_to_index("a8"), def::MOV_PROMO, def::WQ);
-        assert_eq!(0b1111, state.cas_rights);
+        assert_eq!(0b1110, state.cas_rights);
         assert_eq!(0, state.enp_square);
         assert_eq!(def::PLAYER_B, state.player);
         assert_eq!(def::WQ, state.squares[util::map_sqr_notation_to_index("a8")]);
@@ -1251,6 +1663,173 @@ mod tests {
         assert_eq!(state.hash_key, hash_after_castle);
     }
 
+    #[test]
+    fn test_material_key() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let material_key = |fen: &str| State::new(fen, &zob_keys, &bitmask).material_key;
+
+        let mut state = State::new("r3k3/1P6/8/3pP3/8/8/8/4K2R w K d6 0 1", &zob_keys, &bitmask);
+        let original_material_key = state.material_key;
+
+        let mov_list = [
+            ("e5", "d6", def::MOV_ENP, 0),
+            ("e8", "d7", def::MOV_REG, 0),
+            ("b7", "a8", def::MOV_PROMO, def::WQ),
+            ("d7", "d6", def::MOV_REG, 0),
+            ("e1", "g1", def::MOV_CAS, 0),
+        ];
+
+        for (from, to, tp, promo) in mov_list {
+            state.do_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), tp, promo);
+            assert_eq!(material_key(&state.to_fen()), state.material_key, "{}", state.to_fen());
+        }
+
+        assert_eq!(material_key("Q7/8/3k4/8/8/8/8/5RK1 b - - 0 3"), state.material_key);
+
+        for (from, to, tp, _) in mov_list.iter().rev() {
+            state.undo_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), *tp);
+        }
+
+        assert_eq!(original_material_key, state.material_key);
+    }
+
+    #[test]
+    fn test_pawn_key() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let pawn_key = |fen: &str| State::new(fen, &zob_keys, &bitmask).pawn_key;
+
+        assert_eq!(pawn_key("4k3/pp6/8/8/8/8/1P6/4K3 w - - 0 1"), pawn_key("2r1k3/pp6/8/8/8/8/1P6/R3K3 b - - 0 1"));
+        assert_ne!(pawn_key("4k3/pp6/8/8/8/8/1P6/4K3 w - - 0 1"), pawn_key("4k3/pp6/8/8/8/1P6/8/4K3 w - - 0 1"));
+
+        let mut state = State::new("r3k3/1P6/8/3pP3/8/8/2p3K1/1N6 w - d6 0 1", &zob_keys, &bitmask);
+        let original_pawn_key = state.pawn_key;
+
+        let mov_list = [
+            ("e5", "d6", def::MOV_ENP, 0),
+            ("c2", "b1", def::MOV_PROMO, def::BQ),
+            ("b7", "a8", def::MOV_PROMO, def::WQ),
+            ("e8", "d7", def::MOV_REG, 0),
+            ("g2", "g3", def::MOV_REG, 0),
+            ("d7", "d6", def::MOV_REG, 0),
+        ];
+
+        for (from, to, tp, promo) in mov_list {
+            state.do_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), tp, promo);
+            assert_eq!(pawn_key(&state.to_fen()), state.pawn_key, "{}", state.to_fen());
+        }
+
+        assert_eq!(0, state.pawn_key);
+
+        for (from, to, tp, _) in mov_list.iter().rev() {
+            state.undo_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), *tp);
+        }
+
+        assert_eq!(original_pawn_key, state.pawn_key);
+    }
+
+    #[test]
+    fn test_eval_terms() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let state = State::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &zob_keys, &bitmask);
+        assert_eq!(0, state.material_score);
+        assert_eq!(material::TOTAL_PHASE, state.phase);
+
+        let mut state = State::new("r3k2r/1P6/8/3pP3/8/8/2p4P/RN2K2R w KQkq d6 0 1", &zob_keys, &bitmask);
+        let original_eval_terms = (state.material_score, state.phase, state.midgame_sqr_point, state.endgame_sqr_point);
+
+        let mov_list = [
+            ("e5", "d6", def::MOV_ENP, 0),
+            ("e8", "c8", def::MOV_CAS, 0),
+            ("e1", "g1", def::MOV_CAS, 0),
+            ("c2", "b1", def::MOV_PROMO, def::BN),
+            ("h2", "h4", def::MOV_CR_ENP, 0),
+            ("d8", "d6", def::MOV_REG, 0),
+            ("b7", "b8", def::MOV_PROMO, def::WQ),
+        ];
+
+        for (from, to, tp, promo) in mov_list {
+            state.do_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), tp, promo);
+            assert!(state.has_consistent_eval_terms(), "{}", state.to_fen());
+        }
+
+        for (from, to, tp, _) in mov_list.iter().rev() {
+            state.undo_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), *tp);
+            assert!(state.has_consistent_eval_terms(), "{}", state.to_fen());
+        }
+
+        assert_eq!(original_eval_terms, (state.material_score, state.phase, state.midgame_sqr_point, state.endgame_sqr_point));
+
+        let mut eval_params = EvalParams::default();
+        eval_params.p_val += 10;
+        state.set_eval_params(Arc::new(eval_params));
+        assert_eq!(original_eval_terms.0 + 10, state.material_score);
+
+        for (from, to, tp, promo) in mov_list {
+            state.do_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), tp, promo);
+            assert!(state.has_consistent_eval_terms(), "{}", state.to_fen());
+        }
+    }
+
+    #[test]
+    fn test_zob_hash_pos_info() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let hash_key = |fen: &str| State::new(fen, &zob_keys, &bitmask).hash_key;
+
+        let fen = "r3k2r/pppq1ppp/2n2n2/3pp3/1b1PP1b1/2N2N2/PPPQ1PPP/R3KB1R w KQkq - 4 9";
+        assert_ne!(hash_key(fen), hash_key("r3k2r/pppq1ppp/2n2n2/3pp3/1b1PP1b1/2N2N2/PPPQ1PPP/R3KB1R b KQkq - 4 9"));
+        assert_ne!(hash_key(fen), hash_key("r3k2r/pppq1ppp/2n2n2/3pp3/1b1PP1b1/2N2N2/PPPQ1PPP/R3KB1R w Qkq - 4 9"));
+        assert_ne!(hash_key("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1"), hash_key("4k3/8/8/3pP3/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(hash_key(fen), hash_key("r3k2r/pppq1ppp/2n2n2/3pp3/1b1PP1b1/2N2N2/PPPQ1PPP/R3KB1R w KQkq - 0 1"));
+
+        let mut state = State::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", &zob_keys, &bitmask);
+        let mov_list = [
+            ("e2", "e4", def::MOV_CR_ENP),
+            ("d7", "d5", def::MOV_CR_ENP),
+            ("e4", "e5", def::MOV_REG),
+            ("f7", "f5", def::MOV_CR_ENP),
+            ("e5", "f6", def::MOV_ENP),
+            ("e8", "f7", def::MOV_REG),
+            ("h1", "h2", def::MOV_REG),
+        ];
+
+        for (from, to, tp) in mov_list {
+            state.do_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), tp, 0);
+            assert_eq!(hash_key(&state.to_fen()), state.hash_key, "{}", state.to_fen());
+        }
+
+        let hash_before_null_mov = state.hash_key;
+        state.do_null_mov();
+        assert_eq!(hash_key(&state.to_fen()), state.hash_key);
+        state.undo_null_mov();
+        assert_eq!(hash_before_null_mov, state.hash_key);
+    }
+
+    #[test]
+    fn test_is_draw_cas_rights() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new("r3k3/8/8/8/8/8/8/4K2R w Kq - 0 1", &zob_keys, &bitmask);
+
+        let mov_list = [("h1", "h2"), ("a8", "a7"), ("h2", "h1"), ("a7", "a8")];
+
+        for (from, to) in mov_list {
+            state.do_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), def::MOV_REG, 0);
+        }
+
+        assert!(!state.is_draw());
+
+        for (from, to) in mov_list {
+            state.do_mov(util::map_sqr_notation_to_index(from), util::map_sqr_notation_to_index(to), def::MOV_REG, 0);
+        }
+
+        assert!(state.is_draw());
+    }
+
     #[test]
     fn test_bitboard_1() {
         let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
@@ -1355,4 +1934,107 @@ mod tests {
         state.do_mov(util::map_sqr_notation_to_index("a1"), util::map_sqr_notation_to_index("a4"), def::MOV_REG, 0);
         assert!(state.is_draw());
     }
+
+    #[test]
+    fn test_to_fen() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let fen = "r3k2r/pppq1ppp/2n2n2/3pp3/1b1PP1b1/2N2N2/PPPQ1PPP/R3KB1R w KQq - 4 9";
+        let state = State::new(fen, &zob_keys, &bitmask);
+        assert_eq!(fen, state.to_fen());
+
+        let mut state = State::new("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -", &zob_keys, &bitmask);
+        state.do_mov(util::map_sqr_notation_to_index("e2"), util::map_sqr_notation_to_index("e4"), def::MOV_CR_ENP, 0);
+        assert_eq!("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", state.to_fen());
+
+        state.do_mov(util::map_sqr_notation_to_index("g8"), util::map_sqr_notation_to_index("f6"), def::MOV_REG, 0);
+        assert_eq!("rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 2", state.to_fen());
+
+        state.undo_mov(util::map_sqr_notation_to_index("g8"), util::map_sqr_notation_to_index("f6"), def::MOV_REG);
+        assert_eq!("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1", state.to_fen());
+    }
+
+    #[test]
+    fn test_from_fen_errors() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let from_fen_error = |fen: &str| State::from_fen(fen, &zob_keys, &bitmask).err();
+
+        assert_eq!(Some(FenError::InvalidFieldCount(2)), from_fen_error("8/8/8/8/8/8/8/8 w"));
+        assert_eq!(Some(FenError::InvalidSqrs("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR".to_owned())), from_fen_error("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"));
+        assert_eq!(Some(FenError::InvalidPlayer("x".to_owned())), from_fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1"));
+        assert_eq!(Some(FenError::InvalidMovCount("-1".to_owned())), from_fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - -1 1"));
+        assert_eq!(Some(FenError::InvalidKingCount(def::PLAYER_B, 0)), from_fen_error("8/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(Some(FenError::InvalidKingCount(def::PLAYER_W, 2)), from_fen_error("4k3/8/8/8/8/8/8/3KK3 w - - 0 1"));
+        assert_eq!(Some(FenError::PawnOnBackRank("a8".to_owned())), from_fen_error("P3k3/8/8/8/8/8/8/4K3 w - - 0 1"));
+        assert_eq!(Some(FenError::InvalidCasRights("KQkq".to_owned())), from_fen_error("rnbqkbn1/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"));
+        assert_eq!(Some(FenError::InvalidCasRights("KK".to_owned())), from_fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KK - 0 1"));
+        assert_eq!(Some(FenError::InvalidEnpSqr("e6".to_owned())), from_fen_error("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e6 0 1"));
+        assert_eq!(Some(FenError::OpponentInCheck), from_fen_error("4k3/8/8/8/8/8/8/4R1K1 w - - 0 1"));
+    }
+
+    #[test]
+    fn test_chess960_fen() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let state = State::new("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9", &zob_keys, &bitmask);
+        assert_eq!(0b1111, state.cas_rights);
+        assert_eq!([7, 5, 63, 61], state.cas_rook_indexes);
+        assert_eq!("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9", state.to_fen());
+
+        let state = State::new("bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w KQkq - 2 9", &zob_keys, &bitmask);
+        assert_eq!([7, 5, 63, 61], state.cas_rook_indexes);
+
+        let state = State::from_fen("rr2k3/8/8/8/8/8/8/1R2K1RR w GBb - 0 1", &zob_keys, &bitmask).unwrap();
+        assert_eq!(0b1101, state.cas_rights);
+        assert_eq!([6, 1, 63, 57], state.cas_rook_indexes);
+        assert_eq!("rr2k3/8/8/8/8/8/8/1R2K1RR w GQb - 0 1", state.to_fen());
+
+        let from_fen_error = |fen: &str| State::from_fen(fen, &zob_keys, &bitmask).err();
+
+        assert_eq!(Some(FenError::InvalidCasRights("C".to_owned())), from_fen_error("4k3/8/8/8/8/8/8/R3K2R w C - 0 1"));
+        assert_eq!(Some(FenError::InvalidCasRights("Kk".to_owned())), from_fen_error("4k3/8/8/8/8/8/8/R3K2R w Hh - 0 1"));
+        assert_eq!(Some(FenError::InvalidCasRights("KH".to_owned())), from_fen_error("4k3/8/8/8/8/8/8/R3K2R w KH - 0 1"));
+        assert_eq!(Some(FenError::InvalidCasRights("X".to_owned())), from_fen_error("4k3/8/8/8/8/8/8/R3K2R w X - 0 1"));
+    }
+
+    #[test]
+    fn test_chess960_cas_mov() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let cas_test_list = [
+            ("rk5r/8/8/8/8/8/8/RK5R w HAha - 3 1", "b1", "c1", "rk5r/8/8/8/8/8/8/2KR3R b kq - 0 1"),
+            ("rk5r/8/8/8/8/8/8/RK5R w HAha - 3 1", "b1", "g1", "rk5r/8/8/8/8/8/8/R4RK1 b kq - 0 1"),
+            ("4rkr1/8/8/8/8/8/8/4RKR1 b GEge - 3 1", "f8", "g8", "4rrk1/8/8/8/8/8/8/4RKR1 w KQ - 0 2"),
+            ("4rkr1/8/8/8/8/8/8/4RKR1 b GEge - 3 1", "f8", "c8", "2kr2r1/8/8/8/8/8/8/4RKR1 w KQ - 0 2"),
+        ];
+
+        let bitboard_masks = |state: &State| (state.bitboard.w_all, state.bitboard.b_all, state.bitboard.w_rook, state.bitboard.b_rook);
+
+        for (fen, from, to, expected_fen) in cas_test_list {
+            let mut state = State::new(fen, &zob_keys, &bitmask);
+            let original_state = state.clone();
+            let expected_state = State::new(expected_fen, &zob_keys, &bitmask);
+            let from = util::map_sqr_notation_to_index(from);
+            let to = util::map_sqr_notation_to_index(to);
+
+            state.do_mov(from, to, def::MOV_CAS, 0);
+            assert_eq!(expected_fen, state.to_fen());
+            assert_eq!(expected_state.hash_key, state.hash_key);
+            assert_eq!(bitboard_masks(&expected_state), bitboard_masks(&state));
+
+            state.undo_mov(from, to, def::MOV_CAS);
+            assert_eq!(original_state.to_fen(), state.to_fen());
+            assert_eq!(original_state.hash_key, state.hash_key);
+            assert_eq!(bitboard_masks(&original_state), bitboard_masks(&state));
+        }
+
+        let mut state = State::new("rk5r/8/8/8/8/8/8/RK5R w HAha - 0 1", &zob_keys, &bitmask);
+        state.do_mov(util::map_sqr_notation_to_index("h1"), util::map_sqr_notation_to_index("h8"), def::MOV_REG, 0);
+        assert_eq!(0b0101, state.cas_rights);
+    }
 }
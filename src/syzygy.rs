@@ -0,0 +1,1520 @@
+/*
+ * Copyright (C) 2020 Zixiao Han
+ */
+
+//! Syzygy endgame tablebase probing.
+//!
+//! WDL values follow the Syzygy convention: a cursed win is a win that the 50-move rule turns into
+//! a draw, a blessed loss is the reverse. DTZ values count plies to the next capture or pawn move,
+//! positive when the side to move wins; DTZ tables may round them to full moves.
+//!
+//! The table decoding (file layout, piece encoding, pairs-data decompression and the WDL/DTZ probing
+//! rules) follows `src/table.rs` and `src/tablebase.rs` of the shakmaty-syzygy crate, version 0.25.0
+//! (https://github.com/niklasf/shakmaty-syzygy), which is licensed GPL-3.0-or-later. The test tables
+//! under `res/syzygy` are described in `res/syzygy/README.md`.
+
+use crate::{
+    def,
+    mov_table,
+    state::State,
+    util,
+};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const TB_MAX_PIECE_COUNT: usize = 7;
+
+pub const WDL_LOSS: i32 = -2;
+pub const WDL_BLESSED_LOSS: i32 = -1;
+pub const WDL_DRAW: i32 = 0;
+pub const WDL_CURSED_WIN: i32 = 1;
+pub const WDL_WIN: i32 = 2;
+
+const WDL_EXTENSION: &str = "rtbw";
+const DTZ_EXTENSION: &str = "rtbz";
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xe8, 0x23, 0x5d];
+const DTZ_MAGIC: [u8; 4] = [0xd7, 0x66, 0x0c, 0xa5];
+
+const LAYOUT_SPLIT: u8 = 0b1;
+const LAYOUT_HAS_PAWNS: u8 = 0b10;
+
+const FLAG_STM: u8 = 0b1;
+const FLAG_MAPPED: u8 = 0b10;
+const FLAG_WIN_PLIES: u8 = 0b100;
+const FLAG_LOSS_PLIES: u8 = 0b1000;
+const FLAG_WIDE_DTZ: u8 = 0b10000;
+const FLAG_SINGLE_VALUE: u8 = 0b10000000;
+
+const MAX_BLOCK_SIZE: usize = 1024;
+const MAX_SYMBOL_DEPTH: u8 = 16;
+
+const WHITE: usize = 0;
+const BLACK: usize = 1;
+
+const PAWN: usize = 0;
+const KING: usize = 5;
+
+const ROLE_CHARS: [char; 6] = ['P', 'N', 'B', 'R', 'Q', 'K'];
+
+/// Maps squares into the a1-d1-d4 triangle.
+const TRIANGLE: [u64; def::BOARD_SIZE] = [
+    6, 0, 1, 2, 2, 1, 0, 6,
+    0, 7, 3, 4, 4, 3, 7, 0,
+    1, 3, 8, 5, 5, 8, 3, 1,
+    2, 4, 5, 9, 9, 5, 4, 2,
+    2, 4, 5, 9, 9, 5, 4, 2,
+    1, 3, 8, 5, 5, 8, 3, 1,
+    0, 7, 3, 4, 4, 3, 7, 0,
+    6, 0, 1, 2, 2, 1, 0, 6,
+];
+
+const INV_TRIANGLE: [usize; 10] = [1, 2, 3, 10, 11, 19, 0, 9, 18, 27];
+
+/// Maps the squares below the a1-h8 diagonal to 0..28.
+const LOWER: [u64; def::BOARD_SIZE] = [
+    28,  0,  1,  2,  3,  4,  5,  6,
+     0, 29,  7,  8,  9, 10, 11, 12,
+     1,  7, 30, 13, 14, 15, 16, 17,
+     2,  8, 13, 31, 18, 19, 20, 21,
+     3,  9, 14, 18, 32, 22, 23, 24,
+     4, 10, 15, 19, 22, 33, 25, 26,
+     5, 11, 16, 20, 23, 25, 34, 27,
+     6, 12, 17, 21, 24, 26, 27, 35,
+];
+
+/// Piece counts indexed by colour and role (pawn, knight, bishop, rook, queen, king).
+type MaterialKey = [[u8; 6]; 2];
+
+#[derive(PartialEq, Debug)]
+pub enum SyzygyError {
+    Read(String, String),
+    InvalidName(String),
+    InvalidMagic(String),
+    InvalidTable(String),
+}
+
+impl fmt::Display for SyzygyError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyzygyError::Read(path, reason) => write!(formatter, "unable to read {}: {}", path, reason),
+            SyzygyError::InvalidName(path) => write!(formatter, "{} is not named after a material configuration", path),
+            SyzygyError::InvalidMagic(path) => write!(formatter, "{} is not a syzygy table", path),
+            SyzygyError::InvalidTable(path) => write!(formatter, "{} is corrupted", path),
+        }
+    }
+}
+
+/// Index tables shared by all Syzygy tables.
+struct IndexConsts {
+    kk_idx: [[u64; def::BOARD_SIZE]; 10],
+    map_pawns: [u64; def::BOARD_SIZE],
+    lead_pawn_idx: [[u64; def::BOARD_SIZE]; TB_MAX_PIECE_COUNT],
+    lead_pawns_size: [[u64; 4]; TB_MAX_PIECE_COUNT],
+}
+
+impl IndexConsts {
+    fn new() -> Self {
+        let mut kk_idx = [[0; def::BOARD_SIZE]; 10];
+        let mut both_on_diagonal_list = Vec::new();
+        let mut code = 0;
+
+        for (triangle_index, &k1_index) in INV_TRIANGLE.iter().enumerate() {
+            for (k2_index, kk_code) in kk_idx[triangle_index].iter_mut().enumerate() {
+                if def::get_distance(k1_index, k2_index) <= 1 || (get_diagonal_offset(k1_index) == 0 && get_diagonal_offset(k2_index) > 0) {
+                    continue
+                }
+
+                if get_diagonal_offset(k1_index) == 0 && get_diagonal_offset(k2_index) == 0 {
+                    both_on_diagonal_list.push((triangle_index, k2_index));
+                } else {
+                    *kk_code = code;
+                    code += 1;
+                }
+            }
+        }
+
+        for (triangle_index, k2_index) in both_on_diagonal_list {
+            kk_idx[triangle_index][k2_index] = code;
+            code += 1;
+        }
+
+        let mut map_pawns = [0; def::BOARD_SIZE];
+        let mut lead_pawn_idx = [[0; def::BOARD_SIZE]; TB_MAX_PIECE_COUNT];
+        let mut lead_pawns_size = [[0; 4]; TB_MAX_PIECE_COUNT];
+        let mut available_square_count = 48;
+
+        for lead_pawn_count in 1..TB_MAX_PIECE_COUNT {
+            for (file, lead_pawn_size) in lead_pawns_size[lead_pawn_count].iter_mut().enumerate() {
+                let mut idx = 0;
+
+                for rank in 1..7 {
+                    let index = rank * def::DIM_SIZE + file;
+
+                    if lead_pawn_count == 1 {
+                        available_square_count -= 1;
+                        map_pawns[index] = available_square_count;
+                        available_square_count -= 1;
+                        map_pawns[flip_horizontal(index)] = available_square_count;
+                    }
+
+                    lead_pawn_idx[lead_pawn_count][index] = idx;
+                    idx += binomial(map_pawns[index], lead_pawn_count as u64 - 1);
+                }
+
+                *lead_pawn_size = idx;
+            }
+        }
+
+        IndexConsts {
+            kk_idx,
+            map_pawns,
+            lead_pawn_idx,
+            lead_pawns_size,
+        }
+    }
+}
+
+/// How the pieces of one side of a table are split into groups that are indexed together.
+struct GroupData {
+    piece_list: Vec<(usize, usize)>,
+    len_list: Vec<usize>,
+    factor_list: Vec<u64>,
+}
+
+impl GroupData {
+    fn new(consts: &IndexConsts, piece_list: Vec<(usize, usize)>, material_key: &MaterialKey, order: [u8; 2], file: usize) -> Option<Self> {
+        let has_pawns = has_pawns(material_key);
+        let unique_piece_count = get_unique_piece_count(material_key);
+
+        let first_len = if has_pawns {
+            0
+        } else if unique_piece_count >= 3 {
+            3
+        } else if unique_piece_count == 2 {
+            2
+        } else {
+            return None
+        };
+
+        let mut len_list = Vec::new();
+
+        if first_len > 0 {
+            len_list.push(first_len);
+        }
+
+        let mut piece_index = first_len;
+        while piece_index < piece_list.len() {
+            let group_len = piece_list[piece_index..].iter().take_while(|piece| **piece == piece_list[piece_index]).count();
+            len_list.push(group_len);
+            piece_index += group_len;
+        }
+
+        let is_pawn_vs_pawn = material_key[WHITE][PAWN] > 0 && material_key[BLACK][PAWN] > 0;
+
+        let mut factor_list = vec![0; len_list.len() + 1];
+        let mut free_square_count = 64 - len_list[0] - if is_pawn_vs_pawn { len_list[1] } else { 0 };
+        let mut next = if is_pawn_vs_pawn { 2 } else { 1 };
+        let mut idx = 1;
+        let mut k = 0;
+
+        while next < len_list.len() || k == order[0] || k == order[1] {
+            if k == order[0] {
+                factor_list[0] = idx;
+
+                idx *= if has_pawns {
+                    consts.lead_pawns_size[len_list[0]][file]
+                } else if unique_piece_count >= 3 {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                factor_list[1] = idx;
+                idx *= binomial(48 - len_list[0] as u64, len_list[1] as u64);
+            } else {
+                factor_list[next] = idx;
+                idx *= binomial(free_square_count as u64, len_list[next] as u64);
+                free_square_count -= len_list[next];
+                next += 1;
+            }
+
+            k += 1;
+        }
+
+        factor_list[len_list.len()] = idx;
+
+        Some(GroupData {
+            piece_list,
+            len_list,
+            factor_list,
+        })
+    }
+}
+
+/// Encoding and Huffman compression data of one subtable.
+struct PairsData {
+    flags: u8,
+    group_data: GroupData,
+    single_value: u16,
+    block_size: u64,
+    span: u64,
+    block_count: u64,
+    min_sym_len: u8,
+    lowest_sym_list: Vec<u16>,
+    base_list: Vec<u64>,
+    sym_len_list: Vec<u8>,
+    sym_tree: Vec<u8>,
+    sparse_index_offset: u64,
+    sparse_index_size: u64,
+    block_lengths_offset: u64,
+    block_lengths_size: u64,
+    data_offset: u64,
+    dtz_map_index: [usize; 4],
+}
+
+impl PairsData {
+    fn parse(file: &TableFile, mut offset: u64, group_data: GroupData, is_wdl: bool) -> Option<(Self, u64)> {
+        let flags = file.read_u8(offset)?;
+
+        let mut pairs_data = PairsData {
+            flags,
+            group_data,
+            single_value: 0,
+            block_size: 0,
+            span: 0,
+            block_count: 0,
+            min_sym_len: 0,
+            lowest_sym_list: Vec::new(),
+            base_list: Vec::new(),
+            sym_len_list: Vec::new(),
+            sym_tree: Vec::new(),
+            sparse_index_offset: 0,
+            sparse_index_size: 0,
+            block_lengths_offset: 0,
+            block_lengths_size: 0,
+            data_offset: 0,
+            dtz_map_index: [0; 4],
+        };
+
+        if flags & FLAG_SINGLE_VALUE != 0 {
+            if is_wdl {
+                pairs_data.single_value = file.read_u8(offset + 1)? as u16;
+            }
+
+            return Some((pairs_data, offset + 2))
+        }
+
+        let mut header = [0; 10];
+        file.read_exact_at(offset, &mut header)?;
+
+        let table_size = pairs_data.group_data.factor_list[pairs_data.group_data.len_list.len()];
+
+        if header[1] as usize > 10 || header[2] >= 64 {
+            return None
+        }
+
+        pairs_data.block_size = 1 << header[1];
+        pairs_data.span = 1 << header[2];
+        pairs_data.sparse_index_size = table_size.div_ceil(pairs_data.span);
+        pairs_data.block_count = u32::from_le_bytes([header[4], header[5], header[6], header[7]]) as u64;
+        pairs_data.block_lengths_size = pairs_data.block_count + header[3] as u64;
+
+        let max_sym_len = header[8];
+        let min_sym_len = header[9];
+
+        if max_sym_len > 32 || min_sym_len > 32 || max_sym_len < min_sym_len || min_sym_len == 0 {
+            return None
+        }
+
+        pairs_data.min_sym_len = min_sym_len;
+
+        let sym_len_range = (max_sym_len - min_sym_len + 1) as usize;
+
+        let mut lowest_sym_bytes = vec![0; sym_len_range * 2];
+        file.read_exact_at(offset + 10, &mut lowest_sym_bytes)?;
+        pairs_data.lowest_sym_list = lowest_sym_bytes.chunks(2).map(|sym_bytes| u16::from_le_bytes([sym_bytes[0], sym_bytes[1]])).collect();
+
+        let mut base_list = vec![0u64; sym_len_range];
+        for i in (0..sym_len_range - 1).rev() {
+            base_list[i] = (base_list[i + 1] + pairs_data.lowest_sym_list[i] as u64).checked_sub(pairs_data.lowest_sym_list[i + 1] as u64)? / 2;
+
+            if base_list[i] * 2 < base_list[i + 1] {
+                return None
+            }
+        }
+
+        for (i, base) in base_list.iter_mut().enumerate() {
+            *base = base.checked_shl(64 - (min_sym_len as u32 + i as u32))?;
+        }
+
+        pairs_data.base_list = base_list;
+
+        offset += 10 + sym_len_range as u64 * 2;
+
+        let sym_count = file.read_u16(offset)? as usize;
+        offset += 2;
+
+        let mut sym_tree = vec![0; sym_count * 3];
+        file.read_exact_at(offset, &mut sym_tree)?;
+        pairs_data.sym_tree = sym_tree;
+
+        let mut sym_len_list = vec![0; sym_count];
+        let mut visited_list = vec![false; sym_count];
+        for sym in 0..sym_count {
+            set_sym_len(&pairs_data.sym_tree, &mut sym_len_list, &mut visited_list, sym, MAX_SYMBOL_DEPTH)?;
+        }
+
+        pairs_data.sym_len_list = sym_len_list;
+
+        offset += sym_count as u64 * 3 + (sym_count as u64 & 1);
+
+        Some((pairs_data, offset))
+    }
+
+    /// Returns the left and right children of a node in the symbol tree.
+    #[inline]
+    fn get_sym_children(&self, sym: usize) -> (usize, usize) {
+        get_sym_children(&self.sym_tree, sym)
+    }
+}
+
+/// Random access to a table file.
+struct TableFile {
+    file: fs::File,
+    file_size: u64,
+}
+
+impl TableFile {
+    fn open(path: &Path) -> io::Result<Self> {
+        let file = fs::File::open(path)?;
+        let file_size = file.metadata()?.len();
+
+        Ok(TableFile {
+            file,
+            file_size,
+        })
+    }
+
+    #[cfg(unix)]
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        use std::os::unix::fs::FileExt;
+        self.file.read_exact_at(buf, offset)
+    }
+
+    #[cfg(windows)]
+    fn read_at(&self, mut offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+
+        while !buf.is_empty() {
+            match self.file.seek_read(buf, offset)? {
+                0 => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of table")),
+                read_size => {
+                    buf = &mut buf[read_size..];
+                    offset += read_size as u64;
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> Option<()> {
+        self.read_at(offset, buf).ok()
+    }
+
+    /// Reads as much of `buf` as the file holds, leaving the rest untouched.
+    fn read_available_at(&self, offset: u64, buf: &mut [u8]) -> Option<()> {
+        let read_size = (self.file_size.checked_sub(offset)? as usize).min(buf.len());
+        self.read_exact_at(offset, &mut buf[..read_size])
+    }
+
+    fn read_u8(&self, offset: u64) -> Option<u8> {
+        let mut buf = [0; 1];
+        self.read_exact_at(offset, &mut buf)?;
+        Some(buf[0])
+    }
+
+    fn read_u16(&self, offset: u64) -> Option<u16> {
+        let mut buf = [0; 2];
+        self.read_exact_at(offset, &mut buf)?;
+        Some(u16::from_le_bytes(buf))
+    }
+}
+
+enum DtzLookup {
+    Found(i32),
+    OtherSide,
+}
+
+/// A WDL or DTZ table for one material configuration.
+struct Table {
+    file: TableFile,
+    is_wdl: bool,
+    material_key: MaterialKey,
+    sub_table_list: Vec<Vec<PairsData>>,
+    dtz_map: Vec<u8>,
+}
+
+impl Table {
+    fn open(consts: &IndexConsts, path: &Path, material_key: MaterialKey, is_wdl: bool) -> Result<Self, SyzygyError> {
+        let path_str = path.display().to_string();
+        let file = TableFile::open(path).map_err(|error| SyzygyError::Read(path_str.clone(), error.to_string()))?;
+
+        let mut magic = [0; 4];
+        file.read_exact_at(0, &mut magic).ok_or_else(|| SyzygyError::InvalidMagic(path_str.clone()))?;
+
+        if magic != if is_wdl { WDL_MAGIC } else { DTZ_MAGIC } {
+            return Err(SyzygyError::InvalidMagic(path_str))
+        }
+
+        Table::parse(consts, file, material_key, is_wdl).ok_or(SyzygyError::InvalidTable(path_str))
+    }
+
+    fn parse(consts: &IndexConsts, file: TableFile, material_key: MaterialKey, is_wdl: bool) -> Option<Self> {
+        let layout = file.read_u8(4)?;
+        let has_pawns = has_pawns(&material_key);
+        let is_symmetric = material_key[WHITE] == material_key[BLACK];
+
+        if (layout & LAYOUT_HAS_PAWNS != 0) != has_pawns || (layout & LAYOUT_SPLIT != 0) == is_symmetric {
+            return None
+        }
+
+        let is_pawn_vs_pawn = material_key[WHITE][PAWN] > 0 && material_key[BLACK][PAWN] > 0;
+        let piece_count = get_piece_count(&material_key);
+        let file_count = if has_pawns { 4 } else { 1 };
+        let side_count = if is_wdl && !is_symmetric { 2 } else { 1 };
+
+        let mut offset = 5;
+        let mut group_data_list = Vec::new();
+
+        for file_index in 0..file_count {
+            let order_byte = file.read_u8(offset)?;
+            let pawn_order_byte = if is_pawn_vs_pawn { file.read_u8(offset + 1)? } else { 0xff };
+            let order_list = [[order_byte & 0xf, pawn_order_byte & 0xf], [order_byte >> 4, pawn_order_byte >> 4]];
+
+            offset += 1 + is_pawn_vs_pawn as u64;
+
+            let mut piece_bytes = vec![0; piece_count];
+            file.read_exact_at(offset, &mut piece_bytes)?;
+            offset += piece_count as u64;
+
+            let mut side_group_data_list = Vec::new();
+
+            for (side, &order) in order_list.iter().enumerate().take(side_count) {
+                let mut piece_list = Vec::new();
+
+                for piece_byte in &piece_bytes {
+                    let piece_nibble = if side == WHITE { piece_byte & 0xf } else { piece_byte >> 4 };
+                    let role = (piece_nibble & 0b111) as usize;
+
+                    if role == 0 || role > 6 {
+                        return None
+                    }
+
+                    piece_list.push((if piece_nibble & 0b1000 == 0 { WHITE } else { BLACK }, role - 1));
+                }
+
+                let mut piece_key = [[0; 6]; 2];
+                for &(color, role) in &piece_list {
+                    piece_key[color][role] += 1;
+                }
+
+                if piece_key != material_key && flip_material_key(&piece_key) != material_key {
+                    return None
+                }
+
+                if has_pawns != (piece_list[0].1 == PAWN) {
+                    return None
+                }
+
+                side_group_data_list.push(GroupData::new(consts, piece_list, &material_key, order, file_index)?);
+            }
+
+            group_data_list.push(side_group_data_list);
+        }
+
+        offset += offset & 1;
+
+        let mut sub_table_list = Vec::new();
+
+        for side_group_data_list in group_data_list {
+            let mut pairs_data_list = Vec::new();
+
+            for group_data in side_group_data_list {
+                let (pairs_data, next_offset) = PairsData::parse(&file, offset, group_data, is_wdl)?;
+                pairs_data_list.push(pairs_data);
+                offset = next_offset;
+            }
+
+            sub_table_list.push(pairs_data_list);
+        }
+
+        let mut dtz_map = Vec::new();
+
+        if !is_wdl {
+            let map_offset = offset;
+
+            for pairs_data_list in &mut sub_table_list {
+                let pairs_data = &mut pairs_data_list[0];
+
+                if pairs_data.flags & FLAG_MAPPED == 0 {
+                    continue
+                }
+
+                for wdl_index in 0..4 {
+                    if pairs_data.flags & FLAG_WIDE_DTZ != 0 {
+                        pairs_data.dtz_map_index[wdl_index] = (offset - map_offset) as usize + 2;
+                        offset += file.read_u16(offset)? as u64 * 2 + 2;
+                    } else {
+                        pairs_data.dtz_map_index[wdl_index] = (offset - map_offset) as usize + 1;
+                        offset += file.read_u8(offset)? as u64 + 1;
+                    }
+                }
+            }
+
+            dtz_map = vec![0; (offset - map_offset) as usize];
+            file.read_exact_at(map_offset, &mut dtz_map)?;
+
+            offset += offset & 1;
+        }
+
+        for pairs_data_list in &mut sub_table_list {
+            for pairs_data in pairs_data_list {
+                pairs_data.sparse_index_offset = offset;
+                offset += pairs_data.sparse_index_size * 6;
+            }
+        }
+
+        for pairs_data_list in &mut sub_table_list {
+            for pairs_data in pairs_data_list {
+                pairs_data.block_lengths_offset = offset;
+                offset += pairs_data.block_lengths_size * 2;
+            }
+        }
+
+        for pairs_data_list in &mut sub_table_list {
+            for pairs_data in pairs_data_list {
+                offset = (offset + 0x3f) & !0x3f;
+                pairs_data.data_offset = offset;
+                offset = offset.checked_add(pairs_data.block_count * pairs_data.block_size)?;
+            }
+        }
+
+        if offset > file.file_size {
+            return None
+        }
+
+        Some(Table {
+            file,
+            is_wdl,
+            material_key,
+            sub_table_list,
+            dtz_map,
+        })
+    }
+
+    /// Finds the subtable of the position and the index of the position within it, or `None` for
+    /// a DTZ table that only stores the other side to move.
+    fn encode(&self, consts: &IndexConsts, state: &State) -> Option<(&PairsData, u64)> {
+        let has_pawns = has_pawns(&self.material_key);
+        let is_symmetric = self.material_key[WHITE] == self.material_key[BLACK];
+        let is_flipped = (is_symmetric && state.player == def::PLAYER_B) || get_material_key(state) != self.material_key;
+        let is_black_side = (state.player == def::PLAYER_B) ^ is_flipped;
+
+        let flip_index = |index: usize| if is_flipped { flip_vertical(index) } else { index };
+
+        let mut square_list = [0; TB_MAX_PIECE_COUNT];
+        let mut square_count = 0;
+        let mut used_mask = 0;
+
+        let file_index = if has_pawns {
+            let (lead_pawn_color, _) = self.sub_table_list[0][0].group_data.piece_list[0];
+            let mut lead_pawn_mask = get_piece_mask(state, lead_pawn_color ^ is_flipped as usize, PAWN);
+            used_mask |= lead_pawn_mask;
+
+            while lead_pawn_mask != 0 {
+                square_list[square_count] = flip_index(lead_pawn_mask.trailing_zeros() as usize);
+                square_count += 1;
+                lead_pawn_mask &= lead_pawn_mask - 1;
+            }
+
+            for i in 1..square_count {
+                if consts.map_pawns[square_list[0]] < consts.map_pawns[square_list[i]] {
+                    square_list.swap(0, i);
+                }
+            }
+
+            let file = square_list[0] % def::DIM_SIZE;
+            file.min(7 - file)
+        } else {
+            0
+        };
+
+        let pairs_data_list = &self.sub_table_list[file_index];
+        let pairs_data = &pairs_data_list[if is_black_side { pairs_data_list.len() - 1 } else { 0 }];
+
+        if !self.is_wdl && (pairs_data.flags & FLAG_STM != 0) != is_black_side && (!is_symmetric || has_pawns) {
+            return None
+        }
+
+        let lead_pawn_count = square_count;
+
+        for &(color, role) in pairs_data.group_data.piece_list.iter().skip(lead_pawn_count) {
+            let piece_mask = get_piece_mask(state, color ^ is_flipped as usize, role) & !used_mask;
+            let index = piece_mask.trailing_zeros() as usize;
+
+            square_list[square_count] = flip_index(index);
+            square_count += 1;
+            used_mask |= 1 << index;
+        }
+
+        let square_list = &mut square_list[..square_count];
+
+        if square_list[0] % def::DIM_SIZE >= 4 {
+            for index in square_list.iter_mut() {
+                *index = flip_horizontal(*index);
+            }
+        }
+
+        let len_list = &pairs_data.group_data.len_list;
+
+        let mut idx = if has_pawns {
+            let mut idx = consts.lead_pawn_idx[lead_pawn_count][square_list[0]];
+
+            square_list[1..lead_pawn_count].sort_by_key(|index| consts.map_pawns[*index]);
+
+            for (i, &index) in square_list.iter().enumerate().take(lead_pawn_count).skip(1) {
+                idx += binomial(consts.map_pawns[index], i as u64);
+            }
+
+            idx
+        } else {
+            if square_list[0] / def::DIM_SIZE >= 4 {
+                for index in square_list.iter_mut() {
+                    *index = flip_vertical(*index);
+                }
+            }
+
+            for i in 0..len_list[0] {
+                let diagonal_offset = get_diagonal_offset(square_list[i]);
+
+                if diagonal_offset == 0 {
+                    continue
+                }
+
+                if diagonal_offset > 0 {
+                    for index in square_list[i..].iter_mut() {
+                        *index = flip_diagonal(*index);
+                    }
+                }
+
+                break
+            }
+
+            if len_list[0] == 3 {
+                encode_triple(square_list[0], square_list[1], square_list[2])
+            } else {
+                consts.kk_idx[TRIANGLE[square_list[0]] as usize][square_list[1]]
+            }
+        };
+
+        idx *= pairs_data.group_data.factor_list[0];
+
+        let mut is_remaining_pawns = self.material_key[WHITE][PAWN] > 0 && self.material_key[BLACK][PAWN] > 0;
+        let mut group_start = len_list[0];
+
+        for (group_index, &group_len) in len_list.iter().enumerate().skip(1) {
+            let (prev_square_list, group_square_list) = square_list.split_at_mut(group_start);
+            let group_square_list = &mut group_square_list[..group_len];
+            group_square_list.sort_unstable();
+
+            let mut group_idx = 0;
+
+            for (i, &index) in group_square_list.iter().enumerate() {
+                let adjust = prev_square_list.iter().filter(|prev_index| index > **prev_index).count() as u64;
+                group_idx += binomial(index as u64 - adjust - if is_remaining_pawns { 8 } else { 0 }, i as u64 + 1);
+            }
+
+            is_remaining_pawns = false;
+            idx += group_idx * pairs_data.group_data.factor_list[group_index];
+            group_start += group_len;
+        }
+
+        Some((pairs_data, idx))
+    }
+
+    /// Retrieves the value stored at `idx` by decoding the Huffman symbols of its block.
+    fn decompress_pairs(&self, pairs_data: &PairsData, idx: u64) -> Option<u16> {
+        if pairs_data.flags & FLAG_SINGLE_VALUE != 0 {
+            return Some(pairs_data.single_value)
+        }
+
+        let main_idx = idx / pairs_data.span;
+
+        let mut sparse_entry = [0; 6];
+        self.file.read_exact_at(pairs_data.sparse_index_offset + 6 * main_idx, &mut sparse_entry)?;
+
+        let mut block = u32::from_le_bytes([sparse_entry[0], sparse_entry[1], sparse_entry[2], sparse_entry[3]]) as u64;
+        let mut lit_idx = (idx % pairs_data.span) as i64 - (pairs_data.span / 2) as i64 + u16::from_le_bytes([sparse_entry[4], sparse_entry[5]]) as i64;
+
+        while lit_idx < 0 {
+            block = block.checked_sub(1)?;
+            lit_idx += self.file.read_u16(pairs_data.block_lengths_offset + block * 2)? as i64 + 1;
+        }
+
+        loop {
+            let block_length = self.file.read_u16(pairs_data.block_lengths_offset + block * 2)? as i64 + 1;
+
+            if lit_idx < block_length {
+                break
+            }
+
+            lit_idx -= block_length;
+            block += 1;
+        }
+
+        let mut block_buf = [0; MAX_BLOCK_SIZE + 8];
+        let block_buf = &mut block_buf[..pairs_data.block_size as usize + 8];
+        self.file.read_available_at(pairs_data.data_offset + block * pairs_data.block_size, block_buf)?;
+
+        let mut buf_cursor = 8;
+        let mut buf = u64::from_be_bytes([block_buf[0], block_buf[1], block_buf[2], block_buf[3], block_buf[4], block_buf[5], block_buf[6], block_buf[7]]);
+        let mut buf_size = 64;
+        let min_sym_len = pairs_data.min_sym_len as usize;
+
+        let mut sym = loop {
+            let mut len = 0;
+
+            while buf < *pairs_data.base_list.get(len)? {
+                len += 1;
+            }
+
+            let sym = ((buf - pairs_data.base_list[len]) >> (64 - len - min_sym_len)) as usize + pairs_data.lowest_sym_list[len] as usize;
+            let sym_len = *pairs_data.sym_len_list.get(sym)? as i64;
+
+            if lit_idx < sym_len + 1 {
+                break sym
+            }
+
+            lit_idx -= sym_len + 1;
+            len += min_sym_len;
+            buf <<= len;
+            buf_size -= len;
+
+            if buf_size <= 32 {
+                if buf_cursor + 4 > block_buf.len() {
+                    return None
+                }
+
+                buf_size += 32;
+                buf |= (u32::from_be_bytes([block_buf[buf_cursor], block_buf[buf_cursor + 1], block_buf[buf_cursor + 2], block_buf[buf_cursor + 3]]) as u64) << (64 - buf_size);
+                buf_cursor += 4;
+            }
+        };
+
+        while pairs_data.sym_len_list[sym] != 0 {
+            let (left, right) = pairs_data.get_sym_children(sym);
+            let left_len = *pairs_data.sym_len_list.get(left)? as i64;
+
+            if lit_idx < left_len + 1 {
+                sym = left;
+            } else {
+                lit_idx -= left_len + 1;
+                sym = right;
+            }
+        }
+
+        if self.is_wdl {
+            Some(pairs_data.sym_tree[sym * 3] as u16)
+        } else {
+            Some(u16::from_le_bytes([pairs_data.sym_tree[sym * 3], pairs_data.sym_tree[sym * 3 + 1]]) & 0xfff)
+        }
+    }
+
+    fn probe_wdl(&self, consts: &IndexConsts, state: &State) -> Option<i32> {
+        let (pairs_data, idx) = self.encode(consts, state)?;
+
+        match self.decompress_pairs(pairs_data, idx)? {
+            value @ 0..=4 => Some(value as i32 - 2),
+            _ => None,
+        }
+    }
+
+    fn probe_dtz(&self, consts: &IndexConsts, state: &State, wdl: i32) -> Option<DtzLookup> {
+        let (pairs_data, idx) = match self.encode(consts, state) {
+            Some(encoded_pos) => encoded_pos,
+            None => return Some(DtzLookup::OtherSide),
+        };
+
+        let mut value = self.decompress_pairs(pairs_data, idx)? as usize;
+
+        if pairs_data.flags & FLAG_MAPPED != 0 {
+            let wdl_index = match wdl {
+                WDL_WIN => 0,
+                WDL_LOSS => 1,
+                WDL_CURSED_WIN => 2,
+                _ => 3,
+            };
+
+            let map_index = pairs_data.dtz_map_index[wdl_index] + value;
+
+            value = if pairs_data.flags & FLAG_WIDE_DTZ != 0 {
+                u16::from_le_bytes([*self.dtz_map.get(map_index * 2)?, *self.dtz_map.get(map_index * 2 + 1)?]) as usize
+            } else {
+                *self.dtz_map.get(map_index)? as usize
+            };
+        }
+
+        let is_plies = match wdl {
+            WDL_WIN => pairs_data.flags & FLAG_WIN_PLIES != 0,
+            WDL_LOSS => pairs_data.flags & FLAG_LOSS_PLIES != 0,
+            _ => false,
+        };
+
+        let plies = if is_plies { value as i32 } else { value as i32 * 2 };
+
+        Some(DtzLookup::Found(add_plies(get_dtz_before_zeroing(wdl), plies)))
+    }
+}
+
+/// The set of Syzygy tables found in one or more directories.
+pub struct SyzygyTablebase {
+    consts: IndexConsts,
+    wdl_table_dict: HashMap<MaterialKey, Table>,
+    dtz_table_dict: HashMap<MaterialKey, Table>,
+    max_piece_count: usize,
+}
+
+impl Default for SyzygyTablebase {
+    fn default() -> Self {
+        SyzygyTablebase::new()
+    }
+}
+
+impl SyzygyTablebase {
+    pub fn new() -> Self {
+        SyzygyTablebase {
+            consts: IndexConsts::new(),
+            wdl_table_dict: HashMap::new(),
+            dtz_table_dict: HashMap::new(),
+            max_piece_count: 0,
+        }
+    }
+
+    /// Adds every `.rtbw` and `.rtbz` file of a directory; returns the number of tables added.
+    pub fn add_directory(&mut self, path: &Path) -> Result<usize, SyzygyError> {
+        let dir_entry_list = fs::read_dir(path).map_err(|error| SyzygyError::Read(path.display().to_string(), error.to_string()))?;
+
+        let mut table_count = 0;
+
+        for dir_entry in dir_entry_list {
+            let file_path = dir_entry.map_err(|error| SyzygyError::Read(path.display().to_string(), error.to_string()))?.path();
+
+            match file_path.extension().and_then(|extension| extension.to_str()) {
+                Some(WDL_EXTENSION) | Some(DTZ_EXTENSION) => {
+                    self.add_file(&file_path)?;
+                    table_count += 1;
+                },
+                _ => {},
+            }
+        }
+
+        Ok(table_count)
+    }
+
+    /// Adds a table named after its material, e.g. `KQvKR.rtbw`.
+    pub fn add_file(&mut self, path: &Path) -> Result<(), SyzygyError> {
+        let path_str = path.display().to_string();
+
+        let is_wdl = match path.extension().and_then(|extension| extension.to_str()) {
+            Some(WDL_EXTENSION) => true,
+            Some(DTZ_EXTENSION) => false,
+            _ => return Err(SyzygyError::InvalidName(path_str)),
+        };
+
+        let material_key = path.file_stem().and_then(|stem| stem.to_str()).and_then(parse_material_key).ok_or(SyzygyError::InvalidName(path_str))?;
+
+        let table = Table::open(&self.consts, path, material_key, is_wdl)?;
+
+        self.max_piece_count = self.max_piece_count.max(get_piece_count(&material_key));
+
+        if is_wdl {
+            self.wdl_table_dict.insert(material_key, table);
+        } else {
+            self.dtz_table_dict.insert(material_key, table);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the largest number of pieces, kings included, covered by a table.
+    pub fn get_max_piece_count(&self) -> usize {
+        self.max_piece_count
+    }
+
+    /// Returns whether the position could be in the tables: few enough pieces and no castling rights.
+    pub fn is_probable(&self, state: &State) -> bool {
+        state.cas_rights == 0 && get_piece_count(&get_material_key(state)) <= self.max_piece_count
+    }
+
+    /// Probes the WDL value of the position, assuming it was reached by a capture or pawn move;
+    /// returns `None` if a required table is missing.
+    pub fn probe_wdl(&self, state: &mut State) -> Option<i32> {
+        self.probe_wdl_entry(state).map(|(wdl, _)| wdl)
+    }
+
+    /// Probes the DTZ value of the position; returns `None` if a required table is missing.
+    pub fn probe_dtz(&self, state: &mut State) -> Option<i32> {
+        let (wdl, is_zeroing_best) = self.probe_wdl_entry(state)?;
+
+        if wdl == WDL_DRAW {
+            return Some(0)
+        }
+
+        if is_zeroing_best {
+            return Some(get_dtz_before_zeroing(wdl))
+        }
+
+        let legal_mov_list = mov_table::gen_legal_mov_list(state);
+
+        if wdl > WDL_DRAW {
+            for &mov in &legal_mov_list {
+                let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+                if !def::is_p(state.squares[from]) || is_capture(state, to, tp) {
+                    continue
+                }
+
+                state.do_mov(from, to, tp, promo);
+                let next_wdl = self.probe_wdl_entry(state);
+                state.undo_mov(from, to, tp);
+
+                if -next_wdl?.0 == wdl {
+                    return Some(get_dtz_before_zeroing(wdl))
+                }
+            }
+        }
+
+        if let DtzLookup::Found(dtz) = self.probe_dtz_table(state, wdl)? {
+            return Some(dtz)
+        }
+
+        let mut best_dtz = if wdl > WDL_DRAW {
+            None
+        } else {
+            Some(get_dtz_before_zeroing(wdl))
+        };
+
+        for &mov in &legal_mov_list {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+            if def::is_p(state.squares[from]) || is_capture(state, to, tp) {
+                continue
+            }
+
+            state.do_mov(from, to, tp, promo);
+            let next_dtz = self.probe_dtz(state);
+            let is_checkmate = is_checkmate(state);
+            state.undo_mov(from, to, tp);
+
+            let dtz = -next_dtz?;
+
+            if dtz == 1 && is_checkmate {
+                best_dtz = Some(1);
+            } else if dtz.signum() == wdl.signum() {
+                let dtz = add_plies(dtz, 1);
+
+                if best_dtz.is_none_or(|best_dtz| dtz < best_dtz) {
+                    best_dtz = Some(dtz);
+                }
+            }
+        }
+
+        best_dtz
+    }
+
+    /// Keeps the root moves that preserve the tablebase result, preferring moves that make
+    /// progress within the 50-move rule. Returns the filtered moves and the WDL value of the
+    /// position (cursed or blessed once the 50-move counter makes the result unreachable),
+    /// or `None` if a required table is missing.
+    pub fn filter_root_movs_by_dtz(&self, state: &mut State, mov_list: &[u32]) -> Option<(Vec<u32>, i32)> {
+        let root_dtz = self.probe_dtz(state)?;
+
+        let mut scored_mov_list = Vec::new();
+
+        for &mov in mov_list {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+            state.do_mov(from, to, tp, promo);
+
+            let dtz = if root_dtz > 0 && is_checkmate(state) {
+                Some(1)
+            } else if state.non_cap_mov_count != 0 {
+                self.probe_dtz(state).map(|dtz| add_plies(-dtz, 1))
+            } else {
+                self.probe_wdl(state).map(|wdl| get_dtz_before_zeroing(-wdl))
+            };
+
+            state.undo_mov(from, to, tp);
+
+            scored_mov_list.push((mov, dtz?));
+        }
+
+        let non_cap_mov_count = state.non_cap_mov_count as i32;
+
+        let root_wdl = if root_dtz > 0 {
+            if root_dtz + non_cap_mov_count <= 100 { WDL_WIN } else { WDL_CURSED_WIN }
+        } else if root_dtz < 0 {
+            if -root_dtz + non_cap_mov_count <= 100 { WDL_LOSS } else { WDL_BLESSED_LOSS }
+        } else {
+            WDL_DRAW
+        };
+
+        let filtered_mov_list = if root_dtz > 0 {
+            let best_dtz = scored_mov_list.iter().map(|(_, dtz)| *dtz).filter(|dtz| *dtz > 0).min().unwrap_or(0);
+
+            let max_dtz = if !has_repeated(state) && best_dtz + non_cap_mov_count <= 99 {
+                99 - non_cap_mov_count
+            } else {
+                best_dtz
+            };
+
+            scored_mov_list.iter().filter(|(_, dtz)| *dtz > 0 && *dtz <= max_dtz).map(|(mov, _)| *mov).collect()
+        } else if root_dtz < 0 {
+            let best_dtz = scored_mov_list.iter().map(|(_, dtz)| *dtz).min().unwrap_or(0);
+
+            if -best_dtz * 2 + non_cap_mov_count < 100 {
+                mov_list.to_vec()
+            } else {
+                scored_mov_list.iter().filter(|(_, dtz)| *dtz == best_dtz).map(|(mov, _)| *mov).collect()
+            }
+        } else {
+            scored_mov_list.iter().filter(|(_, dtz)| *dtz == 0).map(|(mov, _)| *mov).collect()
+        };
+
+        Some((filtered_mov_list, root_wdl))
+    }
+
+    /// Keeps the root moves with the best WDL value, for when DTZ tables are not available.
+    pub fn filter_root_movs_by_wdl(&self, state: &mut State, mov_list: &[u32]) -> Option<(Vec<u32>, i32)> {
+        let mut scored_mov_list = Vec::new();
+
+        for &mov in mov_list {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+            state.do_mov(from, to, tp, promo);
+            let wdl = self.probe_wdl(state);
+            state.undo_mov(from, to, tp);
+
+            scored_mov_list.push((mov, -wdl?));
+        }
+
+        let best_wdl = scored_mov_list.iter().map(|(_, wdl)| *wdl).max()?;
+
+        Some((scored_mov_list.iter().filter(|(_, wdl)| *wdl == best_wdl).map(|(mov, _)| *mov).collect(), best_wdl))
+    }
+
+    /// Resolves captures and en passant before probing the table, which stores an arbitrary
+    /// value whenever a capture achieves the result. The flag tells whether the best move is a
+    /// capture or pawn move, in which case the DTZ table must not be probed.
+    fn probe_wdl_entry(&self, state: &mut State) -> Option<(i32, bool)> {
+        if !self.is_probable(state) {
+            return None
+        }
+
+        let legal_mov_list = mov_table::gen_legal_mov_list(state);
+
+        let mut best_cap_wdl = WDL_LOSS;
+        let mut best_enp_wdl = WDL_LOSS;
+
+        for &mov in &legal_mov_list {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+            if !is_capture(state, to, tp) {
+                continue
+            }
+
+            state.do_mov(from, to, tp, promo);
+            let next_wdl = self.probe_ab_no_enp(state, WDL_LOSS, -best_cap_wdl);
+            state.undo_mov(from, to, tp);
+
+            let wdl = -next_wdl?;
+
+            if wdl == WDL_WIN {
+                return Some((WDL_WIN, true))
+            }
+
+            if tp == def::MOV_ENP {
+                best_enp_wdl = best_enp_wdl.max(wdl);
+            } else {
+                best_cap_wdl = best_cap_wdl.max(wdl);
+            }
+        }
+
+        let wdl = self.probe_wdl_table(state)?;
+
+        if best_enp_wdl > wdl.max(best_cap_wdl) {
+            return Some((best_enp_wdl, true))
+        }
+
+        best_cap_wdl = best_cap_wdl.max(best_enp_wdl);
+
+        if best_cap_wdl >= wdl {
+            return Some((best_cap_wdl, best_cap_wdl > WDL_DRAW))
+        }
+
+        if wdl == WDL_DRAW && !legal_mov_list.is_empty() && legal_mov_list.iter().all(|mov| util::decode_u32_mov(*mov).2 == def::MOV_ENP) {
+            return Some((best_enp_wdl, true))
+        }
+
+        Some((wdl, false))
+    }
+
+    /// Resolves captures with alpha-beta in positions without en passant rights.
+    fn probe_ab_no_enp(&self, state: &mut State, mut alpha: i32, beta: i32) -> Option<i32> {
+        for mov in mov_table::gen_legal_mov_list(state) {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+            if !is_capture(state, to, tp) {
+                continue
+            }
+
+            state.do_mov(from, to, tp, promo);
+            let next_wdl = self.probe_ab_no_enp(state, -beta, -alpha);
+            state.undo_mov(from, to, tp);
+
+            let wdl = -next_wdl?;
+
+            if wdl >= beta {
+                return Some(wdl)
+            }
+
+            alpha = alpha.max(wdl);
+        }
+
+        Some(alpha.max(self.probe_wdl_table(state)?))
+    }
+
+    fn probe_wdl_table(&self, state: &State) -> Option<i32> {
+        let material_key = get_material_key(state);
+
+        if get_piece_count(&material_key) == 2 {
+            return Some(WDL_DRAW)
+        }
+
+        let table = self.wdl_table_dict.get(&material_key).or_else(|| self.wdl_table_dict.get(&flip_material_key(&material_key)))?;
+        table.probe_wdl(&self.consts, state)
+    }
+
+    fn probe_dtz_table(&self, state: &State, wdl: i32) -> Option<DtzLookup> {
+        let material_key = get_material_key(state);
+
+        let table = self.dtz_table_dict.get(&material_key).or_else(|| self.dtz_table_dict.get(&flip_material_key(&material_key)))?;
+        table.probe_dtz(&self.consts, state, wdl)
+    }
+}
+
+/// Parses a material configuration such as `KRPvKR`; each side must have exactly one king.
+fn parse_material_key(name: &str) -> Option<MaterialKey> {
+    let mut material_key = [[0; 6]; 2];
+    let mut side_list = name.split('v');
+
+    for color in [WHITE, BLACK].iter() {
+        for role_char in side_list.next()?.chars() {
+            let role = ROLE_CHARS.iter().position(|c| *c == role_char)?;
+            material_key[*color][role] += 1;
+        }
+
+        if material_key[*color][KING] != 1 {
+            return None
+        }
+    }
+
+    if side_list.next().is_some() || get_piece_count(&material_key) > TB_MAX_PIECE_COUNT {
+        return None
+    }
+
+    Some(material_key)
+}
+
+fn get_material_key(state: &State) -> MaterialKey {
+    let mut material_key = [[0; 6]; 2];
+
+    for color in [WHITE, BLACK].iter() {
+        for (role, piece_count) in material_key[*color].iter_mut().enumerate() {
+            *piece_count = get_piece_mask(state, *color, role).count_ones() as u8;
+        }
+    }
+
+    material_key
+}
+
+#[inline]
+fn flip_material_key(material_key: &MaterialKey) -> MaterialKey {
+    [material_key[BLACK], material_key[WHITE]]
+}
+
+#[inline]
+fn get_piece_count(material_key: &MaterialKey) -> usize {
+    material_key.iter().flatten().map(|count| *count as usize).sum()
+}
+
+#[inline]
+fn has_pawns(material_key: &MaterialKey) -> bool {
+    material_key[WHITE][PAWN] > 0 || material_key[BLACK][PAWN] > 0
+}
+
+/// Counts the piece types of which a side has exactly one piece.
+#[inline]
+fn get_unique_piece_count(material_key: &MaterialKey) -> usize {
+    material_key.iter().flatten().filter(|count| **count == 1).count()
+}
+
+fn get_piece_mask(state: &State, color: usize, role: usize) -> u64 {
+    let bitboard = &state.bitboard;
+
+    match (color, role) {
+        (WHITE, 0) => bitboard.w_pawn,
+        (WHITE, 1) => bitboard.w_knight,
+        (WHITE, 2) => bitboard.w_bishop,
+        (WHITE, 3) => bitboard.w_rook,
+        (WHITE, 4) => bitboard.w_queen,
+        (WHITE, _) => 1 << state.wk_index,
+        (_, 0) => bitboard.b_pawn,
+        (_, 1) => bitboard.b_knight,
+        (_, 2) => bitboard.b_bishop,
+        (_, 3) => bitboard.b_rook,
+        (_, 4) => bitboard.b_queen,
+        _ => 1 << state.bk_index,
+    }
+}
+
+#[inline]
+fn is_capture(state: &State, to: usize, tp: u8) -> bool {
+    tp == def::MOV_ENP || (tp != def::MOV_CAS && state.squares[to] != 0)
+}
+
+fn is_checkmate(state: &State) -> bool {
+    mov_table::is_in_check(state, state.player) && mov_table::gen_legal_mov_list(state).is_empty()
+}
+
+/// Returns whether a position repeated since the last capture or pawn move.
+fn has_repeated(state: &State) -> bool {
+    let history_len = state.history_pos_stack.len();
+    let recent_pos_list = &state.history_pos_stack[history_len - history_len.min(state.non_cap_mov_count as usize)..];
+
+    recent_pos_list.iter().enumerate().any(|(pos_index, pos_hash)| *pos_hash == state.hash_key || recent_pos_list[pos_index + 1..].contains(pos_hash))
+}
+
+#[inline]
+fn get_dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        WDL_WIN => 1,
+        WDL_CURSED_WIN => 101,
+        WDL_BLESSED_LOSS => -101,
+        WDL_LOSS => -1,
+        _ => 0,
+    }
+}
+
+/// Increases the distance of a non-zero DTZ value by `plies`.
+#[inline]
+fn add_plies(dtz: i32, plies: i32) -> i32 {
+    dtz + dtz.signum() * plies
+}
+
+fn encode_triple(index1: usize, index2: usize, index3: usize) -> u64 {
+    let adjust1 = (index2 > index1) as u64;
+    let adjust2 = (index3 > index1) as u64 + (index3 > index2) as u64;
+    let (rank1, rank2, rank3) = ((index1 / def::DIM_SIZE) as u64, (index2 / def::DIM_SIZE) as u64, (index3 / def::DIM_SIZE) as u64);
+
+    if get_diagonal_offset(index1) != 0 {
+        TRIANGLE[index1] * 63 * 62 + (index2 as u64 - adjust1) * 62 + (index3 as u64 - adjust2)
+    } else if get_diagonal_offset(index2) != 0 {
+        6 * 63 * 62 + rank1 * 28 * 62 + LOWER[index2] * 62 + index3 as u64 - adjust2
+    } else if get_diagonal_offset(index3) != 0 {
+        6 * 63 * 62 + 4 * 28 * 62 + rank1 * 7 * 28 + (rank2 - adjust1) * 28 + LOWER[index3]
+    } else {
+        6 * 63 * 62 + 4 * 28 * 62 + 4 * 7 * 28 + rank1 * 7 * 6 + (rank2 - adjust1) * 6 + (rank3 - adjust2)
+    }
+}
+
+/// Positive above the a1-h8 diagonal, negative below it.
+#[inline]
+fn get_diagonal_offset(index: usize) -> i32 {
+    (index / def::DIM_SIZE) as i32 - (index % def::DIM_SIZE) as i32
+}
+
+#[inline]
+fn flip_vertical(index: usize) -> usize {
+    index ^ 0b111000
+}
+
+#[inline]
+fn flip_horizontal(index: usize) -> usize {
+    index ^ 0b111
+}
+
+#[inline]
+fn flip_diagonal(index: usize) -> usize {
+    ((index >> 3) | (index << 3)) & 0b111111
+}
+
+fn binomial(mut n: u64, k: u64) -> u64 {
+    if k > n {
+        return 0
+    }
+
+    let k = k.min(n - k);
+    let mut result = 1;
+
+    for d in 1..=k {
+        result = result * n / d;
+        n -= 1;
+    }
+
+    result
+}
+
+#[inline]
+fn get_sym_children(sym_tree: &[u8], sym: usize) -> (usize, usize) {
+    let node = &sym_tree[sym * 3..sym * 3 + 3];
+    let left = ((node[1] as usize & 0xf) << 8) | node[0] as usize;
+    let right = ((node[2] as usize) << 4) | (node[1] as usize >> 4);
+
+    (left, right)
+}
+
+fn set_sym_len(sym_tree: &[u8], sym_len_list: &mut [u8], visited_list: &mut [bool], sym: usize, depth: u8) -> Option<()> {
+    if *visited_list.get(sym)? {
+        return Some(())
+    }
+
+    let (left, right) = get_sym_children(sym_tree, sym);
+
+    if right == 0xfff {
+        sym_len_list[sym] = 0;
+    } else {
+        let depth = depth.checked_sub(1)?;
+
+        set_sym_len(sym_tree, sym_len_list, visited_list, left, depth)?;
+        set_sym_len(sym_tree, sym_len_list, visited_list, right, depth)?;
+
+        sym_len_list[sym] = sym_len_list[left].checked_add(sym_len_list[right])?.checked_add(1)?;
+    }
+
+    visited_list[sym] = true;
+
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bitboard::BitMask,
+        prng::XorshiftPrng,
+    };
+
+    const TEST_TB_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/res/syzygy");
+
+    fn load_test_tablebase() -> SyzygyTablebase {
+        let mut tablebase = SyzygyTablebase::new();
+        assert_eq!(24, tablebase.add_directory(Path::new(TEST_TB_PATH)).unwrap());
+        tablebase
+    }
+
+    fn format_mov_list(mov_list: &[u32]) -> Vec<String> {
+        mov_list.iter().map(|mov| util::format_mov(*mov)).collect()
+    }
+
+    #[test]
+    fn test_parse_material_key() {
+        assert_eq!(Some([[1, 0, 0, 1, 0, 1], [0, 0, 0, 1, 0, 1]]), parse_material_key("KRPvKR"));
+        assert_eq!(Some([[0, 2, 0, 0, 0, 1], [0, 0, 0, 0, 0, 1]]), parse_material_key("KNNvK"));
+        assert_eq!(None, parse_material_key("KRvR"));
+        assert_eq!(None, parse_material_key("KXvK"));
+        assert_eq!(None, parse_material_key("KQQQQvKRR"));
+
+        let mut tablebase = SyzygyTablebase::new();
+        assert!(matches!(tablebase.add_file(Path::new("KRvK.bin")), Err(SyzygyError::InvalidName(_))));
+        assert!(matches!(tablebase.add_file(Path::new("KRvK.rtbw")), Err(SyzygyError::Read(_, _))));
+        assert_eq!(0, tablebase.get_max_piece_count());
+    }
+
+    #[test]
+    fn test_probe_wdl_dtz() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let tablebase = load_test_tablebase();
+
+        assert_eq!(4, tablebase.get_max_piece_count());
+
+        let case_list = [
+            ("8/2K5/8/8/8/8/3p4/1k2N3 b - - 0 1", WDL_WIN, 1),
+            ("8/4r3/8/K7/4R3/8/8/5k2 w - - 0 1", WDL_WIN, 1),
+            ("8/8/R2k4/8/8/K7/8/4r3 b - - 0 1", WDL_DRAW, 0),
+            ("K7/8/5r2/8/3k4/5p2/8/8 w - - 0 1", WDL_LOSS, -2),
+            ("1r4k1/8/8/p7/8/8/8/K7 b - - 0 1", WDL_WIN, 1),
+            ("8/8/8/1B6/5K2/3n4/8/5k2 w - - 0 1", WDL_DRAW, 0),
+            ("8/5p2/6k1/K7/8/8/8/8 w - - 0 1", WDL_LOSS, -2),
+            ("K5n1/8/8/7n/k7/8/8/8 w - - 0 1", WDL_DRAW, 0),
+            ("8/8/Q1q5/8/8/2k5/4K3/8 w - - 0 1", WDL_WIN, 1),
+            ("1bB5/8/1k6/8/8/8/6K1/8 b - - 0 1", WDL_DRAW, 0),
+            ("8/8/8/2R5/1K6/8/5k2/8 w - - 0 1", WDL_WIN, 21),
+            ("8/3k4/8/8/8/8/4P3/3K4 w - - 0 1", WDL_DRAW, 0),
+        ];
+
+        for (fen, expected_wdl, expected_dtz) in case_list.iter() {
+            let mut state = State::new(fen, &zob_keys, &bitmask);
+            assert_eq!(Some(*expected_wdl), tablebase.probe_wdl(&mut state), "{}", fen);
+            assert_eq!(Some(*expected_dtz), tablebase.probe_dtz(&mut state), "{}", fen);
+            assert_eq!(*fen, state.to_fen());
+        }
+
+        let mut state = State::new("8/8/8/8/8/8/8/K1k5 w - - 0 1", &zob_keys, &bitmask);
+        assert_eq!(Some(WDL_DRAW), tablebase.probe_wdl(&mut state));
+
+        let mut state = State::new("8/8/8/8/8/1k6/1p6/KQ3Q2 b - - 0 1", &zob_keys, &bitmask);
+        assert_eq!(None, tablebase.probe_wdl(&mut state));
+
+        let mut state = State::new("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1", &zob_keys, &bitmask);
+        assert_eq!(None, tablebase.probe_wdl(&mut state));
+    }
+
+    #[test]
+    fn test_filter_root_movs() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let tablebase = load_test_tablebase();
+
+        let mut state = State::new("8/4r3/8/K7/4R3/8/8/5k2 w - - 0 1", &zob_keys, &bitmask);
+        let mov_list = mov_table::gen_legal_mov_list(&state);
+        let (dtz_mov_list, root_wdl) = tablebase.filter_root_movs_by_dtz(&mut state, &mov_list).unwrap();
+        assert_eq!(vec!["e4e7"], format_mov_list(&dtz_mov_list));
+        assert_eq!(WDL_WIN, root_wdl);
+        let (wdl_mov_list, root_wdl) = tablebase.filter_root_movs_by_wdl(&mut state, &mov_list).unwrap();
+        assert_eq!(vec!["e4e7"], format_mov_list(&wdl_mov_list));
+        assert_eq!(WDL_WIN, root_wdl);
+
+        let mut state = State::new("8/8/8/2R5/1K6/8/5k2/8 w - - 0 1", &zob_keys, &bitmask);
+        let mov_list = mov_table::gen_legal_mov_list(&state);
+        let (dtz_mov_list, root_wdl) = tablebase.filter_root_movs_by_dtz(&mut state, &mov_list).unwrap();
+        assert_eq!(mov_list, dtz_mov_list);
+        assert_eq!(WDL_WIN, root_wdl);
+
+        let mut state = State::new("8/8/8/2R5/1K6/8/5k2/8 w - - 90 1", &zob_keys, &bitmask);
+        let (dtz_mov_list, root_wdl) = tablebase.filter_root_movs_by_dtz(&mut state, &mov_list).unwrap();
+        assert_eq!(vec!["b4c3", "c5c3", "c5c4"], format_mov_list(&dtz_mov_list));
+        assert_eq!(WDL_CURSED_WIN, root_wdl);
+
+        let mut state = State::new("8/8/8/8/8/1k6/1p6/KQ3Q2 b - - 0 1", &zob_keys, &bitmask);
+        let mov_list = mov_table::gen_legal_mov_list(&state);
+        assert_eq!(None, tablebase.filter_root_movs_by_dtz(&mut state, &mov_list));
+    }
+}
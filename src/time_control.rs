@@ -9,6 +9,7 @@ pub struct TimeCapacity {
     pub extra_time_millis: u128,
 }
 
+#[allow(clippy::redundant_field_names)]
 pub fn calculate_time_capacity(total_time_millis: u128, moves_to_go: u128, increment: u128) -> TimeCapacity {
     let main_time_millis = total_time_millis / (1 + moves_to_go * 8 / 10) + increment * 9 / 10;
 
@@ -4,14 +4,21 @@
 
 use crate::{
     def,
+    eval_params::{self, EvalParams},
+    mov_table,
+    state::State,
     util,
 };
 
+use std::fmt;
 use std::io::{self, prelude::*};
+use std::str::FromStr;
 
 const DEFAULT_MOVS_TO_GO: u128 = 50;
 const DEFAULT_MOVS_TO_GO_NO_INCREMENT: u128 = 60;
 
+const GO_SUB_CMDS: [&str; 12] = ["searchmoves", "ponder", "wtime", "btime", "winc", "binc", "movestogo", "depth", "nodes", "mate", "movetime", "infinite"];
+
 pub const FEN_START_POS: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
 pub struct Rawmov {
@@ -27,231 +34,755 @@ pub struct TimeInfo {
     pub increment_millis: u128,
 }
 
+/// The limits given to `go`; the search stops at whichever is reached first, or on `stop` if there are none.
+#[derive(Default)]
+pub struct GoLimits {
+    pub time_info: Option<(TimeInfo, TimeInfo)>,
+    pub movetime_millis: Option<u128>,
+    pub depth: Option<u8>,
+    pub node_count: Option<u64>,
+    pub mate_mov_count: Option<u8>,
+}
+
+impl GoLimits {
+    pub fn is_infinite(&self) -> bool {
+        self.time_info.is_none() && self.movetime_millis.is_none() && self.depth.is_none() && self.node_count.is_none() && self.mate_mov_count.is_none()
+    }
+}
+
 pub enum UciCommand {
     Noop,
+    IsReady,
+    Stop,
+    PonderHit,
+    Quit,
     Reset,
     IgnoredOption,
     Perft(u8),
+    PerftDivide(u8),
+    PerftHashed(u8),
+    PerftSuite(String, u8),
     SetHashSize(usize),
+    SetThreadCount(usize),
+    SetMultiPV(usize),
+    SetChess960(bool),
+    SetOwnBook(bool),
+    SetBookFile(String),
+    SetBookDepth(u16),
+    SetBestBookMove(bool),
+    SetSyzygyPath(String),
+    SetSyzygyProbeDepth(u8),
+    SetSyzygyProbeLimit(usize),
+    SetEvalFile(String),
+    SetEvalParam(String, i32),
     Position(String, Vec<Rawmov>),
     PrintDebugInfo,
-    StartSearchWithTime(u128),
-    StartSearchWithComplextTimeControl((TimeInfo, TimeInfo)),
-    StartSearchToDepth(u8),
-    StartSearchInfinite,
+    StartSearch(GoLimits),
+    StartSearchWithMovs(Vec<Rawmov>, Box<UciCommand>),
+    StartPonder(Box<UciCommand>),
+}
+
+#[derive(PartialEq, Debug)]
+pub enum UciError {
+    UnknownCommand(String),
+    UnknownParam(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+    InvalidFen(String),
+    InvalidMov(String),
+    IllegalMov(String),
+    InvalidFile(String, String),
 }
 
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UciError::UnknownCommand(cmd) => write!(f, "unknown command {}", cmd),
+            UciError::UnknownParam(param) => write!(f, "unknown parameter {}", param),
+            UciError::MissingValue(param) => write!(f, "missing value for {}", param),
+            UciError::InvalidValue(param, value) => write!(f, "invalid value {} for {}", value, param),
+            UciError::InvalidFen(reason) => write!(f, "invalid fen: {}", reason),
+            UciError::InvalidMov(mov_str) => write!(f, "invalid move {}", mov_str),
+            UciError::IllegalMov(mov_str) => write!(f, "illegal move {}", mov_str),
+            UciError::InvalidFile(path, reason) => write!(f, "unable to use file {}: {}", path, reason),
+        }
+    }
+}
+
+/// Parses a command and reports any error to the GUI as `info string`, in which case `Noop` is returned.
 pub fn process_uci_cmd(uci_cmd: &str) -> UciCommand {
-    let mut cmd_seq: Vec<&str> = uci_cmd.split(' ').collect();
+    match parse_uci_cmd(uci_cmd) {
+        Ok(uci_cmd) => uci_cmd,
+        Err(error) => {
+            print_error(&error);
+            UciCommand::Noop
+        },
+    }
+}
+
+pub fn print_error(error: &UciError) {
+    println!("info string {}", error);
+    io::stdout().flush().ok();
+}
+
+pub fn parse_uci_cmd(uci_cmd: &str) -> Result<UciCommand, UciError> {
+    let cmd_seq: Vec<&str> = uci_cmd.split_whitespace().collect();
+
+    if cmd_seq.is_empty() {
+        return Ok(UciCommand::Noop)
+    }
+
     match cmd_seq[0] {
         "uci" => {
             println!("id name {} {}", def::ENGINE_NAME, def::VERSION);
             println!("id author {}", def::AUTHOR);
             println!("option name Hash type spin default {} min {} max {}", def::DEFAULT_HASH_SIZE_MB, def::MIN_HASH_SIZE_MB, def::MAX_HASH_SIZE_MB);
+            println!("option name Threads type spin default {} min 1 max {}", def::DEFAULT_THREAD_COUNT, def::MAX_THREAD_COUNT);
+            println!("option name Ponder type check default false");
+            println!("option name MultiPV type spin default {} min 1 max {}", def::DEFAULT_MULTI_PV, def::MAX_MULTI_PV);
+            println!("option name UCI_Chess960 type check default false");
+            println!("option name OwnBook type check default false");
+            println!("option name BookFile type string default <empty>");
+            println!("option name BookDepth type spin default {} min 1 max {}", def::DEFAULT_BOOK_DEPTH, def::MAX_BOOK_DEPTH);
+            println!("option name BestBookMove type check default false");
+            println!("option name SyzygyPath type string default <empty>");
+            println!("option name SyzygyProbeDepth type spin default {} min 1 max {}", def::DEFAULT_SYZYGY_PROBE_DEPTH, def::MAX_SYZYGY_PROBE_DEPTH);
+            println!("option name SyzygyProbeLimit type spin default {} min 0 max {}", def::DEFAULT_SYZYGY_PROBE_LIMIT, def::DEFAULT_SYZYGY_PROBE_LIMIT);
+            println!("option name EvalFile type string default <empty>");
+
+            let mut eval_params = EvalParams::default();
+            for name in eval_params::SCALAR_PARAM_NAMES.iter() {
+                let default_val = *eval_params.get_scalar_mut(name).unwrap();
+                println!("option name {} type spin default {} min {} max {}", name, default_val, eval_params::MIN_PARAM_VAL, eval_params::MAX_PARAM_VAL);
+            }
+
             println!("uciok");
             io::stdout().flush().ok();
-            UciCommand::Noop
-        },
-        "printdebug" => UciCommand::PrintDebugInfo,
-        "isready" => {
-            println!("readyok");
-            io::stdout().flush().ok();
-            UciCommand::Noop
+            Ok(UciCommand::Noop)
         },
-        "setoption" => {
-            match cmd_seq[2] {
-                "Hash" => {
-                    let hash_size_mb = cmd_seq[4].parse::<usize>().unwrap();
-                    let hash_ratio = hash_size_mb / def::MIN_HASH_SIZE_MB;
-
-                    if hash_ratio == 0 || hash_ratio & (hash_ratio - 1) != 0 {
-                        println!("hash size {} is not supported", hash_size_mb);
-                        return UciCommand::IgnoredOption
-                    }
+        "printdebug" => Ok(UciCommand::PrintDebugInfo),
+        "isready" => Ok(UciCommand::IsReady),
+        "stop" => Ok(UciCommand::Stop),
+        "quit" => Ok(UciCommand::Quit),
+        "setoption" => parse_setoption_cmd(&cmd_seq[1..]),
+        "register" => Ok(UciCommand::Noop),
+        "ucinewgame" => Ok(UciCommand::Reset),
+        "position" => parse_position_cmd(&cmd_seq[1..]),
+        "go" => parse_go_cmd(&cmd_seq[1..]),
+        "perft" => parse_perft_cmd(&cmd_seq[1..]),
+        "perftsuite" => parse_perft_suite_cmd(&cmd_seq[1..]),
+        "ponderhit" => Ok(UciCommand::PonderHit),
+        cmd => Err(UciError::UnknownCommand(cmd.to_owned())),
+    }
+}
 
-                    UciCommand::SetHashSize(hash_ratio * def::MIN_HASH_SIZE_UNIT)
-                },
-                _ => UciCommand::IgnoredOption,
-            }
-        },
-        "register" => UciCommand::Noop,
-        "ucinewgame" => UciCommand::Reset,
-        "position" => match cmd_seq[1] {
-            "startpos" => {
-                if cmd_seq.len() > 3 {
-                    process_position_with_mov_list(FEN_START_POS, cmd_seq.split_off(3))
-                } else {
-                    process_position(FEN_START_POS)
-                }
-            },
-            "fen" => {
-                let len = cmd_seq.len();
-                let mut fen_str_vec = vec![""; len-2];
-                fen_str_vec.copy_from_slice(&cmd_seq[2..len]);
-                process_position(&fen_str_vec.join(" "))
-            },
-            _ => {
-                eprintln!("only support fen or startpos with mov list");
-                UciCommand::Noop
-            }
-        },
-        "go" => process_go_cmd(cmd_seq.split_off(0)),
-        "perft" => UciCommand::Perft(cmd_seq[1].parse::<u8>().unwrap()),
-        "ponderhit" => UciCommand::Noop,
-        _ => {
-            eprintln!("unknown uci command {}", cmd_seq[0]);
-            UciCommand::Noop
-        }
+fn parse_perft_cmd(cmd_seq: &[&str]) -> Result<UciCommand, UciError> {
+    match cmd_seq.first() {
+        Some(&"divide") => Ok(UciCommand::PerftDivide(parse_value("perft divide", cmd_seq.get(1))?)),
+        Some(&"hash") => Ok(UciCommand::PerftHashed(parse_value("perft hash", cmd_seq.get(1))?)),
+        depth => Ok(UciCommand::Perft(parse_value("perft", depth)?)),
     }
 }
 
-fn process_go_cmd(go_cmd_seq: Vec<&str>) -> UciCommand {
-    match go_cmd_seq[1] {
-        "movestogo" => process_time_control_with_movestogo(go_cmd_seq),
-        "wtime" => process_time_control(go_cmd_seq),
-        "movetime" => UciCommand::StartSearchWithTime(go_cmd_seq[2].parse::<u128>().unwrap()),
-        "depth" => UciCommand::StartSearchToDepth(go_cmd_seq[2].parse::<u8>().unwrap()),
-        "infinite" => UciCommand::StartSearchInfinite,
-        "ponder" => UciCommand::Noop,
-        sub_cmd => panic!("unsupported sub command {}", sub_cmd),
+/// `perftsuite <path> [max depth]`; without a depth every depth listed in the file is checked.
+fn parse_perft_suite_cmd(cmd_seq: &[&str]) -> Result<UciCommand, UciError> {
+    if cmd_seq.is_empty() {
+        return Err(UciError::MissingValue("perftsuite".to_owned()))
+    }
+
+    match cmd_seq.last().and_then(|depth| depth.parse::<u8>().ok()) {
+        Some(max_depth) if cmd_seq.len() > 1 => Ok(UciCommand::PerftSuite(cmd_seq[..cmd_seq.len() - 1].join(" "), max_depth)),
+        _ => Ok(UciCommand::PerftSuite(cmd_seq.join(" "), u8::MAX)),
     }
 }
 
-fn process_time_control(go_cmd_seq: Vec<&str>) -> UciCommand {
-    assert!(go_cmd_seq[1] == "wtime");
-    let wtime = go_cmd_seq[2].parse::<u128>().unwrap();
+fn parse_value<T: FromStr>(param: &str, value: Option<&&str>) -> Result<T, UciError> {
+    match value {
+        Some(value) => value.parse::<T>().map_err(|_| UciError::InvalidValue(param.to_owned(), (*value).to_owned())),
+        None => Err(UciError::MissingValue(param.to_owned())),
+    }
+}
 
-    assert!(go_cmd_seq[3] == "btime");
-    let btime = go_cmd_seq[4].parse::<u128>().unwrap();
+fn parse_millis(param: &str, value: Option<&&str>) -> Result<u128, UciError> {
+    // some GUIs send a negative remaining time once the clock has run out
+    let millis: i128 = parse_value(param, value)?;
+    Ok(millis.max(0) as u128)
+}
 
-    let movs_to_go;
-    let mut winc = 0;
-    let mut binc = 0;
+fn parse_setoption_cmd(option_seq: &[&str]) -> Result<UciCommand, UciError> {
+    let name_index = option_seq.iter().position(|token| *token == "name").ok_or_else(|| UciError::MissingValue("name".to_owned()))?;
+    let value_index = option_seq.iter().position(|token| *token == "value").unwrap_or(option_seq.len());
 
-    if go_cmd_seq.len() > 5 && go_cmd_seq[5] == "movestogo" {
-        movs_to_go = go_cmd_seq[6].parse::<u128>().unwrap();
-    } else if go_cmd_seq.len() > 9 && go_cmd_seq[9] == "movestogo" {
-        if go_cmd_seq[5] == "winc" {
-            winc = go_cmd_seq[6].parse::<u128>().unwrap();
-        }
+    if value_index < name_index {
+        return Err(UciError::MissingValue("name".to_owned()))
+    }
 
-        if go_cmd_seq[7] == "binc" {
-            binc = go_cmd_seq[8].parse::<u128>().unwrap()
-        }
+    let name = option_seq[name_index+1..value_index].join(" ");
+    let value = option_seq.get(value_index+1..).map(|value_seq| value_seq.join(" ")).unwrap_or_default();
+    let value = if value.is_empty() { None } else { Some(value.as_str()) };
 
-        movs_to_go = go_cmd_seq[10].parse::<u128>().unwrap();
-    } else if go_cmd_seq.len() > 5 {
-        if go_cmd_seq[5] == "winc" {
-            winc = go_cmd_seq[6].parse::<u128>().unwrap();
-        }
+    match name.to_lowercase().as_str() {
+        "hash" => {
+            let hash_size_mb: usize = parse_value(&name, value.as_ref())?;
+            let hash_ratio = hash_size_mb / def::MIN_HASH_SIZE_MB;
 
-        if go_cmd_seq[7] == "binc" {
-            binc = go_cmd_seq[8].parse::<u128>().unwrap()
-        }
+            if hash_ratio == 0 || hash_ratio & (hash_ratio - 1) != 0 {
+                return Err(UciError::InvalidValue(name, hash_size_mb.to_string()))
+            }
 
-        movs_to_go = DEFAULT_MOVS_TO_GO;
-    } else {
-        movs_to_go = DEFAULT_MOVS_TO_GO_NO_INCREMENT;
-    };
+            Ok(UciCommand::SetHashSize(hash_ratio * def::MIN_HASH_SIZE_UNIT))
+        },
+        "threads" => {
+            let thread_count: usize = parse_value(&name, value.as_ref())?;
+            Ok(UciCommand::SetThreadCount(thread_count.clamp(1, def::MAX_THREAD_COUNT)))
+        },
+        "multipv" => {
+            let multi_pv: usize = parse_value(&name, value.as_ref())?;
+            Ok(UciCommand::SetMultiPV(multi_pv.clamp(1, def::MAX_MULTI_PV)))
+        },
+        "uci_chess960" => {
+            let is_chess960: bool = parse_value(&name, value.as_ref())?;
+            Ok(UciCommand::SetChess960(is_chess960))
+        },
+        "ownbook" => {
+            let is_own_book: bool = parse_value(&name, value.as_ref())?;
+            Ok(UciCommand::SetOwnBook(is_own_book))
+        },
+        "bookfile" => {
+            let book_path = match value {
+                Some("<empty>") | None => "",
+                Some(book_path) => book_path,
+            };
 
-    UciCommand::StartSearchWithComplextTimeControl((
-        TimeInfo{
-            all_time_millis: wtime,
-            moves_to_go: movs_to_go,
-            increment_millis: winc,
+            Ok(UciCommand::SetBookFile(book_path.to_owned()))
         },
-        TimeInfo{
-            all_time_millis: btime,
-            moves_to_go: movs_to_go,
-            increment_millis: binc,
-        }
-    ))
+        "bookdepth" => {
+            let book_depth: u16 = parse_value(&name, value.as_ref())?;
+            Ok(UciCommand::SetBookDepth(book_depth.clamp(1, def::MAX_BOOK_DEPTH)))
+        },
+        "bestbookmove" => {
+            let is_best_book_mov: bool = parse_value(&name, value.as_ref())?;
+            Ok(UciCommand::SetBestBookMove(is_best_book_mov))
+        },
+        "syzygypath" => {
+            let syzygy_path = match value {
+                Some("<empty>") | None => "",
+                Some(syzygy_path) => syzygy_path,
+            };
+
+            Ok(UciCommand::SetSyzygyPath(syzygy_path.to_owned()))
+        },
+        "syzygyprobedepth" => {
+            let probe_depth: u8 = parse_value(&name, value.as_ref())?;
+            Ok(UciCommand::SetSyzygyProbeDepth(probe_depth.clamp(1, def::MAX_SYZYGY_PROBE_DEPTH)))
+        },
+        "syzygyprobelimit" => {
+            let probe_limit: usize = parse_value(&name, value.as_ref())?;
+            Ok(UciCommand::SetSyzygyProbeLimit(probe_limit.min(def::DEFAULT_SYZYGY_PROBE_LIMIT)))
+        },
+        "evalfile" => {
+            let eval_path = match value {
+                Some("<empty>") | None => "",
+                Some(eval_path) => eval_path,
+            };
+
+            Ok(UciCommand::SetEvalFile(eval_path.to_owned()))
+        },
+        _ if EvalParams::default().get_scalar_mut(&name).is_some() => {
+            let param_val: i32 = parse_value(&name, value.as_ref())?;
+            Ok(UciCommand::SetEvalParam(name, param_val.clamp(eval_params::MIN_PARAM_VAL, eval_params::MAX_PARAM_VAL)))
+        },
+        _ => Ok(UciCommand::IgnoredOption),
+    }
 }
 
-fn process_time_control_with_movestogo(go_cmd_seq: Vec<&str>) -> UciCommand {
-    assert!(go_cmd_seq[1] == "movestogo");
-    let movs_to_go = go_cmd_seq[2].parse::<u128>().unwrap();
+fn parse_position_cmd(position_seq: &[&str]) -> Result<UciCommand, UciError> {
+    let movs_index = position_seq.iter().position(|token| *token == "moves").unwrap_or(position_seq.len());
 
-    assert!(go_cmd_seq[3] == "wtime");
-    let wtime = go_cmd_seq[4].parse::<u128>().unwrap();
+    let fen_str = match position_seq.first() {
+        Some(&"startpos") => FEN_START_POS.to_owned(),
+        Some(&"fen") => parse_fen(&position_seq[1..movs_index])?,
+        Some(token) => return Err(UciError::UnknownParam((*token).to_owned())),
+        None => return Err(UciError::MissingValue("position".to_owned())),
+    };
 
-    assert!(go_cmd_seq[5] == "btime");
-    let btime = go_cmd_seq[6].parse::<u128>().unwrap();
+    let mov_list = position_seq.get(movs_index+1..).unwrap_or_default().iter().map(|mov_str| parse_mov_str(mov_str)).collect::<Result<_, _>>()?;
 
-    let mut winc = 0;
-    let mut binc = 0;
+    Ok(UciCommand::Position(fen_str, mov_list))
+}
 
-    if go_cmd_seq.len() > 7 {
-        if go_cmd_seq[7] == "winc" {
-            winc = go_cmd_seq[8].parse::<u128>().unwrap();
-        }
+/// Only checks that a FEN string is present; `State::from_fen` validates the position itself.
+fn parse_fen(fen_seq: &[&str]) -> Result<String, UciError> {
+    if fen_seq.is_empty() {
+        return Err(UciError::MissingValue("fen".to_owned()))
+    }
 
-        if go_cmd_seq[9] == "binc" {
-            binc = go_cmd_seq[10].parse::<u128>().unwrap();
+    Ok(fen_seq.join(" "))
+}
+
+fn parse_go_cmd(go_cmd_seq: &[&str]) -> Result<UciCommand, UciError> {
+    let mut search_mov_list = None;
+    let mut ponder = false;
+    let mut infinite = false;
+    let mut wtime = None;
+    let mut btime = None;
+    let mut winc = None;
+    let mut binc = None;
+    let mut movs_to_go = None;
+    let mut go_limits = GoLimits::default();
+
+    let mut token_iter = go_cmd_seq.iter().peekable();
+    while let Some(token) = token_iter.next() {
+        match *token {
+            "searchmoves" => {
+                let mut mov_list = Vec::new();
+
+                while let Some(mov_str) = token_iter.peek() {
+                    if GO_SUB_CMDS.contains(mov_str) {
+                        break
+                    }
+
+                    mov_list.push(parse_mov_str(mov_str)?);
+                    token_iter.next();
+                }
+
+                search_mov_list = Some(mov_list);
+            },
+            "ponder" => ponder = true,
+            "infinite" => infinite = true,
+            "wtime" => wtime = Some(parse_millis(token, token_iter.next())?),
+            "btime" => btime = Some(parse_millis(token, token_iter.next())?),
+            "winc" => winc = Some(parse_millis(token, token_iter.next())?),
+            "binc" => binc = Some(parse_millis(token, token_iter.next())?),
+            "movestogo" => movs_to_go = Some(parse_value::<u128>(token, token_iter.next())?.max(1)),
+            "depth" => go_limits.depth = Some(parse_value::<u8>(token, token_iter.next())?.max(1)),
+            "nodes" => go_limits.node_count = Some(parse_value(token, token_iter.next())?),
+            "mate" => go_limits.mate_mov_count = Some(parse_value(token, token_iter.next())?),
+            "movetime" => go_limits.movetime_millis = Some(parse_millis(token, token_iter.next())?),
+            param => return Err(UciError::UnknownParam(param.to_owned())),
         }
     }
 
-    UciCommand::StartSearchWithComplextTimeControl((
-        TimeInfo{
-            all_time_millis: wtime,
-            moves_to_go: movs_to_go,
-            increment_millis: winc,
+    if wtime.is_some() || btime.is_some() {
+        let movs_to_go = movs_to_go.unwrap_or(if winc.is_some() || binc.is_some() {
+            DEFAULT_MOVS_TO_GO
+        } else {
+            DEFAULT_MOVS_TO_GO_NO_INCREMENT
+        });
+
+        // a side without a clock is not limited by time
+        go_limits.time_info = Some((
+            TimeInfo{
+                all_time_millis: wtime.unwrap_or(u128::MAX),
+                moves_to_go: movs_to_go,
+                increment_millis: winc.unwrap_or(0),
+            },
+            TimeInfo{
+                all_time_millis: btime.unwrap_or(u128::MAX),
+                moves_to_go: movs_to_go,
+                increment_millis: binc.unwrap_or(0),
+            }
+        ));
+    }
+
+    // infinite overrides all other limits
+    if infinite {
+        go_limits = GoLimits::default();
+    }
+
+    let mut go_cmd = UciCommand::StartSearch(go_limits);
+
+    if ponder {
+        go_cmd = UciCommand::StartPonder(Box::new(go_cmd));
+    }
+
+    if let Some(mov_list) = search_mov_list {
+        go_cmd = UciCommand::StartSearchWithMovs(mov_list, Box::new(go_cmd));
+    }
+
+    Ok(go_cmd)
+}
+
+/// Applies a move received in long algebraic notation to `state`, provided it is legal there.
+pub fn do_raw_mov(state: &mut State, raw_mov: Rawmov) -> Result<(), UciError> {
+    match match_raw_mov_list(state, std::slice::from_ref(&raw_mov)).first() {
+        Some(&mov) => {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+            state.do_mov(from, to, tp, promo);
+            Ok(())
         },
-        TimeInfo{
-            all_time_millis: btime,
-            moves_to_go: movs_to_go,
-            increment_millis: binc,
-        }
-    ))
+        None => Err(UciError::IllegalMov(raw_mov.origin_mov_str)),
+    }
 }
 
-fn process_position(fen_str: &str) -> UciCommand {
-    UciCommand::Position(fen_str.to_owned(), vec![])
+/// Returns the legal moves in `state` that match the given raw moves, in the order they were given.
+/// Castling is accepted as king-takes-rook in any mode, and as the king's destination outside of Chess960.
+pub fn match_raw_mov_list(state: &mut State, raw_mov_list: &[Rawmov]) -> Vec<u32> {
+    let legal_mov_list = mov_table::gen_legal_mov_list(state);
+
+    raw_mov_list.iter().filter_map(|raw_mov| {
+        legal_mov_list.iter().copied().find(|&mov| {
+            let (from, to, tp, promo) = util::decode_u32_mov(mov);
+
+            if tp == def::MOV_CAS {
+                return format_mov(state, mov) == raw_mov.origin_mov_str || format_cas_mov_as_rook_cap(state, mov) == raw_mov.origin_mov_str
+            }
+
+            from == raw_mov.from && to == raw_mov.to && (if promo == 0 { raw_mov.promo.is_empty() } else { raw_mov.promo.starts_with(util::map_promo_piece_to_char(promo)) })
+        })
+    }).collect()
 }
 
-fn process_position_with_mov_list(fen_str: &str, mov_str_list: Vec<&str>) -> UciCommand {
-    let mut mov_list = Vec::new();
-    for mov_str in mov_str_list {
-        mov_list.push(parse_mov_str(mov_str));
+/// Formats a move for the GUI; in Chess960 mode castling is sent as the king taking its own rook.
+pub fn format_mov(state: &State, mov: u32) -> String {
+    if state.is_chess960 && util::decode_u32_mov(mov).2 == def::MOV_CAS {
+        format_cas_mov_as_rook_cap(state, mov)
+    } else {
+        util::format_mov(mov)
     }
+}
+
+pub fn format_pv(state: &State, pv_table: &[u32]) -> String {
+    let mut pv_line = String::new();
+
+    for mov in pv_table {
+        if *mov == 0 {
+            break
+        }
+
+        pv_line.push_str(&format!("{} ", format_mov(state, *mov)));
+    }
+
+    pv_line
+}
+
+fn format_cas_mov_as_rook_cap(state: &State, mov: u32) -> String {
+    let (from, to, _tp, _promo) = util::decode_u32_mov(mov);
+    let rook_index = state.cas_rook_indexes[def::get_cas_index(to)];
 
-    UciCommand::Position(fen_str.to_owned(), mov_list)
+    format!("{}{}", util::map_index_to_sqr_notation(from), util::map_index_to_sqr_notation(rook_index))
 }
 
-fn parse_mov_str(mov_str: &str) -> Rawmov {
+fn is_valid_sqr_str(sqr_str: &str) -> bool {
+    let sqr_chars: Vec<char> = sqr_str.chars().collect();
+    sqr_chars.len() == 2 && ('a'..='h').contains(&sqr_chars[0]) && ('1'..='8').contains(&sqr_chars[1])
+}
+
+pub fn parse_mov_str(mov_str: &str) -> Result<Rawmov, UciError> {
+    if !mov_str.is_ascii() || (mov_str.len() != 4 && mov_str.len() != 5) {
+        return Err(UciError::InvalidMov(mov_str.to_owned()))
+    }
+
     let from_str = &mov_str[0..2];
     let to_str = &mov_str[2..4];
+    let promo_str = &mov_str[4..];
 
-    let mut promotion_piece = String::new();
-    if mov_str.len() == 5 {
-        promotion_piece.push_str(&mov_str[4..]);
+    if !is_valid_sqr_str(from_str) || !is_valid_sqr_str(to_str) || !["", "q", "r", "b", "n"].contains(&promo_str) {
+        return Err(UciError::InvalidMov(mov_str.to_owned()))
     }
 
-    Rawmov {
+    Ok(Rawmov {
         from: util::map_sqr_notation_to_index(from_str),
         to: util::map_sqr_notation_to_index(to_str),
-        promo: promotion_piece,
+        promo: promo_str.to_owned(),
         origin_mov_str: mov_str.to_owned(),
-    }
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        bitboard::BitMask,
+        prng::XorshiftPrng,
         util,
     };
 
     #[test]
     fn test_parse_mov_str() {
-        let raw_mov = parse_mov_str("e1g1");
+        let raw_mov = parse_mov_str("e1g1").unwrap();
         assert_eq!(util::map_sqr_notation_to_index("e1"), raw_mov.from);
         assert_eq!(util::map_sqr_notation_to_index("g1"), raw_mov.to);
         assert_eq!("".to_owned(), raw_mov.promo);
 
-        let raw_mov = parse_mov_str("a7b8q");
+        let raw_mov = parse_mov_str("a7b8q").unwrap();
         assert_eq!(util::map_sqr_notation_to_index("a7"), raw_mov.from);
         assert_eq!(util::map_sqr_notation_to_index("b8"), raw_mov.to);
         assert_eq!("q".to_owned(), raw_mov.promo);
+
+        assert_eq!(Some(UciError::InvalidMov("a7b8k".to_owned())), parse_mov_str("a7b8k").err());
+    }
+
+    #[test]
+    fn test_process_set_thread_count() {
+        match process_uci_cmd("setoption name Threads value 8") {
+            UciCommand::SetThreadCount(thread_count) => assert_eq!(8, thread_count),
+            _ => panic!("Threads option not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_process_set_multi_pv() {
+        match process_uci_cmd("setoption name MultiPV value 3") {
+            UciCommand::SetMultiPV(multi_pv) => assert_eq!(3, multi_pv),
+            _ => panic!("MultiPV option not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_process_set_chess960() {
+        assert!(matches!(process_uci_cmd("setoption name UCI_Chess960 value true"), UciCommand::SetChess960(true)));
+        assert!(matches!(process_uci_cmd("setoption name UCI_Chess960 value false"), UciCommand::SetChess960(false)));
+        assert_eq!(Some(UciError::InvalidValue("UCI_Chess960".to_owned(), "yes".to_owned())), parse_uci_cmd("setoption name UCI_Chess960 value yes").err());
+    }
+
+    #[test]
+    fn test_process_set_book_options() {
+        assert!(matches!(process_uci_cmd("setoption name OwnBook value true"), UciCommand::SetOwnBook(true)));
+        assert!(matches!(process_uci_cmd("setoption name BestBookMove value false"), UciCommand::SetBestBookMove(false)));
+        assert!(matches!(process_uci_cmd("setoption name BookDepth value 16"), UciCommand::SetBookDepth(16)));
+        assert!(matches!(process_uci_cmd("setoption name BookDepth value 0"), UciCommand::SetBookDepth(1)));
+
+        match process_uci_cmd("setoption name BookFile value books/My Book.bin") {
+            UciCommand::SetBookFile(book_path) => assert_eq!("books/My Book.bin", book_path),
+            _ => panic!("BookFile option not parsed"),
+        }
+
+        match process_uci_cmd("setoption name BookFile value <empty>") {
+            UciCommand::SetBookFile(book_path) => assert!(book_path.is_empty()),
+            _ => panic!("BookFile option not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_process_set_syzygy_options() {
+        assert!(matches!(process_uci_cmd("setoption name SyzygyProbeDepth value 4"), UciCommand::SetSyzygyProbeDepth(4)));
+        assert!(matches!(process_uci_cmd("setoption name SyzygyProbeDepth value 0"), UciCommand::SetSyzygyProbeDepth(1)));
+        assert!(matches!(process_uci_cmd("setoption name SyzygyProbeLimit value 5"), UciCommand::SetSyzygyProbeLimit(5)));
+        assert!(matches!(process_uci_cmd("setoption name SyzygyProbeLimit value 9"), UciCommand::SetSyzygyProbeLimit(7)));
+
+        match process_uci_cmd("setoption name SyzygyPath value /tb/wdl:/tb/dtz") {
+            UciCommand::SetSyzygyPath(syzygy_path) => assert_eq!("/tb/wdl:/tb/dtz", syzygy_path),
+            _ => panic!("SyzygyPath option not parsed"),
+        }
+
+        match process_uci_cmd("setoption name SyzygyPath value <empty>") {
+            UciCommand::SetSyzygyPath(syzygy_path) => assert!(syzygy_path.is_empty()),
+            _ => panic!("SyzygyPath option not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_process_set_eval_options() {
+        match process_uci_cmd("setoption name EvalFile value tuned/eval params.toml") {
+            UciCommand::SetEvalFile(path) => assert_eq!("tuned/eval params.toml", path),
+            _ => panic!("EvalFile option not parsed"),
+        }
+
+        match process_uci_cmd("setoption name EvalFile value <empty>") {
+            UciCommand::SetEvalFile(path) => assert_eq!("", path),
+            _ => panic!("EvalFile option not parsed"),
+        }
+
+        match process_uci_cmd("setoption name TempoVal value 15") {
+            UciCommand::SetEvalParam(name, param_val) => assert_eq!(("TempoVal".to_owned(), 15), (name, param_val)),
+            _ => panic!("TempoVal option not parsed"),
+        }
+
+        match process_uci_cmd("setoption name HangingQPen value -5000") {
+            UciCommand::SetEvalParam(_, param_val) => assert_eq!(eval_params::MIN_PARAM_VAL, param_val),
+            _ => panic!("HangingQPen option not parsed"),
+        }
+
+        assert!(matches!(process_uci_cmd("setoption name NMobScore value 5"), UciCommand::IgnoredOption));
+        assert!(parse_uci_cmd("setoption name TempoVal value high").is_err());
+    }
+
+    #[test]
+    fn test_process_go_nodes() {
+        match process_uci_cmd("go nodes 150000") {
+            UciCommand::StartSearch(go_limits) => assert_eq!(Some(150000), go_limits.node_count),
+            _ => panic!("go nodes not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_process_go_mate() {
+        match process_uci_cmd("go mate 3") {
+            UciCommand::StartSearch(go_limits) => assert_eq!(Some(3), go_limits.mate_mov_count),
+            _ => panic!("go mate not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_process_go_combined_limits() {
+        match process_uci_cmd("go wtime 60000 btime 50000 depth 40 nodes 5000 movetime 2000") {
+            UciCommand::StartSearch(go_limits) => {
+                let (w_time_info, b_time_info) = go_limits.time_info.unwrap();
+                assert_eq!(60000, w_time_info.all_time_millis);
+                assert_eq!(50000, b_time_info.all_time_millis);
+                assert_eq!(Some(40), go_limits.depth);
+                assert_eq!(Some(5000), go_limits.node_count);
+                assert_eq!(Some(2000), go_limits.movetime_millis);
+                assert_eq!(None, go_limits.mate_mov_count);
+            },
+            _ => panic!("go limits not parsed"),
+        }
+
+        match process_uci_cmd("go infinite depth 10") {
+            UciCommand::StartSearch(go_limits) => assert!(go_limits.is_infinite()),
+            _ => panic!("go infinite not parsed"),
+        }
+
+        match process_uci_cmd("go") {
+            UciCommand::StartSearch(go_limits) => assert!(go_limits.is_infinite()),
+            _ => panic!("go not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_process_go_searchmoves() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+        let mut state = State::new(FEN_START_POS, &zob_keys, &bitmask);
+
+        match process_uci_cmd("go searchmoves e2e4 d2d4 e2e5 depth 6") {
+            UciCommand::StartSearchWithMovs(raw_mov_list, go_cmd) => {
+                assert_eq!(3, raw_mov_list.len());
+
+                let mov_list = match_raw_mov_list(&mut state, &raw_mov_list);
+                assert_eq!(2, mov_list.len());
+                assert_eq!("e2e4", util::format_mov(mov_list[0]));
+                assert_eq!("d2d4", util::format_mov(mov_list[1]));
+
+                match *go_cmd {
+                    UciCommand::StartSearch(go_limits) => assert_eq!(Some(6), go_limits.depth),
+                    _ => panic!("go depth not parsed"),
+                }
+            },
+            _ => panic!("go searchmoves not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_process_go_ponder() {
+        match process_uci_cmd("go ponder wtime 60000 btime 50000 winc 1000 binc 1000") {
+            UciCommand::StartPonder(go_cmd) => {
+                match *go_cmd {
+                    UciCommand::StartSearch(GoLimits { time_info: Some((w_time_info, b_time_info)), .. }) => {
+                        assert_eq!(60000, w_time_info.all_time_millis);
+                        assert_eq!(50000, b_time_info.all_time_millis);
+                        assert_eq!(1000, b_time_info.increment_millis);
+                    },
+                    _ => panic!("go wtime not parsed"),
+                }
+            },
+            _ => panic!("go ponder not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_process_control_cmds() {
+        assert!(matches!(process_uci_cmd("isready"), UciCommand::IsReady));
+        assert!(matches!(process_uci_cmd("stop"), UciCommand::Stop));
+        assert!(matches!(process_uci_cmd("ponderhit"), UciCommand::PonderHit));
+        assert!(matches!(process_uci_cmd("quit"), UciCommand::Quit));
+    }
+
+    #[test]
+    fn test_process_perft_cmds() {
+        assert!(matches!(process_uci_cmd("perft 5"), UciCommand::Perft(5)));
+        assert!(matches!(process_uci_cmd("perft divide 3"), UciCommand::PerftDivide(3)));
+        assert!(matches!(process_uci_cmd("perft hash 6"), UciCommand::PerftHashed(6)));
+
+        match process_uci_cmd("perftsuite suites/perft suite.epd 4") {
+            UciCommand::PerftSuite(path, max_depth) => {
+                assert_eq!("suites/perft suite.epd", path);
+                assert_eq!(4, max_depth);
+            },
+            _ => panic!("perftsuite not parsed"),
+        }
+
+        assert!(matches!(process_uci_cmd("perftsuite 4"), UciCommand::PerftSuite(path, u8::MAX) if path == "4"));
+        assert_eq!(Some(UciError::MissingValue("perft divide".to_owned())), parse_uci_cmd("perft divide").err());
+        assert_eq!(Some(UciError::MissingValue("perftsuite".to_owned())), parse_uci_cmd("perftsuite").err());
+    }
+
+    #[test]
+    fn test_process_go_time_control_any_order() {
+        match process_uci_cmd("go binc 10 btime 1000 movestogo 20 wtime 2000") {
+            UciCommand::StartSearch(GoLimits { time_info: Some((w_time_info, b_time_info)), .. }) => {
+                assert_eq!(2000, w_time_info.all_time_millis);
+                assert_eq!(0, w_time_info.increment_millis);
+                assert_eq!(1000, b_time_info.all_time_millis);
+                assert_eq!(10, b_time_info.increment_millis);
+                assert_eq!(20, b_time_info.moves_to_go);
+            },
+            _ => panic!("go wtime not parsed"),
+        }
+
+        match process_uci_cmd("go wtime -50 btime 1000 binc 10") {
+            UciCommand::StartSearch(GoLimits { time_info: Some((w_time_info, b_time_info)), .. }) => {
+                assert_eq!(0, w_time_info.all_time_millis);
+                assert_eq!(DEFAULT_MOVS_TO_GO, b_time_info.moves_to_go);
+            },
+            _ => panic!("go wtime not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid_cmds() {
+        assert_eq!(Some(UciError::MissingValue("depth".to_owned())), parse_uci_cmd("go depth").err());
+        assert_eq!(Some(UciError::InvalidValue("movetime".to_owned(), "abc".to_owned())), parse_uci_cmd("go movetime abc").err());
+        assert_eq!(Some(UciError::UnknownParam("foo".to_owned())), parse_uci_cmd("go foo 10").err());
+        assert_eq!(Some(UciError::InvalidMov("e2".to_owned())), parse_uci_cmd("position startpos moves e2e4 e2").err());
+        assert_eq!(Some(UciError::MissingValue("perft".to_owned())), parse_uci_cmd("perft").err());
+        assert_eq!(Some(UciError::UnknownCommand("foo".to_owned())), parse_uci_cmd("foo").err());
+        assert_eq!(Some(UciError::MissingValue("fen".to_owned())), parse_uci_cmd("position fen moves e2e4").err());
+        assert!(parse_uci_cmd("setoption name Hash value lots").is_err());
+        assert!(matches!(process_uci_cmd("go wtime"), UciCommand::Noop));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_process_position_fen_with_movs() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        match process_uci_cmd("position fen rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - moves e2e4 e7e5 e1e2 e8e7") {
+            UciCommand::Position(fen_str, mov_list) => {
+                assert_eq!(4, mov_list.len());
+
+                let mut state = State::new(&fen_str, &zob_keys, &bitmask);
+                assert_eq!(FEN_START_POS, state.to_fen());
+
+                for raw_mov in mov_list {
+                    assert!(do_raw_mov(&mut state, raw_mov).is_ok());
+                }
+
+                assert_eq!(Err(UciError::IllegalMov("e2e4".to_owned())), do_raw_mov(&mut state, parse_mov_str("e2e4").unwrap()));
+            },
+            _ => panic!("position fen not parsed"),
+        }
+    }
+
+    #[test]
+    fn test_chess960_mov_notation() {
+        let zob_keys = XorshiftPrng::new().create_prn_table(def::BOARD_SIZE, def::PIECE_CODE_RANGE);
+        let bitmask = BitMask::new();
+
+        let mut state = State::new("rk5r/8/8/8/8/8/8/RK5R w HAha - 0 1", &zob_keys, &bitmask);
+        let raw_mov_list = vec![parse_mov_str("b1a1").unwrap(), parse_mov_str("b1c1").unwrap(), parse_mov_str("b1h1").unwrap()];
+
+        let mov_list = match_raw_mov_list(&mut state, &raw_mov_list);
+        assert_eq!(vec!["b1c1", "b1c1", "b1g1"], mov_list.iter().map(|mov| util::format_mov(*mov)).collect::<Vec<String>>());
+        assert_eq!("b1c1 b1g1 ", format_pv(&state, &[mov_list[0], mov_list[2], 0]));
+
+        state.is_chess960 = true;
+
+        let mov_list = match_raw_mov_list(&mut state, &raw_mov_list);
+        assert_eq!(vec!["b1a1", "b1c1", "b1h1"], mov_list.iter().map(|mov| format_mov(&state, *mov)).collect::<Vec<String>>());
+
+        assert!(do_raw_mov(&mut state, parse_mov_str("b1h1").unwrap()).is_ok());
+        assert_eq!("rk5r/8/8/8/8/8/8/R4RK1 b kq - 0 1", state.to_fen());
+    }
+}
@@ -27,6 +27,27 @@ pub fn map_piece_char_to_code(piece_char: char) -> u8 {
     }
 }
 
+#[inline]
+pub fn map_piece_code_to_fen_char(piece_code: u8) -> char {
+    match piece_code {
+        def::WK => 'K',
+        def::WQ => 'Q',
+        def::WR => 'R',
+        def::WB => 'B',
+        def::WN => 'N',
+        def::WP => 'P',
+
+        def::BK => 'k',
+        def::BQ => 'q',
+        def::BR => 'r',
+        def::BB => 'b',
+        def::BN => 'n',
+        def::BP => 'p',
+
+        _ => panic!("invalid piece code {}", piece_code),
+    }
+}
+
 #[inline]
 pub fn map_piece_code_to_char(piece_code: u8) -> char {
     match piece_code {
@@ -130,22 +151,7 @@ pub fn format_mov(mov: u32) -> String {
     }
 }
 
-#[inline]
-pub fn format_pv(pv_table: &[u32]) -> String {
-    let mut pv_line = String::new();
-
-    for mov in pv_table {
-        if *mov == 0 {
-            break
-        }
-
-        pv_line.push_str(&&*format!("{} ", format_mov(*mov)));
-    }
-
-    pv_line
-}
-
-#[allow(dead_code)]
+#[allow(dead_code, clippy::print_with_newline)]
 #[inline]
 pub fn print_bitboard(bitboard: u64) {
     let mut index = 56;